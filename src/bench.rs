@@ -0,0 +1,60 @@
+//! `gbrs bench`: how long a single memory access takes. Reads the whole address space in order
+//! and in a random order (with the boot ROM mapped and without) and writes to random RAM
+//! addresses.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use crate::game_boy::memory::{MemResult, MMU};
+use crate::game_boy::memory::addresses as adr;
+
+/// How often every address is accessed
+const ROUNDS: u32 = 500;
+
+fn report(name: &str, accesses: u64, elapsed: Duration) {
+    println!(
+        "{:<32} {:>12} accesses {:>8.2} ns/access",
+        name,
+        accesses,
+        elapsed.as_nanos() as f64 / accesses as f64
+    );
+}
+
+fn bench_reads(name: &str, mmu: &MMU, addresses: &[u16]) {
+    let start = Instant::now();
+    let mut sum = 0u32;
+    for _ in 0..ROUNDS {
+        for address in addresses {
+            sum = sum.wrapping_add(mmu.read_8(black_box(*address)) as u32);
+        }
+    }
+    black_box(sum);
+    report(name, ROUNDS as u64 * addresses.len() as u64, start.elapsed());
+}
+
+fn bench_writes(name: &str, mmu: &mut MMU, addresses: &[u16]) {
+    let start = Instant::now();
+    for round in 0..ROUNDS {
+        for address in addresses {
+            mmu.write_8(black_box(*address), round as u8);
+        }
+    }
+    report(name, ROUNDS as u64 * addresses.len() as u64, start.elapsed());
+}
+
+pub fn run(rom_path: &str) -> MemResult<()> {
+    let mut mmu = MMU::load_from_path(&rom_path.into())?;
+    let mut rng = rand::rng();
+    let in_order: Vec<u16> = (0..=u16::MAX).collect();
+    let random: Vec<u16> = (0..0x10000).map(|_| rng.random()).collect();
+    // VRAM to OAM, the IO registers would have side effects
+    let random_ram: Vec<u16> = (0..0x10000).map(|_| rng.random_range(0x8000..0xFF00)).collect();
+
+    bench_reads("read_8 in order (boot ROM)", &mmu, &in_order);
+    bench_reads("read_8 random (boot ROM)", &mmu, &random);
+    mmu.write_8(adr::memory::BOOT_ROM_ENABLED, 0x01);
+    bench_reads("read_8 in order", &mmu, &in_order);
+    bench_reads("read_8 random", &mmu, &random);
+    bench_writes("write_8 random RAM", &mut mmu, &random_ram);
+    Ok(())
+}
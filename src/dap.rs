@@ -0,0 +1,697 @@
+//! `gbrs dap`: a Debug Adapter Protocol server, so editors (VS Code, ...) can debug a ROM.
+//!
+//! The editor connects to a local socket and sends `launch` with the ROM (`program`), and
+//! optionally a sym file (`symbols`, the ROM's `.sym` by default), a line map (`lines`, see
+//! [`crate::symbols`]), `stopOnEntry` and `headless`. Source breakpoints are mapped through
+//! the line map, or to the label on the line through the sym file. The Game Boy is the only
+//! thread, its registers and the I/O registers are the variables. Interrupt dispatches can be
+//! broken on with the exception filters (`interrupt` for any). The session is recorded, so
+//! `stepBack` and `reverseContinue` work, see [`crate::game_boy::timeline`].
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use serde_json::{json, Value};
+use crate::debugger::{self, Pause, Reverse, Run};
+use crate::game_boy::cpu::callstack::Entry;
+use crate::game_boy::breakpoints::{Breakpoint, BreakpointId, Hit};
+use crate::game_boy::expression::Expression;
+use crate::game_boy::interrupt::Interrupt;
+use crate::game_boy::memory::addresses as adr;
+use crate::game_boy::GameBoy;
+use crate::symbols::{self, LineMap, Symbols};
+use crate::window::GbWindow;
+
+const THREAD_ID: u64 = 1;
+
+/// `variablesReference`s of the scopes
+const REGISTERS: u64 = 1;
+const IO_REGISTERS: u64 = 2;
+
+/// Larger `Content-Length`s are refused rather than allocated, requests are a few KiB at most
+const MAX_MESSAGE_LENGTH: usize = 1 << 20;
+
+/// The condition of a requested breakpoint, an error is reported for that breakpoint only
+type Condition = Result<Option<Expression>, String>;
+
+/// A requested source breakpoint, with the code addresses of its line
+struct LineBreakpoint {
+    line: u64,
+    locations: Vec<(u16, u16)>,
+    condition: Condition,
+}
+
+/// One message, `None` once the connection is closed
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.unwrap_or(0);
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Message of {length} bytes is too long")));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+struct Client {
+    stream: TcpStream,
+    seq: u64,
+}
+
+impl Client {
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        // A closed connection ends the session, when the reader notices
+        let _ = write!(self.stream, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true, "text": text }),
+        );
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Until `configurationDone`
+    Configuring,
+    Paused,
+    Running(Run),
+    Reversing(Reverse),
+    Quit,
+}
+
+struct Session {
+    client: Client,
+    gb: Option<GameBoy>,
+    window: Option<GbWindow>,
+    magnification: usize,
+    symbols: Symbols,
+    lines: LineMap,
+    state: State,
+    stop_on_entry: bool,
+    /// Reported once the request, that paused, got its response
+    stopped: Option<&'static str>,
+    /// The breakpoints, that each `setBreakpoints` replaces
+    source_breakpoints: HashMap<PathBuf, Vec<BreakpointId>>,
+    function_breakpoints: Vec<BreakpointId>,
+    exception_breakpoints: Vec<BreakpointId>,
+}
+
+/// Wait for an editor on the port and debug, until it disconnects or the window is closed
+pub fn run(port: u16, magnification: usize) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for a debugger on 127.0.0.1:{}", port);
+    let (stream, address) = listener.accept()?;
+    println!("Debugger connected from {}", address);
+
+    let (sender, requests) = mpsc::channel();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    thread::spawn(move || {
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        client: Client { stream, seq: 0 },
+        gb: None,
+        window: None,
+        magnification,
+        symbols: Symbols::default(),
+        lines: LineMap::default(),
+        state: State::Configuring,
+        stop_on_entry: false,
+        stopped: None,
+        source_breakpoints: HashMap::new(),
+        function_breakpoints: Vec::new(),
+        exception_breakpoints: Vec::new(),
+    };
+    let mut headless_buffer = vec![0; GbWindow::buffer_size()].into_boxed_slice();
+    while session.state != State::Quit {
+        if session.window.as_ref().is_some_and(|window| !window.is_open()) {
+            session.client.event("terminated", json!({}));
+            break;
+        }
+        // Without a window there is nothing to do, until the next request
+        let running = matches!(session.state, State::Running(_));
+        let request = if !running && session.window.is_none() {
+            requests.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            requests.try_recv()
+        };
+        match request {
+            Ok(request) => session.handle(&request),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => break,
+        }
+        if let State::Running(run) = session.state {
+            session.advance(run, &mut headless_buffer);
+        }
+        if let State::Reversing(reverse) = session.state {
+            session.go_back(reverse, &mut headless_buffer);
+        }
+        if let Some(window) = &mut session.window {
+            window.display();
+        }
+    }
+    Ok(())
+}
+
+impl Session {
+    fn handle(&mut self, request: &Value) {
+        if request["type"] != "request" {
+            return;
+        }
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsHitConditionalBreakpoints": true,
+                "supportsSetVariable": true,
+                "supportsReadMemoryRequest": true,
+                "supportsEvaluateForHovers": true,
+                "supportsStepBack": true,
+                "supportsExceptionFilterOptions": true,
+                "exceptionBreakpointFilters": exception_filters(),
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(arguments),
+            "setExceptionBreakpoints" => self.set_exception_breakpoints(arguments),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "Game Boy" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "I/O", "variablesReference": IO_REGISTERS, "expensive": false },
+            ] })),
+            "variables" => self.variables(arguments),
+            "setVariable" => self.set_variable(arguments),
+            "readMemory" => self.read_memory(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "continue" => self.resume(|_| Run::Continue).map(|_| json!({ "allThreadsContinued": true })),
+            "next" => self.resume(Run::next),
+            "stepIn" => self.resume(|gb| Run::step(gb, 1)),
+            "stepOut" => self.resume(Run::finish),
+            "stepBack" => self.reverse(Reverse::Step(1)),
+            "reverseContinue" => self.reverse(Reverse::Continue),
+            "pause" => {
+                if let State::Running(_) = self.state {
+                    self.state = State::Paused;
+                    self.stopped = Some("pause");
+                }
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.state = State::Quit;
+                Ok(json!({}))
+            }
+            _ => Err(format!("`{}` isn't supported", command)),
+        };
+        match result {
+            Ok(body) => self.client.respond(request, body),
+            Err(message) => self.client.fail(request, &message),
+        }
+        if command == "launch" && self.gb.is_some() {
+            self.client.event("initialized", json!({}));
+        }
+        if let Some(reason) = self.stopped.take() {
+            self.client.stopped(reason, None);
+        }
+    }
+
+    fn gb(&mut self) -> Result<&mut GameBoy, String> {
+        self.gb.as_mut().ok_or_else(|| "No ROM was launched".to_owned())
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let program = arguments["program"].as_str().ok_or("`program` (the ROM) is missing")?;
+        let program = PathBuf::from(program);
+        let gb = GameBoy::load(&program).map_err(|e| format!("Could not load {}: {:?}", program.display(), e))?;
+        self.symbols = match arguments["symbols"].as_str() {
+            Some(path) => Symbols::load(Path::new(path)).map_err(|e| format!("Could not load {}: {}", path, e))?,
+            None => Symbols::for_rom(&program).map_err(|e| format!("Could not load the symbols: {}", e))?,
+        };
+        if let Some(path) = arguments["lines"].as_str() {
+            self.lines = LineMap::load(Path::new(path)).map_err(|e| format!("Could not load {}: {}", path, e))?;
+        }
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        if !arguments["headless"].as_bool().unwrap_or(false) {
+            self.window = Some(GbWindow::new(self.magnification));
+        }
+        self.gb = Some(gb);
+        self.gb()?.start_recording();
+        Ok(json!({}))
+    }
+
+    /// The `(bank, address)`es of the code on the line
+    fn addresses_of_line(&self, path: &Path, line: usize) -> Vec<(u16, u16)> {
+        let addresses = self.lines.addresses_of(path, line);
+        if !addresses.is_empty() {
+            return addresses;
+        }
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|source| symbols::label_on_line(&source, line))
+            .and_then(|label| self.symbols.address_of(&label))
+            .into_iter()
+            .collect()
+    }
+
+    /// Names, that aren't variables, are labels from the sym file
+    fn parse_expression(&self, text: &str) -> Result<Expression, String> {
+        let labels = |name: &str| self.symbols.address_of(name).map(|(_, address)| address as i64);
+        Expression::parse_with(text, &labels).map_err(|e| format!("Invalid expression: {}", e))
+    }
+
+    /// The `condition` and the `hitCondition` (`10` breaks from the 10th hit on, `% 10` on
+    /// every 10th, `== 10` only on the 10th) of a breakpoint, as one expression
+    fn condition(&self, breakpoint: &Value) -> Condition {
+        let condition = breakpoint["condition"].as_str().map(str::trim).filter(|c| !c.is_empty());
+        let hits = breakpoint["hitCondition"].as_str().map(str::trim).filter(|c| !c.is_empty()).map(|hits| {
+            if hits.starts_with('%') {
+                format!("hits {} == 0", hits)
+            } else if hits.starts_with(|c: char| "=!<>".contains(c)) {
+                format!("hits {}", hits)
+            } else {
+                format!("hits >= {}", hits)
+            }
+        });
+        let text = match (condition, hits) {
+            (Some(condition), Some(hits)) => format!("({}) && {}", condition, hits),
+            (Some(condition), None) => condition.to_owned(),
+            (None, Some(hits)) => hits,
+            (None, None) => return Ok(None),
+        };
+        self.parse_expression(&text).map(Some)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(arguments["source"]["path"].as_str().ok_or("The source has no path")?);
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        let wanted: Vec<LineBreakpoint> = requested
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0);
+                LineBreakpoint {
+                    line,
+                    locations: self.addresses_of_line(&path, line as usize),
+                    condition: self.condition(breakpoint),
+                }
+            })
+            .collect();
+        let gb = self.gb.as_mut().ok_or("No ROM was launched")?;
+        for id in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            gb.breakpoints().remove(id);
+        }
+        let mut ids = Vec::new();
+        let mut result = Vec::new();
+        for LineBreakpoint { line, locations, condition } in wanted {
+            let condition = match condition {
+                Ok(condition) => condition,
+                Err(message) => {
+                    result.push(json!({ "verified": false, "line": line, "message": message }));
+                    continue;
+                }
+            };
+            if locations.is_empty() {
+                result.push(json!({ "verified": false, "line": line, "message": "No code found for this line" }));
+                continue;
+            }
+            let added: Vec<BreakpointId> = locations
+                .iter()
+                .map(|(bank, address)| add_breakpoint(gb, *bank, *address, condition.clone()))
+                .collect();
+            result.push(json!({ "verified": true, "id": added[0].number(), "line": line }));
+            ids.extend(added);
+        }
+        self.source_breakpoints.insert(path, ids);
+        Ok(json!({ "breakpoints": result }))
+    }
+
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        let wanted: Vec<(Option<(u16, u16)>, Condition)> = requested
+            .iter()
+            .map(|breakpoint| {
+                let name = breakpoint["name"].as_str().unwrap_or("");
+                let location = self.symbols.address_of(name).or_else(|| debugger::number(name).ok().map(|a| (0, a)));
+                (location, self.condition(breakpoint))
+            })
+            .collect();
+        let gb = self.gb.as_mut().ok_or("No ROM was launched")?;
+        for id in self.function_breakpoints.drain(..) {
+            gb.breakpoints().remove(id);
+        }
+        let mut ids = Vec::new();
+        let mut result = Vec::new();
+        for (location, condition) in wanted {
+            match (location, condition) {
+                (Some((bank, address)), Ok(condition)) => {
+                    let id = add_breakpoint(gb, bank, address, condition);
+                    result.push(json!({ "verified": true, "id": id.number() }));
+                    ids.push(id);
+                }
+                (None, _) => result.push(json!({ "verified": false, "message": "Unknown label" })),
+                (_, Err(message)) => result.push(json!({ "verified": false, "message": message })),
+            }
+        }
+        self.function_breakpoints = ids;
+        Ok(json!({ "breakpoints": result }))
+    }
+
+    /// The filters are interrupts, see [`exception_filters`]
+    fn set_exception_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let filters = arguments["filters"].as_array().cloned().unwrap_or_default();
+        let options = arguments["filterOptions"].as_array().cloned().unwrap_or_default();
+        let wanted: Vec<(Option<Option<Interrupt>>, Condition)> = filters
+            .iter()
+            .map(|filter| (filter.as_str(), Ok(None)))
+            .chain(options.iter().map(|option| (option["filterId"].as_str(), self.condition(option))))
+            .map(|(filter, condition)| (filter.and_then(interrupt_of_filter), condition))
+            .collect();
+        let gb = self.gb.as_mut().ok_or("No ROM was launched")?;
+        for id in self.exception_breakpoints.drain(..) {
+            gb.breakpoints().remove(id);
+        }
+        let mut ids = Vec::new();
+        let mut result = Vec::new();
+        for (interrupt, condition) in wanted {
+            match (interrupt, condition) {
+                (Some(interrupt), Ok(condition)) => {
+                    let id = gb.breakpoints().add(Breakpoint::interrupt(interrupt));
+                    gb.breakpoints().set_condition(id, condition);
+                    result.push(json!({ "verified": true, "id": id.number() }));
+                    ids.push(id);
+                }
+                (None, _) => result.push(json!({ "verified": false, "message": "Unknown filter" })),
+                (_, Err(message)) => result.push(json!({ "verified": false, "message": message })),
+            }
+        }
+        self.exception_breakpoints = ids;
+        Ok(json!({ "breakpoints": result }))
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        self.gb()?;
+        if self.stop_on_entry {
+            self.state = State::Paused;
+            self.stopped = Some("entry");
+        } else {
+            self.state = State::Running(Run::Continue);
+        }
+        Ok(json!({}))
+    }
+
+    fn resume(&mut self, run: impl FnOnce(&GameBoy) -> Run) -> Result<Value, String> {
+        let run = run(self.gb()?);
+        self.state = State::Running(run);
+        Ok(json!({}))
+    }
+
+    fn reverse(&mut self, reverse: Reverse) -> Result<Value, String> {
+        self.gb()?;
+        self.state = State::Reversing(reverse);
+        Ok(json!({}))
+    }
+
+    fn go_back(&mut self, reverse: Reverse, headless_buffer: &mut Box<[u8]>) {
+        let Some(gb) = self.gb.as_mut() else { return };
+        let buffer = match &mut self.window {
+            Some(window) => window.buffer_mut(),
+            None => headless_buffer,
+        };
+        self.state = State::Paused;
+        let stopped = match reverse {
+            Reverse::Step(_) => gb.step_back(buffer).map(|went_back| match went_back {
+                true => ("step", None),
+                false => ("step", Some("The recording starts here".to_owned())),
+            }),
+            Reverse::Continue => gb.reverse_continue(buffer).map(|hit| match hit {
+                Some(hit) => (hit_reason(hit.hit()), Some(hit.to_string())),
+                None => ("step", Some("No breakpoint was hit before, back at the start of the recording".to_owned())),
+            }),
+        };
+        match stopped {
+            Ok((reason, text)) => self.client.stopped(reason, text),
+            Err(e) => self.client.stopped("exception", Some(format!("Emulation fault: {}", e))),
+        }
+    }
+
+    fn advance(&mut self, mut run: Run, headless_buffer: &mut Box<[u8]>) {
+        let Some(gb) = self.gb.as_mut() else { return };
+        if let Some(window) = &self.window {
+            crate::update_buttons(gb, window);
+        }
+        let buffer = match &mut self.window {
+            Some(window) => window.buffer_mut(),
+            None => headless_buffer,
+        };
+        let pause = match run.advance(gb, buffer) {
+            Ok(None) => {
+                self.state = State::Running(run);
+                return;
+            }
+            Ok(Some(pause)) => pause,
+            Err(e) => {
+                self.state = State::Paused;
+                self.client.stopped("exception", Some(format!("Emulation fault: {}", e)));
+                return;
+            }
+        };
+        self.state = State::Paused;
+        match pause {
+            Pause::Done => self.client.stopped("step", None),
+            Pause::Breakpoint(hit) => self.client.stopped(hit_reason(hit.hit()), Some(hit.to_string())),
+            Pause::LockUp(lock_up) => self.client.stopped("exception", Some(format!("CPU locked up: {}", lock_up))),
+        }
+    }
+
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let gb = self.gb.as_ref().ok_or("No ROM was launched")?;
+        let frames: Vec<Value> = gb
+            .backtrace()
+            .iter()
+            .enumerate()
+            .map(|(id, backtrace_frame)| {
+                let address = backtrace_frame.location().address();
+                let bank = backtrace_frame.location().bank().unwrap_or(0);
+                let mut name = self.symbols.describe(bank, address);
+                match backtrace_frame.entry() {
+                    Some(Entry::Call) | None => {}
+                    Some(entry) => name += &format!(" [{}]", entry),
+                }
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:04X}", address),
+                });
+                if let Some((path, line)) = self.lines.line_of(bank, address) {
+                    frame["source"] = json!({ "path": path });
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&mut self, arguments: &Value) -> Result<Value, String> {
+        let gb = self.gb.as_ref().ok_or("No ROM was launched")?;
+        let variables = match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS) => {
+                let r = gb.registers();
+                let flag = |set: bool, name: char| if set { name } else { '-' };
+                let flags: String = [
+                    flag(r.zero_flag(), 'Z'),
+                    flag(r.negative_flag(), 'N'),
+                    flag(r.half_carry_flag(), 'H'),
+                    flag(r.carry_flag(), 'C'),
+                ]
+                .iter()
+                .collect();
+                let mut variables: Vec<Value> = [("A", r.a()), ("F", r.f()), ("B", r.b()), ("C", r.c()), ("D", r.d()), ("E", r.e()), ("H", r.h()), ("L", r.l())]
+                    .iter()
+                    .map(|(name, value)| variable(name, format!("${:02X}", value), None))
+                    .collect();
+                variables.extend(
+                    [("AF", r.af()), ("BC", r.bc()), ("DE", r.de()), ("HL", r.hl()), ("SP", r.sp()), ("PC", r.pc())]
+                        .iter()
+                        .map(|(name, value)| variable(name, format!("${:04X}", value), Some(*value))),
+                );
+                variables.push(variable("Flags", flags, None));
+                variables.push(variable("IME", (r.ime() as u8).to_string(), None));
+                variables.push(variable("ROM bank", gb.memory().rom().bank_of(0x4000).to_string(), None));
+                variables
+            }
+            Some(IO_REGISTERS) => adr::IO_REGISTER_NAMES
+                .iter()
+                .map(|(address, name)| variable(name, format!("${:02X}", gb.memory().read_8(*address)), Some(*address)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn set_variable(&mut self, arguments: &Value) -> Result<Value, String> {
+        let name = arguments["name"].as_str().unwrap_or("");
+        let value = arguments["value"].as_str().unwrap_or("");
+        let gb = self.gb()?;
+        match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS) => {
+                let mut registers = gb.registers();
+                debugger::set_register(&mut registers, name, value)?;
+                gb.set_registers(registers);
+            }
+            Some(IO_REGISTERS) => {
+                let (address, _) = adr::IO_REGISTER_NAMES
+                    .iter()
+                    .find(|(_, n)| *n == name)
+                    .ok_or_else(|| format!("Unknown register `{}`", name))?;
+                gb.poke(*address, &[debugger::number(value)?]);
+            }
+            _ => return Err("Only registers can be changed".to_owned()),
+        }
+        Ok(json!({ "value": value }))
+    }
+
+    fn read_memory(&mut self, arguments: &Value) -> Result<Value, String> {
+        let gb = self.gb.as_ref().ok_or("No ROM was launched")?;
+        let reference = arguments["memoryReference"].as_str().ok_or("No memory reference")?;
+        let start = debugger::number::<u16>(reference)? as i64 + arguments["offset"].as_i64().unwrap_or(0);
+        let count = arguments["count"].as_i64().unwrap_or(0);
+        if !(0..0x10000).contains(&start) {
+            return Ok(json!({ "address": format!("0x{:X}", start), "unreadableBytes": count }));
+        }
+        let end = (start + count).min(0x10000);
+        let bytes: Vec<u8> = (start..end).map(|address| gb.memory().read_8(address as u16)).collect();
+        Ok(json!({
+            "address": format!("0x{:04X}", start),
+            "data": base64(&bytes),
+            "unreadableBytes": count - bytes.len() as i64,
+        }))
+    }
+
+    /// Watch expressions and hovers, see [`Expression`]
+    fn evaluate(&mut self, arguments: &Value) -> Result<Value, String> {
+        let expression = self.parse_expression(arguments["expression"].as_str().unwrap_or(""))?;
+        let gb = self.gb.as_ref().ok_or("No ROM was launched")?;
+        let value = gb.evaluate(&expression);
+        let mut result = json!({ "result": debugger::format_value(value), "variablesReference": 0 });
+        if (0..=0xFFFF).contains(&value) {
+            result["memoryReference"] = json!(format!("0x{:04X}", value));
+        }
+        Ok(result)
+    }
+}
+
+fn variable(name: &str, value: String, memory: Option<u16>) -> Value {
+    let mut variable = json!({ "name": name, "value": value, "variablesReference": 0 });
+    if let Some(address) = memory {
+        variable["memoryReference"] = json!(format!("0x{:04X}", address));
+    }
+    variable
+}
+
+/// The reason of the stopped event for a breakpoint hit
+fn hit_reason(hit: Hit) -> &'static str {
+    match hit {
+        Hit::Execute => "breakpoint",
+        Hit::Access { .. } => "data breakpoint",
+        Hit::Interrupt(_) => "exception",
+    }
+}
+
+/// `interrupt` breaks on any interrupt dispatch, the others on one interrupt
+fn exception_filters() -> Value {
+    let any = json!({ "filter": "interrupt", "label": "Interrupts", "supportsCondition": true });
+    let each = Interrupt::NAMES.iter().map(|(name, _)| {
+        json!({ "filter": name, "label": format!("{} interrupt", name), "supportsCondition": true })
+    });
+    Value::Array(std::iter::once(any).chain(each).collect())
+}
+
+/// `Some(None)` for any interrupt, `None` for unknown filters
+fn interrupt_of_filter(filter: &str) -> Option<Option<Interrupt>> {
+    match filter {
+        "interrupt" => Some(None),
+        name => Interrupt::from_name(name).map(Some),
+    }
+}
+
+/// Only ROM addresses are banked
+fn add_breakpoint(gb: &mut GameBoy, bank: u16, address: u16, condition: Option<Expression>) -> BreakpointId {
+    let breakpoint = if address < 0x8000 { Breakpoint::execute_in(bank, address) } else { Breakpoint::execute(address) };
+    let id = gb.breakpoints().add(breakpoint);
+    gb.breakpoints().set_condition(id, condition);
+    id
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = u32::from_be_bytes([0, chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,780 @@
+//! `gbrs --debug`: a command line debugger, that runs next to the window. Commands are read
+//! from stdin on their own thread, so the window stays open (and the emulation keeps running
+//! on `continue`) while waiting for input. An empty line repeats the last command, input while
+//! the emulation runs pauses it first.
+//!
+//! Addresses and values are hex, the `$` or `0x` prefix is optional. Counts are decimal.
+//! Conditions and `print` take [expressions](crate::game_boy::expression), where numbers are
+//! decimal like in RGBDS. Labels of the ROM's sym file can be used for addresses, also in
+//! expressions. The debugger always runs M-cycle by M-cycle, so steps end right
+//! between two instructions.
+
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use crate::game_boy::breakpoints::{Access, Breakpoint, BreakpointHit, ValueCondition};
+use crate::game_boy::cpu::callstack::Entry;
+use crate::game_boy::cpu::disassembler::{disassemble, Location};
+use crate::game_boy::cpu::history::HISTORY_LENGTH;
+use crate::game_boy::cpu::registers::Registers;
+use crate::game_boy::cpu::LockUp;
+use crate::game_boy::expression::Expression;
+use crate::game_boy::interrupt::Interrupt;
+use crate::game_boy::{ClockInformation, GBRSError, GameBoy};
+use crate::symbols::Symbols;
+use crate::window::GbWindow;
+
+const HELP: &str = "\
+break [[BANK:]ADDR|LABEL] [if EXPR]
+                             break before the instruction at ADDR (list the breakpoints)
+break interrupt [vblank|stat|timer|serial|joypad] [if EXPR]
+                             break when the interrupt (any) is dispatched
+delete N                     remove breakpoint N
+watch [read|write|rw] ADDR[-ADDR]|REGISTER [==|!=|<|> VALUE|& MASK == VALUE] [if EXPR]
+                             break on data accesses (writes by default), e.g. `watch rLCDC`
+cond N [EXPR]                only break if EXPR holds, e.g. `a == $3F && hits > 10`
+print EXPR                   evaluate an expression, e.g. `[hl]`
+display [EXPR]               evaluate EXPR whenever the emulation pauses (list them)
+undisplay N                  stop displaying expression N
+step [N]                     run N instructions (1), into calls
+next                         run one instruction, a CALL or RST returns first
+finish                       run until the current function returns
+continue                     run until a breakpoint is hit
+reverse-step [N]             go back N instructions (1), the last seconds are recorded
+reverse-continue             go back to the last time a breakpoint was hit
+frame [N]                    run until N frames (1) are done
+regs                         show the registers
+backtrace                    show the calls (and interrupts), that led here
+history [N]                  show the last N instructions (all that are kept), oldest first
+mem ADDR [LENGTH]            show memory, 64 bytes by default (ADDR can be a label, too)
+disasm [ADDR] [COUNT]        disassemble COUNT instructions (10) from ADDR (PC)
+set REGISTER VALUE           change a register (a ... l, af ... hl, sp, pc, ime)
+set [ADDR] VALUE             change a byte of memory, without side effects
+asm ADDR INSTRUCTION         assemble into memory (or the ROM image), e.g. `asm c000 ld a, $42`
+quit";
+
+/// Bytes per line of `mem`
+const MEM_LINE_LENGTH: u16 = 16;
+
+/// What the emulation does, until it pauses again. Shared with the DAP server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Run {
+    /// Until a breakpoint is hit
+    Continue,
+    /// `count` more instructions, the current one has `started`
+    Step { count: u32, started: bool },
+    /// Until the instruction at `address` is next and SP is back at `sp` (the call returned)
+    Over { address: u16, sp: u16 },
+    /// Until SP rises above `sp`, when the function returns
+    Finish { sp: u16 },
+    /// Until `count` more frames are done
+    Frames { count: u64 },
+}
+
+impl Run {
+    /// `count` instructions, into calls
+    pub fn step(gb: &GameBoy, count: u32) -> Run {
+        // After a watchpoint the instruction is still running, it counts as the first step
+        Run::Step { count: count.max(1), started: gb.cpu().cycles_left() > 0 }
+    }
+
+    /// One instruction, a CALL or RST returns first
+    pub fn next(gb: &GameBoy) -> Run {
+        match gb.cpu().current_instruction() {
+            Some(instruction) if matches!(instruction.opcode().mnemonic(), "CALL" | "RST") && gb.cpu().cycles_left() == 0 => {
+                Run::Over { address: instruction.next_address(), sp: gb.registers().sp() }
+            }
+            _ => Run::step(gb, 1),
+        }
+    }
+
+    /// Until the current function returns
+    pub fn finish(gb: &GameBoy) -> Run {
+        Run::Finish { sp: gb.registers().sp() }
+    }
+
+    /// Is it done after this clock?
+    fn done(&mut self, info: &ClockInformation, gb: &GameBoy) -> bool {
+        let between_instructions = gb.cpu().cycles_left() == 0;
+        match self {
+            Run::Step { count, started } => {
+                *started |= info.instruction().is_new();
+                if *started && between_instructions {
+                    *count -= 1;
+                    *started = false;
+                }
+                *count == 0
+            }
+            Run::Over { address, sp } => {
+                let registers = gb.registers();
+                between_instructions && registers.pc() == *address && registers.sp() >= *sp
+            }
+            Run::Finish { sp } => between_instructions && gb.registers().sp() > *sp,
+            Run::Frames { count } => {
+                if info.frame_done() {
+                    *count -= 1;
+                }
+                *count == 0
+            }
+            Run::Continue => false,
+        }
+    }
+
+    /// Clock until it is done, a breakpoint is hit or the frame is done (`None`, it isn't
+    /// paused yet)
+    pub fn advance(&mut self, gb: &mut GameBoy, buffer: &mut Box<[u8]>) -> Result<Option<Pause>, GBRSError> {
+        loop {
+            let info = gb.clock(buffer)?;
+            if let Some(lock_up) = info.lock_up() {
+                return Ok(Some(Pause::LockUp(lock_up)));
+            }
+            if let Some(hit) = info.breakpoint() {
+                return Ok(Some(Pause::Breakpoint(hit)));
+            }
+            if self.done(&info, gb) {
+                return Ok(Some(Pause::Done));
+            }
+            if info.frame_done() {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Why the emulation paused
+#[derive(Copy, Clone, Debug)]
+pub enum Pause {
+    Done,
+    Breakpoint(BreakpointHit),
+    LockUp(LockUp),
+}
+
+/// Going back in time, see [`crate::game_boy::timeline`]. Shared with the DAP server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reverse {
+    Step(u32),
+    Continue,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Paused,
+    Running(Run),
+    Reversing(Reverse),
+    Quit,
+}
+
+pub struct Debugger {
+    commands: Receiver<String>,
+    state: State,
+    last_command: String,
+    /// Shown whenever the emulation pauses
+    displays: Vec<Expression>,
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new(symbols: Symbols) -> Debugger {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Debugger {
+            commands,
+            state: State::Paused,
+            last_command: String::new(),
+            displays: Vec::new(),
+            symbols,
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.state == State::Paused
+    }
+
+    /// The next command, if there is one. Stdin was closed, if the state is `Quit` afterwards.
+    fn poll(&mut self, block: bool) -> Option<String> {
+        let line = if block {
+            self.commands.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            self.commands.try_recv()
+        };
+        match line {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.state = State::Quit;
+                None
+            }
+        }
+    }
+
+    fn pause(&mut self, gb: &GameBoy) {
+        self.state = State::Paused;
+        show_location(gb, &self.symbols);
+        self.show_displays(gb);
+        prompt();
+    }
+
+    fn show_displays(&self, gb: &GameBoy) {
+        for (i, expression) in self.displays.iter().enumerate() {
+            println!("{}: {} = {}", i + 1, expression, format_value(gb.evaluate(expression)));
+        }
+    }
+
+    /// Run the command, or print why it couldn't be run
+    fn execute(&mut self, line: &str, gb: &mut GameBoy) {
+        let line = if line.trim().is_empty() { self.last_command.clone() } else { line.trim().to_owned() };
+        if line.is_empty() {
+            prompt();
+            return;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words[0] {
+            "break" | "b" => self.add_breakpoint(&words[1..], gb),
+            "delete" | "d" => self.delete_breakpoint(&words[1..], gb),
+            "watch" | "w" => self.add_watchpoint(&words[1..], gb),
+            "cond" => set_condition(&words[1..], gb, &self.symbols),
+            "print" | "p" => expression(&words[1..], &self.symbols).map(|expression| {
+                println!("{}", format_value(gb.evaluate(&expression)));
+            }),
+            "display" if words.len() == 1 => {
+                self.show_displays(gb);
+                Ok(())
+            }
+            "display" => expression(&words[1..], &self.symbols).map(|expression| {
+                println!("{}: {} = {}", self.displays.len() + 1, expression, format_value(gb.evaluate(&expression)));
+                self.displays.push(expression);
+            }),
+            "undisplay" => match words.get(1).and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if (1..=self.displays.len()).contains(&n) => {
+                    self.displays.remove(n - 1);
+                    Ok(())
+                }
+                _ => Err("Which display?".to_owned()),
+            },
+            "step" | "s" => count(words.get(1), 1).map(|count| {
+                self.state = State::Running(Run::step(gb, count));
+            }),
+            "next" | "n" => {
+                self.state = State::Running(Run::next(gb));
+                Ok(())
+            }
+            "finish" => {
+                self.state = State::Running(Run::finish(gb));
+                Ok(())
+            }
+            "continue" | "c" => {
+                self.state = State::Running(Run::Continue);
+                Ok(())
+            }
+            "reverse-step" | "rs" => count(words.get(1), 1).map(|count: u32| {
+                self.state = State::Reversing(Reverse::Step(count.max(1)));
+            }),
+            "reverse-continue" | "rc" => {
+                self.state = State::Reversing(Reverse::Continue);
+                Ok(())
+            }
+            "frame" | "f" => count(words.get(1), 1).map(|count: u64| {
+                self.state = State::Running(Run::Frames { count: count.max(1) });
+            }),
+            "regs" | "r" => {
+                show_registers(gb);
+                Ok(())
+            }
+            "backtrace" | "bt" => {
+                backtrace(gb, &self.symbols).iter().for_each(|line| println!("{}", line));
+                Ok(())
+            }
+            "history" => show_history(&words[1..], gb, &self.symbols),
+            "mem" | "x" => show_memory(&words[1..], gb, &self.symbols),
+            "disasm" | "l" => show_disassembly(&words[1..], gb, &self.symbols),
+            "set" => set(&words[1..], gb, &self.symbols),
+            "asm" | "a" => assemble(&words[1..], gb, &self.symbols),
+            "help" | "h" | "?" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "quit" | "q" => {
+                self.state = State::Quit;
+                Ok(())
+            }
+            command => Err(format!("Unknown command `{}`, try `help`", command)),
+        };
+        self.last_command = line;
+        if let Err(e) = result {
+            println!("{}", e);
+        }
+        if self.paused() {
+            prompt();
+        }
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str], gb: &mut GameBoy) -> Result<(), String> {
+        let Some(location) = args.first() else {
+            let breakpoints = gb.breakpoints();
+            for (id, breakpoint, enabled) in breakpoints.iter() {
+                let condition = breakpoints.condition(id).map(|c| format!(" if {}", c)).unwrap_or_default();
+                println!(
+                    "{:>3} {}{}{}, {} hits",
+                    id.number(),
+                    describe_breakpoint(breakpoint, &self.symbols),
+                    condition,
+                    if enabled { "" } else { " (disabled)" },
+                    breakpoints.hits(id).unwrap_or(0)
+                );
+            }
+            return Ok(());
+        };
+        let (args, condition) = split_condition(args, &self.symbols)?;
+        if let ["interrupt", name @ ..] = args {
+            let interrupt = match name {
+                [] => None,
+                [name] => Some(Interrupt::from_name(name).ok_or_else(|| format!("Unknown interrupt `{}`", name))?),
+                _ => return Err("Expected `break interrupt [vblank|stat|timer|serial|joypad] [if EXPR]`".to_owned()),
+            };
+            report_added(gb, Breakpoint::interrupt(interrupt), condition, &self.symbols);
+            return Ok(());
+        }
+        if args.len() != 1 {
+            return Err("Expected `break [BANK:]ADDR|LABEL [if EXPR]`".to_owned());
+        }
+        let breakpoint = match (self.symbols.address_of(location), location.split_once(':')) {
+            // Only ROM addresses are banked
+            (Some((bank, address)), _) if address < 0x8000 => Breakpoint::execute_in(bank, address),
+            (Some((_, address)), _) => Breakpoint::execute(address),
+            (None, Some((bank, address))) => Breakpoint::execute_in(number(bank)?, number(address)?),
+            (None, None) => Breakpoint::execute(number(location)?),
+        };
+        report_added(gb, breakpoint, condition, &self.symbols);
+        Ok(())
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str], gb: &mut GameBoy) -> Result<(), String> {
+        let number: u32 = args.first().and_then(|n| n.parse().ok()).ok_or("Which breakpoint?")?;
+        let id = gb.breakpoints().iter().map(|(id, _, _)| id).find(|id| id.number() == number);
+        match id {
+            Some(id) => {
+                gb.breakpoints().remove(id);
+                Ok(())
+            }
+            None => Err(format!("There is no breakpoint {}", number)),
+        }
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str], gb: &mut GameBoy) -> Result<(), String> {
+        let (access, args) = match args.first() {
+            Some(&"read") | Some(&"r") => (Access::Read, &args[1..]),
+            Some(&"write") => (Access::Write, &args[1..]),
+            Some(&"rw") => (Access::ReadWrite, &args[1..]),
+            _ => (Access::Write, args),
+        };
+        let (args, condition) = split_condition(args, &self.symbols)?;
+        let target = args.first().ok_or("What to watch?")?;
+        let breakpoint = match Breakpoint::io_register(target, access) {
+            Some(breakpoint) => breakpoint,
+            None => Breakpoint::watch(range(target, &self.symbols)?, access),
+        };
+        let breakpoint = match args[1..] {
+            [] => breakpoint,
+            ["&", mask, "==", value] => breakpoint.when(ValueCondition::Mask { mask: number(mask)?, value: number(value)? }),
+            [operator, value] => {
+                let value = number(value)?;
+                breakpoint.when(match operator {
+                    "==" => ValueCondition::Equal(value),
+                    "!=" => ValueCondition::NotEqual(value),
+                    "<" => ValueCondition::Less(value),
+                    ">" => ValueCondition::Greater(value),
+                    _ => return Err(format!("Unknown comparison `{}`", operator)),
+                })
+            }
+            _ => return Err("Expected a comparison and a value, or `& MASK == VALUE`".to_owned()),
+        };
+        report_added(gb, breakpoint, condition, &self.symbols);
+        Ok(())
+    }
+}
+
+/// Debug the Game Boy, with the window or headless. Returns, when the window is closed or on
+/// `quit`, with the Game Boy and the symbols back (to save the profile, for example).
+pub fn run(mut gb: GameBoy, mut window: Option<GbWindow>, symbols: Symbols) -> (GameBoy, Symbols) {
+    // Steps have to end between instructions
+    gb.use_block_cache(false);
+    gb.start_recording();
+    let mut debugger = Debugger::new(symbols);
+    let mut headless_buffer = vec![0; GbWindow::buffer_size()].into_boxed_slice();
+    println!("Paused, type `help` for the commands");
+    show_location(&gb, &debugger.symbols);
+    prompt();
+
+    while debugger.state != State::Quit {
+        if window.as_ref().is_some_and(|window| !window.is_open()) {
+            break;
+        }
+        // Without a window there is nothing to do, until the next command
+        let block = debugger.paused() && window.is_none();
+        if let Some(line) = debugger.poll(block) {
+            if !debugger.paused() {
+                // Any input interrupts the running command, an empty line only pauses
+                println!("Interrupted");
+                debugger.pause(&gb);
+                if line.trim().is_empty() {
+                    continue;
+                }
+            }
+            debugger.execute(&line, &mut gb);
+        }
+        if let State::Reversing(reverse) = debugger.state {
+            let buffer = match &mut window {
+                Some(window) => window.buffer_mut(),
+                None => &mut headless_buffer,
+            };
+            match go_back(reverse, &mut gb, buffer) {
+                Ok(message) => message.iter().for_each(|message| println!("{}", message)),
+                Err(e) => println!("Emulation fault: {}", e),
+            }
+            debugger.pause(&gb);
+        }
+        if let State::Running(mut run) = debugger.state {
+            if let Some(window) = &window {
+                crate::update_buttons(&mut gb, window);
+            }
+            let buffer = match &mut window {
+                Some(window) => window.buffer_mut(),
+                None => &mut headless_buffer,
+            };
+            match run.advance(&mut gb, buffer) {
+                Ok(None) => debugger.state = State::Running(run),
+                Ok(Some(pause)) => {
+                    match pause {
+                        Pause::Done => {}
+                        Pause::Breakpoint(hit) => println!("{}", hit),
+                        Pause::LockUp(lock_up) => {
+                            println!("CPU locked up: {} ({})", lock_up, gb.cpu().debug_stack_info());
+                            backtrace(&gb, &debugger.symbols).iter().for_each(|line| println!("{}", line));
+                        }
+                    }
+                    debugger.pause(&gb);
+                }
+                Err(e) => {
+                    println!("Emulation fault: {}", e);
+                    debugger.pause(&gb);
+                }
+            }
+        }
+        if let Some(window) = &mut window {
+            // Also keeps the window responsive while paused, the update rate is limited
+            window.display();
+        }
+    }
+    (gb, debugger.symbols)
+}
+
+/// Go back in time. Returns why it stopped early, or the breakpoint hit.
+fn go_back(reverse: Reverse, gb: &mut GameBoy, buffer: &mut Box<[u8]>) -> Result<Option<String>, GBRSError> {
+    match reverse {
+        Reverse::Step(count) => {
+            for i in 0..count {
+                if !gb.step_back(buffer)? {
+                    return Ok(Some(format!("Went back {} of {} instructions, the recording starts here", i, count)));
+                }
+            }
+            Ok(None)
+        }
+        Reverse::Continue => Ok(Some(match gb.reverse_continue(buffer)? {
+            Some(hit) => hit.to_string(),
+            None => "No breakpoint was hit before, back at the start of the recording".to_owned(),
+        })),
+    }
+}
+
+fn prompt() {
+    print!("(gbrs) ");
+    let _ = io::stdout().flush();
+}
+
+fn report_added(gb: &mut GameBoy, breakpoint: Breakpoint, condition: Option<Expression>, symbols: &Symbols) {
+    let description = describe_breakpoint(&breakpoint, symbols);
+    let id = gb.breakpoints().add(breakpoint);
+    match &condition {
+        Some(condition) => println!("Breakpoint {}: {} if {}", id.number(), description, condition),
+        None => println!("Breakpoint {}: {}", id.number(), description),
+    }
+    gb.breakpoints().set_condition(id, condition);
+}
+
+/// The breakpoint, with the label at its address
+fn describe_breakpoint(breakpoint: &Breakpoint, symbols: &Symbols) -> String {
+    let label = match breakpoint {
+        // Bank 0 is fixed
+        Breakpoint::Execute { bank, address } => {
+            let bank = bank.or((*address < 0x4000).then_some(0));
+            symbols.label_for(Location::new(bank, *address))
+        }
+        Breakpoint::Watch { range, .. } => symbols.label_for(Location::new(None, *range.start())),
+        Breakpoint::Interrupt(_) => None,
+    };
+    match label {
+        Some(label) => format!("{} ({})", breakpoint, label),
+        None => breakpoint.to_string(),
+    }
+}
+
+/// `cond N [EXPR]`, without an expression the condition is removed
+fn set_condition(args: &[&str], gb: &mut GameBoy, symbols: &Symbols) -> Result<(), String> {
+    let number: u32 = args.first().and_then(|n| n.parse().ok()).ok_or("Which breakpoint?")?;
+    let condition = if args.len() > 1 { Some(expression(&args[1..], symbols)?) } else { None };
+    let id = gb.breakpoints().iter().map(|(id, _, _)| id).find(|id| id.number() == number);
+    match id {
+        Some(id) => {
+            gb.breakpoints().set_condition(id, condition);
+            Ok(())
+        }
+        None => Err(format!("There is no breakpoint {}", number)),
+    }
+}
+
+/// The arguments before `if`, and the condition after it
+fn split_condition<'a, 'b>(
+    args: &'a [&'b str],
+    symbols: &Symbols,
+) -> Result<(&'a [&'b str], Option<Expression>), String> {
+    match args.iter().position(|word| *word == "if") {
+        Some(i) => Ok((&args[..i], Some(expression(&args[i + 1..], symbols)?))),
+        None => Ok((args, None)),
+    }
+}
+
+/// Names, that aren't variables, are labels
+fn expression(words: &[&str], symbols: &Symbols) -> Result<Expression, String> {
+    if words.is_empty() {
+        return Err("Expected an expression".to_owned());
+    }
+    let labels = |name: &str| symbols.address_of(name).map(|(_, address)| address as i64);
+    Expression::parse_with(&words.join(" "), &labels).map_err(|e| format!("Invalid expression: {}", e))
+}
+
+/// Hex and decimal
+pub fn format_value(value: i64) -> String {
+    if value < 0 { format!("{}", value) } else { format!("${:X} ({})", value, value) }
+}
+
+/// A line per frame, innermost first, e.g. `#1  00:0162  Func+$2`. Callers, that didn't enter
+/// the frame above with a CALL, say how it was entered: `[RST]` or `[VBlank interrupt]`.
+pub fn backtrace(gb: &GameBoy, symbols: &Symbols) -> Vec<String> {
+    gb.backtrace()
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let location = frame.location();
+            let mut line = format!("#{:<2} {}", i, location);
+            if let Some(label) = symbols.label_for(location) {
+                line += &format!("  {}", label);
+            }
+            match frame.entry() {
+                Some(Entry::Call) | None => {}
+                Some(entry) => line += &format!("  [{}]", entry),
+            }
+            line
+        })
+        .collect()
+}
+
+/// The last `count` instructions, oldest first, with the registers before each of them
+pub fn history(gb: &GameBoy, symbols: &Symbols, count: usize) -> Vec<String> {
+    let history = gb.cpu().history();
+    history
+        .entries()
+        .skip(history.entries().count().saturating_sub(count))
+        .map(|entry| {
+            let instruction = match entry.instruction(gb.memory()) {
+                Some(instruction) => instruction.format_with(&names(gb, symbols)),
+                None => format!("db ${:02X}", entry.opcode()),
+            };
+            let label = symbols.label_for(entry.location()).map(|label| format!("  {}", label)).unwrap_or_default();
+            format!("{:<7}  {:<20} {}{}", entry.location().to_string(), instruction, entry.registers(), label)
+        })
+        .collect()
+}
+
+/// The names of addresses in disassembly, with the banks that are mapped right now
+pub fn names<'a>(gb: &'a GameBoy, symbols: &'a Symbols) -> impl Fn(Location) -> Option<String> + 'a {
+    move |location| symbols.name_of(gb.cpu().location_of(location.address())).map(str::to_owned)
+}
+
+/// The next instruction, with its bank and label
+fn show_location(gb: &GameBoy, symbols: &Symbols) {
+    let location = gb.cpu().location_of(gb.cpu().get_pc());
+    let label = symbols.label_for(location).map(|label| format!(" <{}>", label)).unwrap_or_default();
+    match gb.cpu().current_instruction() {
+        Some(instruction) => println!("{}{}  {}", location, label, instruction.format_with(&names(gb, symbols))),
+        None => println!("{}{}  ?", location, label),
+    }
+}
+
+/// `history [N]`
+fn show_history(args: &[&str], gb: &GameBoy, symbols: &Symbols) -> Result<(), String> {
+    let count = count(args.first(), HISTORY_LENGTH)?;
+    history(gb, symbols, count).iter().for_each(|line| println!("{}", line));
+    Ok(())
+}
+
+fn show_registers(gb: &GameBoy) {
+    let registers = gb.registers();
+    let flag = |set: bool, name: char| if set { name } else { '-' };
+    println!(
+        "{} {}{}{}{} LY={:02X} frame {}",
+        registers,
+        flag(registers.zero_flag(), 'Z'),
+        flag(registers.negative_flag(), 'N'),
+        flag(registers.half_carry_flag(), 'H'),
+        flag(registers.carry_flag(), 'C'),
+        gb.memory().read_ly(),
+        gb.frames()
+    );
+}
+
+fn show_memory(args: &[&str], gb: &GameBoy, symbols: &Symbols) -> Result<(), String> {
+    let start = address(args.first().ok_or("Which address?")?, symbols)?;
+    let length: u16 = count(args.get(1), 64)?;
+    let end = start.saturating_add(length.max(1) - 1);
+    let mut line_start = start;
+    loop {
+        let line_end = line_start.saturating_add(MEM_LINE_LENGTH - 1).min(end);
+        let bytes: Vec<String> = (line_start..=line_end).map(|a| format!("{:02X}", gb.memory().read_8(a))).collect();
+        println!("{:04X}: {}", line_start, bytes.join(" "));
+        if line_end == end {
+            return Ok(());
+        }
+        line_start = line_end + 1;
+    }
+}
+
+fn show_disassembly(args: &[&str], gb: &GameBoy, symbols: &Symbols) -> Result<(), String> {
+    let pc = gb.cpu().get_pc();
+    let mut address = match args.first() {
+        Some(text) => self::address(text, symbols)?,
+        None => pc,
+    };
+    let count: u16 = count(args.get(1), 10)?;
+    let names = names(gb, symbols);
+    for _ in 0..count {
+        let marker = if address == pc { "=>" } else { "  " };
+        if let Some(name) = symbols.name_of(gb.cpu().location_of(address)) {
+            println!("{}:", name);
+        }
+        match disassemble(gb.memory(), address) {
+            Some(instruction) => {
+                println!("{} {}  {}", marker, instruction.location(), instruction.format_with(&names));
+                address = instruction.next_address();
+            }
+            None => {
+                println!("{} {:04X}  db ${:02X}", marker, address, gb.memory().read_8(address));
+                address = address.wrapping_add(1);
+            }
+        }
+        if address == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Assemble the instruction at the address and show, what it became
+fn assemble(args: &[&str], gb: &mut GameBoy, symbols: &Symbols) -> Result<(), String> {
+    let [target, source @ ..] = args else {
+        return Err("Expected `asm ADDR INSTRUCTION`".to_owned());
+    };
+    if source.is_empty() {
+        return Err("Expected `asm ADDR INSTRUCTION`".to_owned());
+    }
+    let start = address(target, symbols)?;
+    let length = gb.patch(start, &source.join(" ")).map_err(|e| e.to_string())?.len() as u16;
+    let mut count = 0;
+    let mut address = start;
+    while address.wrapping_sub(start) < length {
+        address = disassemble(gb.memory(), address).map_or(address.wrapping_add(1), |i| i.next_address());
+        count += 1;
+    }
+    show_disassembly(&[target, &count.to_string()], gb, symbols)
+}
+
+fn set(args: &[&str], gb: &mut GameBoy, symbols: &Symbols) -> Result<(), String> {
+    let [target, value] = args else {
+        return Err("Expected `set REGISTER VALUE` or `set [ADDR] VALUE`".to_owned());
+    };
+    if let Some(text) = target.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        gb.poke(address(text, symbols)?, &[number(value)?]);
+        return Ok(());
+    }
+    let mut registers = gb.registers();
+    set_register(&mut registers, target, value)?;
+    gb.set_registers(registers);
+    show_registers(gb);
+    Ok(())
+}
+
+/// Set a register (`a`, `hl`, `sp`, `ime`, ...) by its name to a hex value
+pub fn set_register(registers: &mut Registers, name: &str, value: &str) -> Result<(), String> {
+    match name.to_ascii_lowercase().as_str() {
+        "a" => registers.set_a(number(value)?),
+        "f" => registers.set_f(number(value)?),
+        "b" => registers.set_b(number(value)?),
+        "c" => registers.set_c(number(value)?),
+        "d" => registers.set_d(number(value)?),
+        "e" => registers.set_e(number(value)?),
+        "h" => registers.set_h(number(value)?),
+        "l" => registers.set_l(number(value)?),
+        "af" => registers.set_af(number(value)?),
+        "bc" => registers.set_bc(number(value)?),
+        "de" => registers.set_de(number(value)?),
+        "hl" => registers.set_hl(number(value)?),
+        "sp" => registers.set_sp(number(value)?),
+        "pc" => registers.set_pc(number(value)?),
+        "ime" => registers.set_ime(number::<u8>(value)? != 0),
+        register => return Err(format!("Unknown register `{}`", register)),
+    }
+    Ok(())
+}
+
+/// A hex number, with or without `$` or `0x`
+pub fn number<T: TryFrom<u32>>(text: &str) -> Result<T, String> {
+    let digits = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")).unwrap_or(text);
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| format!("Invalid number `{}`", text))
+}
+
+/// A label or a hex number
+fn address(text: &str, symbols: &Symbols) -> Result<u16, String> {
+    match symbols.address_of(text) {
+        Some((_, address)) => Ok(address),
+        None => number(text),
+    }
+}
+
+/// A decimal count, `default` if it's left out
+fn count<T: std::str::FromStr>(text: Option<&&str>, default: T) -> Result<T, String> {
+    match text {
+        Some(text) => text.parse().map_err(|_| format!("Invalid count `{}`", text)),
+        None => Ok(default),
+    }
+}
+
+/// `ADDR` or `START-END`, addresses can be labels
+fn range(text: &str, symbols: &Symbols) -> Result<RangeInclusive<u16>, String> {
+    match text.split_once('-') {
+        Some((start, end)) => Ok(address(start, symbols)?..=address(end, symbols)?),
+        None => {
+            let address = address(text, symbols)?;
+            Ok(address..=address)
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! `gbrs disasm`: turn a whole ROM into RGBDS source, that assembles back into the same ROM.
+//!
+//! Code is found by recursive descent from the entry point, the RST and the interrupt vectors.
+//! Everything that isn't reached that way is written as data, so the bytes always stay the same.
+//!
+//! Labels of the ROM's `.sym` file replace the generated ones, labels in RAM become constants.
+//!
+//! With a code/data log of the ROM (`ROM.cdl`, see `--cdl`), the code, that was executed, is
+//! traced too, so code only reached through jump tables is found. Bytes, that were only read as
+//! data or tiles, are never traced as code.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+use crate::game_boy::cpu::cdl;
+use crate::game_boy::cpu::disassembler::{disassemble_rom, Instruction, Location};
+use crate::game_boy::memory::addresses as adr;
+use crate::game_boy::memory::rom::Rom;
+use crate::symbols::Symbols;
+
+const ENTRY_POINTS: [(u16, &str); 14] = [
+    (0x0000, "RST_00"),
+    (0x0008, "RST_08"),
+    (0x0010, "RST_10"),
+    (0x0018, "RST_18"),
+    (0x0020, "RST_20"),
+    (0x0028, "RST_28"),
+    (0x0030, "RST_30"),
+    (0x0038, "RST_38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDCInterrupt"),
+    (0x0050, "TimerOverflowInterrupt"),
+    (0x0058, "SerialTransferCompleteInterrupt"),
+    (0x0060, "JoypadTransitionInterrupt"),
+    (0x0100, "Entry"),
+];
+
+/// Bytes per `db` line
+const DATA_LINE_LENGTH: usize = 16;
+
+pub struct RomDisassembly {
+    image: Vec<u8>,
+    /// Instructions by their offset in the image
+    instructions: HashMap<usize, Instruction>,
+    /// Is the byte part of an instruction?
+    code: Vec<bool>,
+    /// Was the byte used as data (or tiles), but never executed?
+    data: Vec<bool>,
+    labels: BTreeMap<Location, String>,
+    /// Names of RAM addresses, written as `DEF`s
+    constants: BTreeMap<u16, String>,
+}
+
+impl RomDisassembly {
+    pub fn new(image: Vec<u8>) -> RomDisassembly {
+        let code = vec![false; image.len()];
+        let data = vec![false; image.len()];
+        RomDisassembly {
+            image,
+            instructions: HashMap::new(),
+            code,
+            data,
+            labels: BTreeMap::new(),
+            constants: BTreeMap::new(),
+        }
+    }
+
+    pub fn bank_count(&self) -> u16 {
+        self.image.len().div_ceil(Rom::BANK_SIZE) as u16
+    }
+
+    /// Trace the code from the entry point and the RST and interrupt vectors
+    pub fn trace_entry_points(&mut self) {
+        for (address, name) in ENTRY_POINTS {
+            let location = Location::new(Some(0), address);
+            self.labels.insert(location, name.to_owned());
+            self.trace(location);
+        }
+    }
+
+    /// Keep the bytes out of the code, that a code/data log saw used as data (or tiles), but
+    /// never executed. Logs of another ROM are ignored.
+    pub fn use_code_data_log(&mut self, flags: &[u8]) {
+        if flags.len() == self.image.len() {
+            for (data, flags) in self.data.iter_mut().zip(flags) {
+                *data = flags & cdl::CODE == 0 && flags & (cdl::DATA | cdl::TILES) != 0;
+            }
+        }
+    }
+
+    /// Trace the code from each instruction, that a code/data log saw executed. Code, that
+    /// wasn't found by tracing from the entry points, gets a label.
+    pub fn trace_executed(&mut self, flags: &[u8]) {
+        if flags.len() != self.image.len() {
+            return;
+        }
+        for (offset, flags) in flags.iter().enumerate() {
+            if flags & cdl::INSTRUCTION != 0 && !self.code[offset] {
+                let bank = offset / Rom::BANK_SIZE;
+                let address = if bank == 0 { offset } else { Rom::BANK_SIZE + offset % Rom::BANK_SIZE };
+                let location = Location::new(Some(bank as u16), address as u16);
+                self.labels.entry(location).or_insert_with(|| format!("Code_{:03X}_{:04X}", bank, address));
+                self.trace(location);
+            }
+        }
+    }
+
+    /// Use the labels of a sym file, instead of the generated ones. Local labels keep their full
+    /// name, RGBDS accepts that.
+    pub fn use_symbols(&mut self, symbols: &Symbols) {
+        let names: HashSet<&str> = symbols.labels().map(|(_, name)| name).collect();
+        self.labels.retain(|_, name| !names.contains(name.as_str()));
+        for ((bank, address), name) in symbols.labels() {
+            if address >= 0x8000 {
+                // Constants can't be local, the I/O registers are defined already
+                if !name.contains('.') && !adr::IO_REGISTER_NAMES.iter().any(|(_, io)| *io == name) {
+                    self.constants.insert(address, name.to_owned());
+                }
+            } else if let Some(location) = self.resolve(Location::new(Some(bank), address)) {
+                self.labels.insert(location, name.to_owned());
+            }
+        }
+    }
+
+    /// Follow the code starting at the location, including all jumps and calls, that can be
+    /// resolved statically
+    pub fn trace(&mut self, start: Location) {
+        let mut todo = vec![start];
+        while let Some(location) = todo.pop() {
+            let bank = location.bank().unwrap_or(0);
+            let mut address = location.address();
+            while let Some(offset) = self.offset(Location::new(Some(bank), address)) {
+                // Already traced, or in the middle of another instruction
+                if self.code[offset] {
+                    break;
+                }
+                let instruction = match disassemble_rom(&self.image, bank, address) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                let length = instruction.length() as usize;
+                let bytes = offset..offset + length;
+                if self.code[bytes.clone()].iter().any(|c| *c) || self.data[bytes].iter().any(|d| *d) {
+                    break;
+                }
+                self.code[offset..offset + length].fill(true);
+                self.instructions.insert(offset, instruction);
+
+                if let Some(target) = instruction.target().and_then(|t| self.resolve(t)) {
+                    let kind = match instruction.opcode().mnemonic() {
+                        "CALL" | "RST" => "Call",
+                        _ => "Jump",
+                    };
+                    self.labels.entry(target).or_insert_with(|| {
+                        format!("{}_{:03X}_{:04X}", kind, target.bank().unwrap_or(0), target.address())
+                    });
+                    todo.push(target);
+                }
+                if instruction.ends_block() {
+                    break;
+                }
+                address = instruction.next_address();
+                // Falling through from bank 0 into the switchable bank can't be followed
+                if address % Rom::BANK_SIZE as u16 == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Fill in the bank of a location in ROM, if it can be known. Without an MBC the second
+    /// bank is always mapped.
+    fn resolve(&self, location: Location) -> Option<Location> {
+        let location = match (location.bank(), location.address()) {
+            (_, 0x8000..=0xFFFF) => return None,
+            (Some(_), _) => location,
+            (None, address) if self.bank_count() == 2 => Location::new(Some(1), address),
+            (None, _) => return None,
+        };
+        self.offset(location).map(|_| location)
+    }
+
+    /// The offset of a ROM location in the image
+    fn offset(&self, location: Location) -> Option<usize> {
+        let bank = location.bank()? as usize;
+        let address = location.address() as usize;
+        if address >= 2 * Rom::BANK_SIZE || (bank == 0) != (address < Rom::BANK_SIZE) {
+            return None;
+        }
+        let offset = bank * Rom::BANK_SIZE + address % Rom::BANK_SIZE;
+        if offset < self.image.len() { Some(offset) } else { None }
+    }
+
+    /// The label at the location, if it will be written. Labels can't be in the middle of an
+    /// instruction.
+    fn label(&self, location: Location) -> Option<&String> {
+        if location.address() >= 0x8000 {
+            return self.constants.get(&location.address());
+        }
+        let location = self.resolve(location)?;
+        let offset = self.offset(location)?;
+        if self.code[offset] && !self.instructions.contains_key(&offset) {
+            return None;
+        }
+        self.labels.get(&location)
+    }
+
+    /// Write the RGBDS source
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "; Disassembled by gbrs")?;
+        writeln!(out)?;
+        for (address, name) in adr::IO_REGISTER_NAMES {
+            writeln!(out, "DEF {} EQU ${:04X}", name, address)?;
+        }
+        for (address, name) in &self.constants {
+            writeln!(out, "DEF {} EQU ${:04X}", name, address)?;
+        }
+        for bank in 0..self.bank_count() {
+            writeln!(out)?;
+            if bank == 0 {
+                writeln!(out, "SECTION \"ROM Bank $000\", ROM0[$0000]")?;
+            } else {
+                writeln!(out, "SECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[${:03X}]", bank, bank)?;
+            }
+            self.write_bank(bank, out)?;
+        }
+        Ok(())
+    }
+
+    fn write_bank(&self, bank: u16, out: &mut dyn Write) -> io::Result<()> {
+        let start = bank as usize * Rom::BANK_SIZE;
+        let end = (start + Rom::BANK_SIZE).min(self.image.len());
+        let base = if bank == 0 { 0 } else { Rom::BANK_SIZE };
+        let location = |offset: usize| Location::new(Some(bank), (base + offset - start) as u16);
+        let names = |l: Location| self.label(l).cloned();
+
+        let mut data: Vec<u8> = Vec::with_capacity(DATA_LINE_LENGTH);
+        let mut offset = start;
+        while offset < end {
+            let instruction = self.instructions.get(&offset);
+            let label = self.labels.get(&location(offset));
+            if !data.is_empty() && (instruction.is_some() || label.is_some() || data.len() == DATA_LINE_LENGTH) {
+                write_data(&data, out)?;
+                data.clear();
+            }
+            if let Some(label) = label.filter(|_| instruction.is_some() || !self.code[offset]) {
+                writeln!(out, "{}:", label)?;
+            }
+            match instruction {
+                Some(instruction) => {
+                    match needs_raw_bytes(instruction) {
+                        true => write!(out, "    db {}", format_bytes(instruction.bytes()))?,
+                        false => write!(out, "    {}", instruction.format_with(&names))?,
+                    }
+                    writeln!(out, " ; {}", instruction.location())?;
+                    offset += instruction.length() as usize;
+                }
+                None => {
+                    data.push(self.image[offset]);
+                    offset += 1;
+                }
+            }
+        }
+        if !data.is_empty() {
+            write_data(&data, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Instructions, that RGBDS would not assemble into the same bytes
+fn needs_raw_bytes(instruction: &Instruction) -> bool {
+    match instruction.bytes() {
+        // rgbasm may add a NOP after HALT
+        [0x76] => true,
+        // rgbasm always writes STOP as $10 $00
+        [0x10, operand] => *operand != 0x00,
+        // rgbasm may optimize LD [$FF00+n8] into LDH
+        [0xEA, _, 0xFF] | [0xFA, _, 0xFF] => true,
+        _ => instruction.opcode().is_illegal(),
+    }
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("${:02X}", b)).collect::<Vec<_>>().join(", ")
+}
+
+fn write_data(data: &[u8], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "    db {}", format_bytes(data))
+}
+
+/// Disassemble the ROM at `rom_path` into `output` (stdout if `None`)
+pub fn run(rom_path: &str, output: Option<&str>) -> io::Result<()> {
+    let mut disassembly = RomDisassembly::new(std::fs::read(rom_path)?);
+    let cdl_path = Path::new(rom_path).with_extension("cdl");
+    let flags = if cdl_path.exists() { std::fs::read(cdl_path)? } else { Vec::new() };
+    disassembly.use_code_data_log(&flags);
+    disassembly.trace_entry_points();
+    disassembly.trace_executed(&flags);
+    disassembly.use_symbols(&Symbols::for_rom(Path::new(rom_path))?);
+    match output {
+        Some(path) => disassembly.write(&mut io::BufWriter::new(std::fs::File::create(path)?)),
+        None => disassembly.write(&mut io::BufWriter::new(io::stdout().lock())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::cpu::assembler::assemble;
+
+    /// Two banks: code in both, instructions RGBDS would change and data after the code
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 2 * Rom::BANK_SIZE];
+        let mut place = |offset: usize, bytes: &[u8]| image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        for (address, _) in ENTRY_POINTS {
+            place(address as usize, &[0xC9]); // RET
+        }
+        place(0x0100, &[0x00, 0xC3, 0x50, 0x01]); // NOP, JP $0150
+        place(0x0150, &[
+            0xFA, 0x44, 0xFF, // LD A,[$FF44]
+            0xEA, 0x80, 0xFF, // LD [$FF80],A
+            0x76,             // HALT
+            0x10, 0x01,       // STOP $01
+            0xCD, 0x00, 0x40, // CALL $4000
+            0x20, 0xF2,       // JR NZ,$0150
+            0xD3,             // illegal
+        ]);
+        place(0x0200, b"data, not code");
+        place(0x4000, &[
+            0x21, 0x00, 0xC0, // LD HL,$C000
+            0xE0, 0x40,       // LDH [rLCDC],A
+            0x22,             // LD [HL+],A
+            0xC9,             // RET
+            0x01, 0x02, 0x03,
+        ]);
+        image
+    }
+
+    fn source(disassembly: &RomDisassembly) -> String {
+        let mut out = Vec::new();
+        disassembly.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Assemble the output of the disassembler back into a ROM image
+    fn reassemble(source: &str, size: usize) -> Vec<u8> {
+        let mut image = vec![0u8; size];
+        for chunk in assemble(source, 0).unwrap() {
+            let offset = match chunk.bank() {
+                Some(bank) => bank as usize * Rom::BANK_SIZE + chunk.address() as usize - Rom::BANK_SIZE,
+                None => chunk.address() as usize,
+            };
+            image[offset..offset + chunk.bytes().len()].copy_from_slice(chunk.bytes());
+        }
+        image
+    }
+
+    #[test]
+    fn disassembly_assembles_into_the_same_rom() {
+        let image = image();
+        let mut disassembly = RomDisassembly::new(image.clone());
+        disassembly.trace_entry_points();
+        let source = source(&disassembly);
+        assert_eq!(reassemble(&source, image.len()), image);
+    }
+
+    #[test]
+    fn instructions_rgbds_would_change_are_written_as_bytes() {
+        let mut disassembly = RomDisassembly::new(image());
+        disassembly.trace_entry_points();
+        let source = source(&disassembly);
+        let lines = [
+            "Jump_000_0150:",
+            "    db $FA, $44, $FF ; 00:0150",
+            "    db $EA, $80, $FF ; 00:0153",
+            "    db $76 ; 00:0156",
+            "    db $10, $01 ; 00:0157",
+            "    CALL Call_001_4000 ; 00:0159",
+            "    JR NZ, Jump_000_0150 ; 00:015C",
+            "    db $D3 ; 00:015E",
+        ];
+        assert!(source.contains(&lines.join("\n")), "{}", source);
+    }
+
+    #[test]
+    fn code_is_traced_from_the_entry_points() {
+        let mut disassembly = RomDisassembly::new(image());
+        disassembly.trace_entry_points();
+        let source = source(&disassembly);
+        assert!(source.contains("RST_38:\n    RET ; 00:0038\n    db $00, $00"));
+        assert!(source.contains("Entry:\n    NOP ; 00:0100\n    JP Jump_000_0150 ; 00:0101\n"));
+        assert!(source.contains("SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$001]\nCall_001_4000:\n    LD HL, $C000 ; 01:4000\n"));
+        // Not reached, so data
+        assert!(source.contains("$64, $61, $74, $61, $2C"));
+        assert!(source.contains("    RET ; 01:4006\n    db $01, $02, $03"));
+    }
+
+    #[test]
+    fn logged_data_is_not_traced_and_executed_code_is() {
+        let mut image = image();
+        // Only reached through a jump table
+        image[0x0300] = 0xC9;
+        let mut flags = vec![0u8; image.len()];
+        flags[0x0100] = cdl::DATA;
+        flags[0x0300] = cdl::CODE | cdl::INSTRUCTION;
+        let mut disassembly = RomDisassembly::new(image.clone());
+        disassembly.use_code_data_log(&flags);
+        disassembly.trace_entry_points();
+        disassembly.trace_executed(&flags);
+        let source = source(&disassembly);
+        assert!(source.contains("Entry:\n    db $00, $C3, $50, $01"));
+        assert!(!source.contains("Jump_000_0150"));
+        assert!(source.contains("Code_000_0300:\n    RET ; 00:0300\n"));
+        assert_eq!(reassemble(&source, image.len()), image);
+    }
+
+    #[test]
+    fn symbols_replace_generated_labels() {
+        let image = image();
+        let mut disassembly = RomDisassembly::new(image.clone());
+        disassembly.trace_entry_points();
+        disassembly.use_symbols(&Symbols::parse("01:4000 ClearScreen\n00:C000 wBuffer\n00:FF40 rLCDC\n"));
+        let source = source(&disassembly);
+        assert!(source.contains("    CALL ClearScreen ; 00:0159"));
+        assert!(source.contains("ClearScreen:\n    LD HL, $C000 ; 01:4000\n"));
+        assert!(source.contains("DEF wBuffer EQU $C000\n"));
+        assert_eq!(source.matches("DEF rLCDC ").count(), 1);
+        assert!(!source.contains("Call_001_4000"));
+        assert_eq!(reassemble(&source, image.len()), image);
+    }
+}
@@ -2,18 +2,41 @@ use crate::game_boy::memory::MemError;
 use crate::game_boy::video::{PPU, VideoMode};
 use std::path::PathBuf;
 use crate::game_boy::cpu::debug::DebugStackInfo;
+use crate::game_boy::cpu::{CpuError, LockUp};
+use crate::game_boy::cpu::registers::Registers;
+use crate::game_boy::cpu::callstack::BacktraceFrame;
+use crate::game_boy::cpu::profiler::Profile;
+use crate::game_boy::cpu::cdl::CodeDataLog;
+use crate::game_boy::cpu::blocks::BlockCache;
+use crate::game_boy::cpu::assembler::{self, AsmError};
+use std::fmt::{Display, Formatter};
 use crate::game_boy::interrupt::Interrupt;
 use crate::game_boy::memory::video::LcdStatusBit;
+use crate::game_boy::scheduler::{Event, Scheduler};
+use crate::game_boy::cpu::time::DIVIDER_PERIOD;
+use crate::game_boy::hooks::Hooks;
+use crate::game_boy::breakpoints::{BreakpointHit, Breakpoints, Hit};
+use crate::game_boy::expression::{Environment, Expression};
+use crate::game_boy::timeline::Timeline;
 
 pub mod cpu;
 pub mod memory;
+
+pub use memory::joypad::Button;
 mod video;
-mod interrupt;
+pub mod interrupt;
+mod scheduler;
 mod helpers;
+pub mod hooks;
+pub mod breakpoints;
+pub mod expression;
+pub mod timeline;
 
 #[derive(Debug)]
 pub enum GBRSError {
     MemError(MemError),
+    /// The emulated CPU faulted. PC still points to the faulting instruction.
+    CpuError(CpuError),
 }
 
 impl From<MemError> for GBRSError {
@@ -22,6 +45,21 @@ impl From<MemError> for GBRSError {
     }
 }
 
+impl From<CpuError> for GBRSError {
+    fn from(e: CpuError) -> Self {
+        Self::CpuError(e)
+    }
+}
+
+impl Display for GBRSError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GBRSError::MemError(e) => write!(f, "memory error: {:?}", e),
+            GBRSError::CpuError(e) => write!(f, "CPU fault: {}", e),
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct InstructionInformation {
     instruction: u8,
@@ -42,7 +80,10 @@ impl InstructionInformation {
 #[derive(Copy, Clone)]
 pub struct ClockInformation {
     instruction: InstructionInformation,
-    frame_done: bool
+    frame_done: bool,
+    lock_up: Option<LockUp>,
+    cycles: u32,
+    breakpoint: Option<BreakpointHit>
 }
 
 impl ClockInformation {
@@ -54,27 +95,39 @@ impl ClockInformation {
         self.frame_done
     }
 
-    pub fn new(instruction: u8, data: [Option<u8>; 4], stack_info: DebugStackInfo, is_new_instruction: bool, clocks_left: u32, frame_done: bool) -> ClockInformation {
-        ClockInformation {
-            instruction: InstructionInformation {
-                instruction,
-                data,
-                stack_info,
-                is_new: is_new_instruction,
-                clocks_left
-            },
-            frame_done
-        }
+    /// Set, if the CPU locked up on an illegal opcode during this clock
+    pub fn lock_up(&self) -> Option<LockUp> {
+        self.lock_up
+    }
+
+    /// The M-cycles this clock took. More than one, if the CPU was only waiting and the idle
+    /// cycles were skipped.
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    /// Set, if a breakpoint was hit. The emulation should pause, see [`Breakpoints`].
+    pub fn breakpoint(&self) -> Option<BreakpointHit> {
+        self.breakpoint
     }
 }
 
 // #[derive(Debug)]
 pub struct GameBoy {
     cpu: cpu::Cpu,
-    // cycles left in current instruction
-    cycles_left_in_instruction: u32,
     clock_number_in_current_frame: u32,
-    old_stat_interrupt_state: bool
+    old_stat_interrupt_state: bool,
+    /// The mode of the LCD controller. STAT only mirrors it and loses it, when it's written.
+    video_mode: VideoMode,
+    scheduler: Scheduler,
+    /// Set, if instructions run from cached blocks instead of M-cycle by M-cycle
+    blocks: Option<BlockCache>,
+    /// A CPU cycle of a block in double speed mode, that didn't make a whole PPU cycle
+    spare_cpu_cycle: bool,
+    /// Frames done since power on
+    frames: u64,
+    /// `None` unless recording for reverse debugging
+    timeline: Option<Box<Timeline>>,
 }
 
 // Constants
@@ -90,8 +143,6 @@ impl GameBoy {
     const LINES: u32 = GameBoy::DRAW_LINES + GameBoy::V_BLANK_LINES;
 
     const CLOCKS: u32 = GameBoy::LINES * GameBoy::CLOCKS_PER_LINE;
-
-    const V_BLANK_INTERRUPT_CLOCK: u32 = Self::DRAW_LINES * Self::CLOCKS_PER_LINE;
 }
 
 
@@ -99,12 +150,25 @@ impl GameBoy {
 impl GameBoy {
     pub fn load<'a>(path: &'_ PathBuf) -> Result<GameBoy, GBRSError> {
         let memory = memory::MMU::load_from_path(path)?;
-        Ok(GameBoy {
-            cpu: cpu::Cpu::new(memory),
-            cycles_left_in_instruction: 0,
+        Ok(GameBoy::new(cpu::Cpu::new(memory)))
+    }
+
+    fn new(cpu: cpu::Cpu) -> GameBoy {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(0, Event::LineStart);
+        scheduler.schedule(0, Event::DividerTick);
+        // The timer is disabled, until TAC is written
+        GameBoy {
+            cpu,
             clock_number_in_current_frame: 0,
-            old_stat_interrupt_state: false
-        })
+            old_stat_interrupt_state: false,
+            video_mode: VideoMode::OAM,
+            scheduler,
+            blocks: None,
+            spare_cpu_cycle: false,
+            frames: 0,
+            timeline: None,
+        }
     }
 }
 
@@ -117,6 +181,116 @@ impl GameBoy {
     pub fn cpu(&self) -> &cpu::Cpu {
         &self.cpu
     }
+
+    pub fn registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    /// Where the CPU is and which calls and interrupts led there, innermost first
+    pub fn backtrace(&self) -> Vec<BacktraceFrame> {
+        self.cpu.backtrace()
+    }
+
+    /// Frames done since power on
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+// Debugging
+impl GameBoy {
+    /// Assemble `source` for `address` and write it there, without side effects. ROM addresses
+    /// patch the mapped bank of the ROM image. Returns the bytes, that were written.
+    pub fn patch(&mut self, address: u16, source: &str) -> Result<Vec<u8>, AsmError> {
+        let bytes = assembler::assemble_bytes(source, address)?;
+        self.poke(address, &bytes);
+        Ok(bytes)
+    }
+
+    /// Write bytes to memory, without side effects (see [`memory::MMU::poke`])
+    pub fn poke(&mut self, address: u16, bytes: &[u8]) {
+        self.cpu.memory_mut().poke(address, bytes);
+        if let Some(blocks) = &mut self.blocks {
+            blocks.clear();
+        }
+    }
+
+    /// The hooks to observe the emulation with. They are installed on first use, see
+    /// [`GameBoy::remove_hooks`].
+    pub fn hooks(&mut self) -> &mut Hooks {
+        self.cpu.hooks()
+    }
+
+    /// Remove all hooks, without any the emulation doesn't have to call them
+    pub fn remove_hooks(&mut self) {
+        self.cpu.remove_hooks();
+    }
+
+    /// The breakpoints and watchpoints. They are installed on first use, see
+    /// [`GameBoy::remove_breakpoints`].
+    pub fn breakpoints(&mut self) -> &mut Breakpoints {
+        self.cpu.breakpoints()
+    }
+
+    /// Remove all breakpoints, without any the emulation doesn't have to check them
+    pub fn remove_breakpoints(&mut self) {
+        self.cpu.remove_breakpoints();
+    }
+
+    /// Count the cycles of the emulated program by PC and call stack, see [`Profile`]. Starts
+    /// over, if it already runs.
+    pub fn start_profile(&mut self) {
+        self.cpu.start_profile();
+    }
+
+    /// Stop profiling, the profile so far
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.cpu.take_profile()
+    }
+
+    /// Log what each ROM byte is used for, see [`CodeDataLog`]. `flags` are of an earlier
+    /// session. Returns `false`, if they don't fit the ROM and were ignored.
+    pub fn start_code_data_log(&mut self, flags: Option<&[u8]>) -> bool {
+        let mut log = CodeDataLog::new(self.memory().rom().data().len());
+        let merged = flags.is_none_or(|flags| log.merge(flags));
+        self.cpu.start_code_data_log(log);
+        merged
+    }
+
+    /// Stop logging, the log so far
+    pub fn take_code_data_log(&mut self) -> Option<CodeDataLog> {
+        self.cpu.take_code_data_log()
+    }
+
+    /// Evaluate the expression in the current state
+    pub fn evaluate(&self, expression: &Expression) -> i64 {
+        expression.evaluate(&Environment::new(self.registers(), self.memory()))
+    }
+
+    /// Overwrite the CPU registers, e.g. to set up a state to test
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.cpu.set_registers(registers);
+    }
+}
+
+// Execution
+impl GameBoy {
+    /// Run instructions from cached, pre-decoded blocks instead of M-cycle by M-cycle. This is
+    /// a lot faster, but memory accesses aren't timed and the PPU and the timers only catch up
+    /// between instructions. Meant for bulk headless runs, compare with the default to check.
+    pub fn use_block_cache(&mut self, enabled: bool) {
+        self.blocks = if enabled { Some(BlockCache::new()) } else { None };
+    }
+}
+
+// Input
+impl GameBoy {
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.memory().is_pressed(button) != pressed {
+            self.record_input(button, pressed);
+        }
+        self.cpu.set_button(button, pressed);
+    }
 }
 
 // Clocking
@@ -130,108 +304,239 @@ impl GameBoy {
     // - 144 lines
     // - 10 lines V-Blank
 
-    /// Do one single clock cycle in the GB-CPU
-    pub fn clock(&mut self, buffer: &mut Box<[u8]>) -> ClockInformation {
+    /// Do one single clock cycle in the GB-CPU. If the CPU is only waiting (HALT, STOP), skip
+    /// right to the next event instead, but never past the end of the frame. With the block
+    /// cache a whole block runs, until the next event is due.
+    ///
+    /// On an emulation fault PC is left on the faulting instruction, see [`CpuError`].
+    pub fn clock(&mut self, buffer: &mut Box<[u8]>) -> Result<ClockInformation, GBRSError> {
         let ins = self.cpu.peek_instruction();
         let data = self.cpu.peek_data();
         let stack_info = self.cpu().debug_stack_info();
-        let current_line = self.clock_number_in_current_frame / Self::CLOCKS_PER_LINE;
-        let clock_in_line = self.clock_number_in_current_frame % Self::CLOCKS_PER_LINE;
+        self.record();
 
-        self.cpu.memory_mut().set_ly(current_line as u8);
-        self.cpu.memory_mut().update_lyc_ly_cmp();
-
-        let mut stat_interrupt_state = self.cpu.memory().get_lcd_status(LcdStatusBit::LycLyCmp);
+        while let Some((due, event)) = self.scheduler.pop_due() {
+            self.handle_event(due, event, buffer);
+        }
 
-        if self.clock_number_in_current_frame == Self::V_BLANK_INTERRUPT_CLOCK {
-            // println!("VBLANK interrupt");
-            self.cpu.request_interrupt(Interrupt::VBlank);
+        if self.cpu.is_stopped() && self.clock_number_in_current_frame == 0 {
+            // The LCD controller doesn't run in STOP mode, the screen stays blank
+            buffer.fill(0);
         }
 
-        // if current_line == 0 && !self.memory().boot_rom_enabled() {
-        //     println!("New Frame");
-        // }
-
-        // TODO variable MODE 3 length https://gbdev.io/pandocs/Rendering.html#mode-3-length
-        // Pixel Transfer takes different times
-        if current_line < GameBoy::DRAW_LINES {
-            if clock_in_line < GameBoy::OAM_SEARCH_CLOCKS {
-                // OAM search
-                self.cpu.memory_mut().set_video_mode(VideoMode::OAM);
-                stat_interrupt_state |= self.cpu.memory().get_lcd_status(LcdStatusBit::OamStatInterrupt);
-            } else if clock_in_line < GameBoy::PIXEL_TRANSFER_CLOCKS {
-                // Pixel transfer
-                self.cpu.memory_mut().set_video_mode(VideoMode::PixelTransfer);
+        let was_locked_up = self.cpu.locked_up().is_some();
+
+        let mut new_instruction = false;
+        let cycles = if self.cpu.is_waiting() {
+            let until_frame_end = (Self::CLOCKS - self.clock_number_in_current_frame) as u64;
+            self.scheduler.cycles_until_next().min(until_frame_end) as u32
+        } else if self.blocks.is_some() {
+            new_instruction = true;
+            self.block_clock()?
+        } else {
+            new_instruction = self._cpu_clock()?;
+            if self.paused_before_instruction() {
+                // The instruction didn't start, no time passed
+                0
             } else {
-                // H-Blank
-                self.cpu.memory_mut().set_video_mode(VideoMode::HBlank);
-                stat_interrupt_state |= self.cpu.memory().get_lcd_status(LcdStatusBit::HBlankStatInterrupt);
+                if self.cpu.memory().double_speed() && self.cpu.breakpoint_hit().is_none() {
+                    // In double speed mode the CPU and the timers run twice per PPU cycle
+                    new_instruction |= self._cpu_clock()?;
+                }
+                1
+            }
+        };
+
+        self.scheduler.advance(cycles as u64);
+        self.clock_number_in_current_frame += cycles;
+        // A block can run past the end of the frame
+        let new_frame = self.clock_number_in_current_frame >= Self::CLOCKS;
+        if new_frame {
+            self.clock_number_in_current_frame -= Self::CLOCKS;
+            self.frames += 1;
+            if let Some(hooks) = self.cpu.installed_hooks() {
+                hooks.frame(self.frames);
             }
-        } else {
-            // V-Blank
-            self.cpu.memory_mut().set_video_mode(VideoMode::VBlank);
-            stat_interrupt_state |= self.cpu.memory().get_lcd_status(LcdStatusBit::VBlankStatInterrupt);
         }
+        Ok(ClockInformation {
+            instruction: InstructionInformation {
+                instruction: ins,
+                data,
+                stack_info,
+                is_new: new_instruction,
+                clocks_left: self.cpu.cycles_left(),
+            },
+            frame_done: new_frame,
+            lock_up: self.cpu.locked_up().filter(|_| !was_locked_up),
+            cycles,
+            breakpoint: self.cpu.take_breakpoint_hit(),
+        })
+    }
+
+    /// Did the CPU stop at an execute breakpoint, without doing the cycle?
+    fn paused_before_instruction(&self) -> bool {
+        matches!(self.cpu.breakpoint_hit().map(|hit| hit.hit()), Some(Hit::Execute))
+    }
+
+    /// Neither the timers nor the LCD controller run in STOP mode, but their events keep coming,
+    /// so the frame counter keeps going and the frontend still gets to poll the buttons that end
+    /// STOP mode.
+    ///
+    /// Events are handled late, after a block of the block cache ran past them. Periodic events
+    /// are scheduled relative to `due`, so they don't drift.
+    fn handle_event(&mut self, due: u64, event: Event, buffer: &mut Box<[u8]>) {
+        let stopped = self.cpu.is_stopped();
+        match event {
+            Event::LineStart => {
+                let late = (self.scheduler.now() - due) as u32;
+                let clock = (self.clock_number_in_current_frame + Self::CLOCKS - late) % Self::CLOCKS;
+                let line = clock / Self::CLOCKS_PER_LINE;
+                let previous_line = (line + Self::LINES - 1) % Self::LINES;
+                if let Some(hooks) = self.cpu.installed_hooks() {
+                    hooks.scanline(line as u8);
+                }
+                if !stopped {
+                    if previous_line < Self::DRAW_LINES {
+                        // Probably can't write line by line
+                        PPU::write_line(self.cpu.memory_mut(), buffer);
+                    }
+                    self.cpu.memory_mut().set_ly(line as u8);
+                    if line == Self::DRAW_LINES {
+                        self.cpu.request_interrupt(Interrupt::VBlank);
+                    }
+                }
+                if line < Self::DRAW_LINES {
+                    self.video_mode = VideoMode::OAM;
+                    self.scheduler.schedule_at(due + Self::OAM_SEARCH_CLOCKS as u64, Event::PixelTransfer);
+                } else {
+                    self.video_mode = VideoMode::VBlank;
+                }
+                self.scheduler.schedule_at(due + Self::CLOCKS_PER_LINE as u64, Event::LineStart);
+            }
+            // TODO variable MODE 3 length https://gbdev.io/pandocs/Rendering.html#mode-3-length
+            // Pixel Transfer takes different times
+            Event::PixelTransfer => {
+                self.video_mode = VideoMode::PixelTransfer;
+                self.scheduler.schedule_at(due + Self::PIXEL_TRANSFER_CLOCKS as u64, Event::HBlank);
+            }
+            Event::HBlank => self.video_mode = VideoMode::HBlank,
+            Event::DividerTick => {
+                if !stopped {
+                    self.cpu.divider_tick();
+                }
+                self.scheduler.schedule_at(due + self.ppu_cycles(DIVIDER_PERIOD), Event::DividerTick);
+            }
+            Event::TimerTick => {
+                if !stopped {
+                    self.cpu.timer_tick();
+                }
+                self.schedule_timer(due);
+            }
+        }
+        if !stopped {
+            self.update_lcd_status();
+        }
+    }
+
+    /// Bring the mode and LYC=LY back into STAT and request the STAT interrupt on a rising edge
+    /// of its ORed sources
+    fn update_lcd_status(&mut self) {
+        let mmu = self.cpu.memory_mut();
+        mmu.set_video_mode(self.video_mode);
+        mmu.update_lyc_ly_cmp();
+        let mut stat_interrupt_state = mmu.get_lcd_status(LcdStatusBit::LycLyCmp)
+            && mmu.get_lcd_status(LcdStatusBit::LycStatInterrupt);
+        stat_interrupt_state |= match self.video_mode {
+            VideoMode::OAM => mmu.get_lcd_status(LcdStatusBit::OamStatInterrupt),
+            VideoMode::PixelTransfer => false,
+            VideoMode::HBlank => mmu.get_lcd_status(LcdStatusBit::HBlankStatInterrupt),
+            VideoMode::VBlank => mmu.get_lcd_status(LcdStatusBit::VBlankStatInterrupt),
+        };
 
         if !self.old_stat_interrupt_state && stat_interrupt_state {
             self.cpu.request_interrupt(Interrupt::LcdcStatus);
         }
+        self.old_stat_interrupt_state = stat_interrupt_state;
+    }
 
-        // TODO first draw or first new clock()
-        let new_instruction = self._cpu_clock();
-
-        if current_line < GameBoy::DRAW_LINES && clock_in_line + 1 == Self::CLOCKS_PER_LINE {
-            // Probably can't write line by line
-            PPU::write_line(self.cpu.memory_mut(), buffer);
+    /// (Re)schedule the next increment of TIMA, after it ticked or DIV, TIMA or TAC was written at
+    /// `from`
+    fn schedule_timer(&mut self, from: u64) {
+        self.scheduler.cancel(Event::TimerTick);
+        if let Some(period) = self.cpu.timer_period() {
+            self.scheduler.schedule_at(from + self.ppu_cycles(period), Event::TimerTick);
         }
+    }
 
-        self.clock_number_in_current_frame += 1;
-        let new_frame = self.clock_number_in_current_frame == Self::CLOCKS;
-        if new_frame { self.clock_number_in_current_frame = 0; }
-        ClockInformation::new(ins, data, stack_info, new_instruction, self.cycles_left_in_instruction, new_frame)
-        // new_frame
+    /// The PPU cycles for this many CPU cycles
+    fn ppu_cycles(&self, cpu_cycles: u32) -> u64 {
+        if self.cpu.memory().double_speed() {
+            (cpu_cycles / 2) as u64
+        } else {
+            cpu_cycles as u64
+        }
     }
 
     /// Try to do the next cpu instruction.
     /// Returns `true`, if a new instruction started
     /// Returns `false`, if the old one is still running
-    fn _cpu_clock(&mut self) -> bool {
-        let mut r = false;
-        if self.cycles_left_in_instruction <= 0 {
-            // tick() returns the time needed for the new instructions, _including_ the current
-            // clock cycle -> tick() - 1
-            self.cycles_left_in_instruction = self.cpu.tick() - 1;
-            r = true;
+    fn _cpu_clock(&mut self) -> Result<bool, CpuError> {
+        // Memory accesses of the instruction happen in their own M-cycle
+        let r = self.cpu.cycle()?;
+        self.handle_register_writes();
+        Ok(r)
+    }
+
+    /// Run a block from the block cache, until the next event is due. Returns the PPU cycles it
+    /// took.
+    fn block_clock(&mut self) -> Result<u32, CpuError> {
+        let double_speed = self.cpu.memory().double_speed();
+        let budget = self.scheduler.cycles_until_next().min(u32::MAX as u64 / 2) as u32;
+        let budget = if double_speed { budget * 2 } else { budget };
+        let blocks = self.blocks.as_mut().unwrap();
+        let cpu_cycles = self.cpu.run_block(blocks, budget)?;
+        self.handle_register_writes();
+        Ok(if double_speed {
+            let cpu_cycles = cpu_cycles + self.spare_cpu_cycle as u32;
+            self.spare_cpu_cycle = cpu_cycles % 2 == 1;
+            cpu_cycles / 2
         } else {
-            self.cycles_left_in_instruction -= 1;
+            cpu_cycles
+        })
+    }
+
+    /// Register writes, that the event handlers depend on
+    fn handle_register_writes(&mut self) {
+        if self.cpu.memory_mut().take_lcd_status_written() {
+            self.update_lcd_status();
         }
-        self.cpu.timer_clock_cycle();
-        r
-    }
-
-    pub fn frame(&mut self, buffer: &mut Box<[u8]>) {
-        for line in 0..GameBoy::LINES {
-            self.cpu.memory_mut().set_ly(line as u8);
-            if line < GameBoy::DRAW_LINES {
-                for clock in 0..GameBoy::CLOCKS_PER_LINE {
-                    // TODO set modes
-                    if clock < GameBoy::OAM_SEARCH_CLOCKS {
-                        // OAM search
-                    } else if clock < GameBoy::PIXEL_TRANSFER_CLOCKS {
-                        // Pixel transfer
-                    } else {
-                        // H-Blank
-                    }
-                    self._cpu_clock();
-                }
-                PPU::write_line(self.cpu.memory_mut(), buffer);
-            } else {
-                for _ in 0..GameBoy::CLOCKS_PER_LINE {
-                    // V-Blank
-                    self._cpu_clock();
-                }
+        let now = self.scheduler.now();
+        // TIMA counts the falling edges of a DIV bit, resetting DIV restarts both
+        if self.cpu.memory_mut().take_divider_written() {
+            self.scheduler.cancel(Event::DividerTick);
+            self.scheduler.schedule_at(now + self.ppu_cycles(DIVIDER_PERIOD), Event::DividerTick);
+            self.cpu.memory_mut().take_timer_written();
+            self.schedule_timer(now);
+        } else if self.cpu.memory_mut().take_timer_written() {
+            self.schedule_timer(now);
+        }
+    }
+
+    /// Clock until the frame is done or a breakpoint is hit
+    pub fn frame(&mut self, buffer: &mut Box<[u8]>) -> Result<Option<BreakpointHit>, GBRSError> {
+        loop {
+            let info = self.clock(buffer)?;
+            if info.breakpoint().is_some() || info.frame_done() {
+                return Ok(info.breakpoint());
             }
         }
+    }
+}
 
+#[cfg(test)]
+impl GameBoy {
+    /// A Game Boy after the boot ROM, about to run `program` at $0100 of a cartridge without MBC
+    fn with_program(program: &[u8]) -> GameBoy {
+        GameBoy::new(cpu::Cpu::with_program(program))
     }
 }
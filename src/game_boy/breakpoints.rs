@@ -0,0 +1,500 @@
+//! Breakpoints and watchpoints, that pause the emulation. They are installed with
+//! [`GameBoy::breakpoints`](crate::game_boy::GameBoy::breakpoints) and hits are reported by
+//! [`ClockInformation::breakpoint`](crate::game_boy::ClockInformation::breakpoint).
+//!
+//! An execute breakpoint pauses right before its instruction, the clock it is hit in takes no
+//! time. Clocking again runs the instruction, so resuming doesn't hit the same breakpoint again.
+//! Watchpoints and interrupt breakpoints pause after the M-cycle (or the instruction, with the
+//! block cache) they are hit in, an unfinished instruction continues when clocking again.
+//!
+//! A breakpoint with a condition ([`Breakpoints::set_condition`]) only pauses, if the
+//! [`Expression`] holds when it is reached.
+
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use crate::game_boy::expression::{Environment, Expression};
+use crate::game_boy::interrupt::Interrupt;
+use crate::game_boy::memory::addresses as adr;
+
+/// The kind of memory access, that a watchpoint reacts to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    /// Does a watchpoint for `self` react to `access` (a read or a write)?
+    fn matches(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::ReadWrite => write!(f, "read/write"),
+        }
+    }
+}
+
+/// A condition on the value, that was read or written
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValueCondition {
+    Equal(u8),
+    NotEqual(u8),
+    Less(u8),
+    Greater(u8),
+    /// The bits in `mask` have these values
+    Mask { mask: u8, value: u8 },
+}
+
+impl ValueCondition {
+    fn holds(self, value: u8) -> bool {
+        match self {
+            ValueCondition::Equal(n) => value == n,
+            ValueCondition::NotEqual(n) => value != n,
+            ValueCondition::Less(n) => value < n,
+            ValueCondition::Greater(n) => value > n,
+            ValueCondition::Mask { mask, value: expected } => value & mask == expected & mask,
+        }
+    }
+}
+
+impl Display for ValueCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueCondition::Equal(n) => write!(f, "== ${:02X}", n),
+            ValueCondition::NotEqual(n) => write!(f, "!= ${:02X}", n),
+            ValueCondition::Less(n) => write!(f, "< ${:02X}", n),
+            ValueCondition::Greater(n) => write!(f, "> ${:02X}", n),
+            ValueCondition::Mask { mask, value } => write!(f, "& ${:02X} == ${:02X}", mask, value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at the address. The bank only matters for ROM addresses, `None`
+    /// matches any bank.
+    Execute { bank: Option<u16>, address: u16 },
+    /// A data access (not an opcode fetch) in the address range
+    Watch { range: RangeInclusive<u16>, access: Access, condition: Option<ValueCondition> },
+    /// The dispatch of the interrupt, `None` for any
+    Interrupt(Option<Interrupt>),
+}
+
+impl Breakpoint {
+    pub fn execute(address: u16) -> Breakpoint {
+        Breakpoint::Execute { bank: None, address }
+    }
+
+    pub fn execute_in(bank: u16, address: u16) -> Breakpoint {
+        Breakpoint::Execute { bank: Some(bank), address }
+    }
+
+    pub fn watch(range: RangeInclusive<u16>, access: Access) -> Breakpoint {
+        Breakpoint::Watch { range, access, condition: None }
+    }
+
+    /// Watch an I/O register by its hardware.inc name, with or without the `r` (`rLCDC`, `LCDC`)
+    pub fn io_register(name: &str, access: Access) -> Option<Breakpoint> {
+        adr::IO_REGISTER_NAMES
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name) || n[1..].eq_ignore_ascii_case(name))
+            .map(|(address, _)| Breakpoint::watch(*address..=*address, access))
+    }
+
+    pub fn interrupt(interrupt: Option<Interrupt>) -> Breakpoint {
+        Breakpoint::Interrupt(interrupt)
+    }
+
+    /// Only break, if the value meets the condition. Only watchpoints have values.
+    pub fn when(self, condition: ValueCondition) -> Breakpoint {
+        match self {
+            Breakpoint::Watch { range, access, .. } => Breakpoint::Watch { range, access, condition: Some(condition) },
+            breakpoint => breakpoint,
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Execute { bank: Some(bank), address } => write!(f, "execute {:02X}:{:04X}", bank, address),
+            Breakpoint::Execute { bank: None, address } => write!(f, "execute {:04X}", address),
+            Breakpoint::Watch { range, access, condition } => {
+                write!(f, "{} ", access)?;
+                match adr::io_register_name(*range.start()) {
+                    Some(name) if range.start() == range.end() => write!(f, "{}", name)?,
+                    _ if range.start() == range.end() => write!(f, "{:04X}", range.start())?,
+                    _ => write!(f, "{:04X}-{:04X}", range.start(), range.end())?,
+                }
+                match condition {
+                    Some(condition) => write!(f, " {}", condition),
+                    None => Ok(()),
+                }
+            }
+            Breakpoint::Interrupt(Some(interrupt)) => write!(f, "interrupt {:?}", interrupt),
+            Breakpoint::Interrupt(None) => write!(f, "interrupt"),
+        }
+    }
+}
+
+/// Identifies an installed breakpoint
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BreakpointId(u32);
+
+impl BreakpointId {
+    /// The number the breakpoint was added as, starting at 1
+    pub fn number(&self) -> u32 { self.0 }
+}
+
+/// What happened
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hit {
+    /// The instruction at PC is next
+    Execute,
+    /// `access` is either `Read` or `Write`
+    Access { access: Access, address: u16, value: u8 },
+    Interrupt(Interrupt),
+}
+
+/// A breakpoint was hit and the emulation paused
+#[derive(Copy, Clone, Debug)]
+pub struct BreakpointHit {
+    id: BreakpointId,
+    hit: Hit,
+    pc: u16,
+}
+
+impl BreakpointHit {
+    pub fn id(&self) -> BreakpointId { self.id }
+    pub fn hit(&self) -> Hit { self.hit }
+    /// The instruction, that hit the breakpoint (the PC pushed for interrupts)
+    pub fn pc(&self) -> u16 { self.pc }
+}
+
+impl Display for BreakpointHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "breakpoint {} at ${:04X}", self.id.0, self.pc)?;
+        match self.hit {
+            Hit::Execute => Ok(()),
+            Hit::Access { access, address, value } => write!(f, ": {} ${:04X} = ${:02X}", access, address, value),
+            Hit::Interrupt(interrupt) => write!(f, ": interrupt {:?}", interrupt),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    id: BreakpointId,
+    breakpoint: Breakpoint,
+    enabled: bool,
+    condition: Option<Expression>,
+    /// How often it was reached, whether the condition held or not
+    hits: u32,
+}
+
+impl Entry {
+    /// It was reached, does it pause?
+    fn reached(&mut self, environment: &Environment, value: u8) -> bool {
+        self.hits = self.hits.saturating_add(1);
+        match &self.condition {
+            Some(condition) => condition.holds(&environment.for_hit(self.hits, value)),
+            None => true,
+        }
+    }
+}
+
+/// The installed breakpoints and watchpoints
+#[derive(Default)]
+pub struct Breakpoints {
+    next_id: u32,
+    entries: Vec<Entry>,
+    /// The first hit since the last clock
+    hit: Option<BreakpointHit>,
+    /// The execute breakpoint at this PC was just hit, its instruction runs next
+    resume_at: Option<u16>,
+    /// The start of the current instruction
+    instruction_pc: u16,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        self.next_id += 1;
+        let id = BreakpointId(self.next_id);
+        self.entries.push(Entry { id, breakpoint, enabled: true, condition: None, hits: 0 });
+        id
+    }
+
+    /// Returns `false`, if there is no such breakpoint
+    pub fn remove(&mut self, id: BreakpointId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    /// Returns `false`, if there is no such breakpoint
+    pub fn set_enabled(&mut self, id: BreakpointId, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Only pause, if the expression holds. Returns `false`, if there is no such breakpoint.
+    pub fn set_condition(&mut self, id: BreakpointId, condition: Option<Expression>) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.condition = condition;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn condition(&self, id: BreakpointId) -> Option<&Expression> {
+        self.entries.iter().find(|entry| entry.id == id).and_then(|entry| entry.condition.as_ref())
+    }
+
+    /// How often the breakpoint was reached, `None` if there is no such breakpoint
+    pub fn hits(&self, id: BreakpointId) -> Option<u32> {
+        self.entries.iter().find(|entry| entry.id == id).map(|entry| entry.hits)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The breakpoints, in the order they were added, and whether they are enabled
+    pub fn iter(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint, bool)> {
+        self.entries.iter().map(|entry| (entry.id, &entry.breakpoint, entry.enabled))
+    }
+
+    /// Count the hit on every enabled breakpoint, that `matches`. The first one, whose
+    /// condition holds, pauses.
+    fn reach(&mut self, environment: &Environment, value: u8, matches: impl Fn(&Breakpoint) -> bool) -> Option<BreakpointId> {
+        let mut id = None;
+        for entry in self.entries.iter_mut().filter(|entry| entry.enabled && matches(&entry.breakpoint)) {
+            if entry.reached(environment, value) && id.is_none() {
+                id = Some(entry.id);
+            }
+        }
+        id
+    }
+
+    fn record(&mut self, id: BreakpointId, hit: Hit, pc: u16) {
+        if self.hit.is_none() {
+            self.hit = Some(BreakpointHit { id, hit, pc });
+        }
+    }
+
+    /// Before an instruction starts. Returns `true`, if it has to wait for the next clock.
+    pub(crate) fn check_execute(&mut self, pc: u16, bank: u16, environment: &Environment) -> bool {
+        self.instruction_pc = pc;
+        if self.resume_at.take() == Some(pc) {
+            return false;
+        }
+        // Only ROM is banked
+        let banked = pc < 0x8000;
+        let id = self.reach(environment, 0, |breakpoint| match *breakpoint {
+            Breakpoint::Execute { bank: b, address } => address == pc && (!banked || b.is_none_or(|b| b == bank)),
+            _ => false,
+        });
+        match id {
+            Some(id) => {
+                self.record(id, Hit::Execute, pc);
+                self.resume_at = Some(pc);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// After a data access, `access` is either `Read` or `Write`
+    pub(crate) fn check_access(&mut self, access: Access, address: u16, value: u8, environment: &Environment) {
+        let id = self.reach(environment, value, |breakpoint| match breakpoint {
+            Breakpoint::Watch { range, access: watched, condition } => {
+                watched.matches(access) && range.contains(&address) && condition.is_none_or(|c| c.holds(value))
+            }
+            _ => false,
+        });
+        if let Some(id) = id {
+            self.record(id, Hit::Access { access, address, value }, self.instruction_pc);
+        }
+    }
+
+    /// When an interrupt is dispatched, with the PC that is pushed
+    pub(crate) fn check_interrupt(&mut self, interrupt: Interrupt, pc: u16, environment: &Environment) {
+        let id = self.reach(environment, 0, |breakpoint| match *breakpoint {
+            Breakpoint::Interrupt(i) => i.is_none_or(|i| i == interrupt),
+            _ => false,
+        });
+        if let Some(id) = id {
+            self.record(id, Hit::Interrupt(interrupt), pc);
+        }
+    }
+
+    /// The same breakpoints and hit counts, for re-executing the past (see
+    /// [`crate::game_boy::timeline`]), without changing these
+    pub(crate) fn copy_for_replay(&self) -> Breakpoints {
+        Breakpoints { next_id: self.next_id, entries: self.entries.clone(), hit: None, resume_at: None, instruction_pc: 0 }
+    }
+
+    /// The execute breakpoint at `pc` doesn't pause again, before its instruction runs
+    pub(crate) fn resume_at(&mut self, pc: u16) {
+        self.resume_at = Some(pc);
+    }
+
+    pub(crate) fn hit(&self) -> Option<BreakpointHit> {
+        self.hit
+    }
+
+    pub(crate) fn take_hit(&mut self) -> Option<BreakpointHit> {
+        self.hit.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::cpu::registers::Registers;
+    use crate::game_boy::memory::MMU;
+
+    fn memory() -> MMU {
+        MMU::load_from_bytes(vec![0u8; 0x8000].into_boxed_slice()).unwrap()
+    }
+
+    /// The breakpoint, that the execute check at `pc` in `bank` hits
+    fn execute(breakpoints: &mut Breakpoints, pc: u16, bank: u16, environment: &Environment) -> Option<BreakpointId> {
+        breakpoints.check_execute(pc, bank, environment).then(|| breakpoints.take_hit().unwrap().id())
+    }
+
+    #[test]
+    fn execute_breakpoints_match_the_bank_of_rom_only() {
+        let mmu = memory();
+        let environment = Environment::new(Registers::default(), &mmu);
+        let mut breakpoints = Breakpoints::new();
+        let any_bank = breakpoints.add(Breakpoint::execute(0x4100));
+        let bank_2 = breakpoints.add(Breakpoint::execute_in(2, 0x4200));
+        let ram = breakpoints.add(Breakpoint::execute_in(3, 0xC000));
+        assert_eq!(execute(&mut breakpoints, 0x4100, 1, &environment), Some(any_bank));
+        assert_eq!(execute(&mut breakpoints, 0x4200, 1, &environment), None);
+        assert_eq!(execute(&mut breakpoints, 0x4200, 2, &environment), Some(bank_2));
+        assert_eq!(execute(&mut breakpoints, 0xC000, 0, &environment), Some(ram));
+        assert_eq!(execute(&mut breakpoints, 0x4101, 1, &environment), None);
+    }
+
+    #[test]
+    fn resuming_runs_the_instruction_once() {
+        let mmu = memory();
+        let environment = Environment::new(Registers::default(), &mmu);
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Breakpoint::execute(0x0150));
+        assert_eq!(execute(&mut breakpoints, 0x0150, 0, &environment), Some(id));
+        assert_eq!(execute(&mut breakpoints, 0x0150, 0, &environment), None);
+        assert_eq!(execute(&mut breakpoints, 0x0150, 0, &environment), Some(id));
+        assert_eq!(breakpoints.hits(id), Some(2));
+    }
+
+    #[test]
+    fn watchpoints_match_the_range_and_the_access() {
+        let mmu = memory();
+        let environment = Environment::new(Registers::default(), &mmu);
+        let mut breakpoints = Breakpoints::new();
+        let writes = breakpoints.add(Breakpoint::watch(0xC000..=0xC00F, Access::Write));
+        let reads = breakpoints.add(Breakpoint::watch(0xD000..=0xD000, Access::Read));
+        let both = breakpoints.add(Breakpoint::watch(0xE000..=0xE000, Access::ReadWrite));
+        let mut access = |access: Access, address: u16| {
+            breakpoints.check_access(access, address, 0x42, &environment);
+            breakpoints.take_hit().map(|hit| (hit.id(), hit.hit()))
+        };
+        assert_eq!(access(Access::Write, 0xC010), None);
+        assert_eq!(access(Access::Read, 0xC005), None);
+        let hit = Hit::Access { access: Access::Write, address: 0xC00F, value: 0x42 };
+        assert_eq!(access(Access::Write, 0xC00F), Some((writes, hit)));
+        assert_eq!(access(Access::Write, 0xD000), None);
+        assert_eq!(access(Access::Read, 0xD000).map(|(id, _)| id), Some(reads));
+        assert_eq!(access(Access::Read, 0xE000).map(|(id, _)| id), Some(both));
+        assert_eq!(access(Access::Write, 0xE000).map(|(id, _)| id), Some(both));
+    }
+
+    #[test]
+    fn value_conditions() {
+        let holds = |condition: ValueCondition| [0x10, 0x80, 0x81].map(|value| condition.holds(value));
+        assert_eq!(holds(ValueCondition::Equal(0x80)), [false, true, false]);
+        assert_eq!(holds(ValueCondition::NotEqual(0x80)), [true, false, true]);
+        assert_eq!(holds(ValueCondition::Less(0x80)), [true, false, false]);
+        assert_eq!(holds(ValueCondition::Greater(0x80)), [false, false, true]);
+        assert_eq!(holds(ValueCondition::Mask { mask: 0x81, value: 0x80 }), [false, true, false]);
+
+        let mmu = memory();
+        let environment = Environment::new(Registers::default(), &mmu);
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Breakpoint::watch(0xC000..=0xC000, Access::Write).when(ValueCondition::Greater(0x7F)));
+        breakpoints.check_access(Access::Write, 0xC000, 0x7F, &environment);
+        assert!(breakpoints.take_hit().is_none());
+        breakpoints.check_access(Access::Write, 0xC000, 0x80, &environment);
+        assert_eq!(breakpoints.take_hit().map(|hit| hit.id()), Some(id));
+    }
+
+    #[test]
+    fn io_registers_are_watched_by_name() {
+        let lcdc = Breakpoint::io_register("rLCDC", Access::Write).unwrap();
+        assert_eq!(lcdc, Breakpoint::watch(0xFF40..=0xFF40, Access::Write));
+        assert_eq!(Breakpoint::io_register("lcdc", Access::Write), Some(lcdc.clone()));
+        assert_eq!(Breakpoint::io_register("rFOO", Access::Write), None);
+        assert_eq!(lcdc.when(ValueCondition::Equal(0x91)).to_string(), "write rLCDC == $91");
+    }
+
+    #[test]
+    fn disabled_breakpoints_are_not_reached() {
+        let mmu = memory();
+        let environment = Environment::new(Registers::default(), &mmu);
+        let mut breakpoints = Breakpoints::new();
+        let id = breakpoints.add(Breakpoint::execute(0x0150));
+        assert!(breakpoints.set_enabled(id, false));
+        assert_eq!(execute(&mut breakpoints, 0x0150, 0, &environment), None);
+        assert_eq!(breakpoints.hits(id), Some(0));
+        breakpoints.set_enabled(id, true);
+        assert_eq!(execute(&mut breakpoints, 0x0150, 0, &environment), Some(id));
+    }
+
+    #[test]
+    fn hits_are_counted_whether_the_condition_holds_or_not() {
+        let mmu = memory();
+        let environment = Environment::new(Registers::default(), &mmu);
+        let mut breakpoints = Breakpoints::new();
+        let every_third = breakpoints.add(Breakpoint::interrupt(Some(Interrupt::VBlank)));
+        breakpoints.set_condition(every_third, Some(Expression::parse("hits % 3 == 0").unwrap()));
+        let any = breakpoints.add(Breakpoint::interrupt(None));
+        let mut paused = Vec::new();
+        for interrupt in [Interrupt::VBlank, Interrupt::TimerOverflow, Interrupt::VBlank, Interrupt::VBlank] {
+            breakpoints.check_interrupt(interrupt, 0x0150, &environment);
+            paused.push(breakpoints.take_hit().map(|hit| (hit.id(), hit.hit())));
+        }
+        // The first breakpoint, that pauses, is reported
+        assert_eq!(
+            paused,
+            [
+                Some((any, Hit::Interrupt(Interrupt::VBlank))),
+                Some((any, Hit::Interrupt(Interrupt::TimerOverflow))),
+                Some((any, Hit::Interrupt(Interrupt::VBlank))),
+                Some((every_third, Hit::Interrupt(Interrupt::VBlank))),
+            ]
+        );
+        assert_eq!(breakpoints.hits(every_third), Some(3));
+        assert_eq!(breakpoints.hits(any), Some(4));
+    }
+}
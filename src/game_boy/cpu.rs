@@ -1,14 +1,41 @@
 pub mod callmap;
+pub mod callstack;
 mod instructions;
+mod flags;
 pub use callmap::*;
 pub mod interrupts;
 pub mod time;
 pub mod debug;
-
-use super::memory::MMU;
+pub mod registers;
+pub mod opcodes;
+pub mod disassembler;
+pub mod assembler;
+pub mod blocks;
+pub mod profiler;
+pub mod cdl;
+pub mod history;
+mod mcycle;
+
+use std::fmt::{Display, Formatter};
+use super::memory::{MemRegion, MemorySnapshot, MMU};
+use debug::DebugStackInfo;
+use super::memory::joypad::Button;
+use super::interrupt::Interrupt;
+use opcodes::{Opcode, CB_OPCODES, OPCODES};
+use mcycle::{Bus, InFlight};
+use super::hooks::{Hooks, MemoryEvent};
+use super::breakpoints::{Access, BreakpointHit, Breakpoints};
+use super::expression::Environment;
+use flags::LazyFlags;
+use callstack::CallStack;
+use profiler::Profile;
+use cdl::CodeDataLog;
+use history::History;
 
 pub struct Cpu {
     registers: [u8; 8],
+    /// The flags of the last ALU operation, that aren't stored in F yet
+    lazy_flags: Option<LazyFlags>,
     // a_reg: u8, // Accumulator
     // flag_reg: u8,
     // b_reg: u8,
@@ -22,24 +49,76 @@ pub struct Cpu {
     mmu: MMU,
     /// Also called IME -> Interrupt Master Enable
     interrupts_enabled: bool,
+    /// Set by EI. IME is only enabled after the instruction following EI
+    ime_scheduled: bool,
     halted: bool,
     stopped: bool,
-    clock_counter_divider: u32,
-    clock_counter: u32,
+    /// Set, once the CPU executed an illegal opcode. It never recovers from this.
+    locked_up: Option<LockUp>,
+    /// The instruction (or interrupt dispatch), that didn't finish yet
+    in_flight: Option<InFlight>,
+    bus: Bus,
+    /// `None` until hooks are installed, so the emulation only checks for that
+    hooks: Option<Box<Hooks>>,
+    /// `None` until breakpoints are installed, like `hooks`
+    breakpoints: Option<Box<Breakpoints>>,
+    call_stack: CallStack,
+    /// `None` unless profiling, like `hooks`
+    profile: Option<Box<Profile>>,
+    /// `None` unless logging code and data, like `hooks`
+    code_data_log: Option<Box<CodeDataLog>>,
+    history: History,
+}
+
+/// The hooks, breakpoints, profile and code/data log, see [`Cpu::swap_tools`]
+#[derive(Default)]
+pub(crate) struct Tools {
+    hooks: Option<Box<Hooks>>,
+    pub(crate) breakpoints: Option<Box<Breakpoints>>,
+    profile: Option<Box<Profile>>,
+    code_data_log: Option<Box<CodeDataLog>>,
+}
+
+/// See [`Cpu::snapshot`]
+#[derive(Clone)]
+pub struct CpuSnapshot {
+    registers: [u8; 8],
+    lazy_flags: Option<LazyFlags>,
+    pc: u16,
+    sp: u16,
+    memory: MemorySnapshot,
+    interrupts_enabled: bool,
+    ime_scheduled: bool,
+    halted: bool,
+    stopped: bool,
+    locked_up: Option<LockUp>,
+    in_flight: Option<InFlight>,
+    bus: Bus,
+    call_stack: CallStack,
+    history: History,
 }
 
 impl Cpu {
     pub fn new(mmu: MMU) -> Cpu {
         Cpu {
             registers: [0u8; 8],
+            lazy_flags: None,
             pc: 0x0, // 0x0100,
             sp: 0xFFFE,
             mmu,
             interrupts_enabled: false,
+            ime_scheduled: false,
             halted: false,
             stopped: false,
-            clock_counter_divider: 0,
-            clock_counter: 0,
+            locked_up: None,
+            in_flight: None,
+            bus: Bus::default(),
+            hooks: None,
+            breakpoints: None,
+            call_stack: CallStack::default(),
+            profile: None,
+            code_data_log: None,
+            history: History::default(),
         }
     }
 
@@ -50,6 +129,107 @@ impl Cpu {
     pub fn memory_mut(&mut self) -> &mut MMU {
         &mut self.mmu
     }
+
+    /// The installed hooks, they are installed on first use
+    pub fn hooks(&mut self) -> &mut Hooks {
+        self.hooks.get_or_insert_with(Default::default)
+    }
+
+    /// The hooks, if any are installed
+    pub fn installed_hooks(&mut self) -> Option<&mut Hooks> {
+        self.hooks.as_deref_mut()
+    }
+
+    /// Remove all hooks, the emulation runs at full speed again
+    pub fn remove_hooks(&mut self) {
+        self.hooks = None;
+    }
+
+    /// The installed breakpoints, they are installed on first use
+    pub fn breakpoints(&mut self) -> &mut Breakpoints {
+        self.breakpoints.get_or_insert_with(Default::default)
+    }
+
+    /// The breakpoints, if any are installed
+    pub fn installed_breakpoints(&self) -> Option<&Breakpoints> {
+        self.breakpoints.as_deref()
+    }
+
+    /// Remove all breakpoints, the emulation runs at full speed again
+    pub fn remove_breakpoints(&mut self) {
+        self.breakpoints = None;
+    }
+
+    /// Was a breakpoint hit since the last [`Cpu::take_breakpoint_hit`]?
+    pub fn breakpoint_hit(&self) -> Option<BreakpointHit> {
+        self.breakpoints.as_ref().and_then(|breakpoints| breakpoints.hit())
+    }
+
+    pub fn take_breakpoint_hit(&mut self) -> Option<BreakpointHit> {
+        self.breakpoints.as_mut().and_then(|breakpoints| breakpoints.take_hit())
+    }
+
+    /// Swap the debugging tools with the given ones, e.g. with none while the past is
+    /// re-executed
+    pub(crate) fn swap_tools(&mut self, tools: &mut Tools) {
+        std::mem::swap(&mut self.hooks, &mut tools.hooks);
+        std::mem::swap(&mut self.breakpoints, &mut tools.breakpoints);
+        std::mem::swap(&mut self.profile, &mut tools.profile);
+        std::mem::swap(&mut self.code_data_log, &mut tools.code_data_log);
+    }
+
+    /// The state of the CPU and the memory, without the debugging tools
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            registers: self.registers,
+            lazy_flags: self.lazy_flags,
+            pc: self.pc,
+            sp: self.sp,
+            memory: self.mmu.snapshot(),
+            interrupts_enabled: self.interrupts_enabled,
+            ime_scheduled: self.ime_scheduled,
+            halted: self.halted,
+            stopped: self.stopped,
+            locked_up: self.locked_up,
+            in_flight: self.in_flight,
+            bus: self.bus.clone(),
+            call_stack: self.call_stack.clone(),
+            history: self.history.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &CpuSnapshot) {
+        self.registers = snapshot.registers;
+        self.lazy_flags = snapshot.lazy_flags;
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.mmu.restore(&snapshot.memory);
+        self.interrupts_enabled = snapshot.interrupts_enabled;
+        self.ime_scheduled = snapshot.ime_scheduled;
+        self.halted = snapshot.halted;
+        self.stopped = snapshot.stopped;
+        self.locked_up = snapshot.locked_up;
+        self.in_flight = snapshot.in_flight;
+        self.bus = snapshot.bus.clone();
+        self.call_stack = snapshot.call_stack.clone();
+        self.history = snapshot.history.clone();
+    }
+
+    /// Check the execute breakpoints, before the instruction at PC starts. Returns `true`, if
+    /// the CPU has to pause before it.
+    pub(super) fn break_before_instruction(&mut self) -> bool {
+        let (pc, bank) = (self.pc, self.rom_bank_of(self.pc));
+        self.with_breakpoints(|breakpoints, environment| breakpoints.check_execute(pc, bank, environment))
+            .unwrap_or(false)
+    }
+
+    /// Call `check` with the breakpoints and the state they see, if any are installed
+    pub(super) fn with_breakpoints<T>(&mut self, check: impl FnOnce(&mut Breakpoints, &Environment) -> T) -> Option<T> {
+        let mut breakpoints = self.breakpoints.take()?;
+        let result = check(&mut breakpoints, &Environment::new(self.registers(), &self.mmu));
+        self.breakpoints = Some(breakpoints);
+        Some(result)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -100,6 +280,79 @@ impl Register16 {
     }
 }
 
+/// The CPU hit one of the illegal opcodes and locked up, like the real hardware does.
+/// Only the CPU stops, PPU and timers keep running.
+#[derive(Copy, Clone, Debug)]
+pub struct LockUp {
+    pc: u16,
+    opcode: u8,
+}
+
+impl LockUp {
+    /// The address of the illegal opcode
+    pub fn pc(&self) -> u16 { self.pc }
+    pub fn opcode(&self) -> u8 { self.opcode }
+}
+
+impl Display for LockUp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "illegal opcode ${:02X} at ${:04X}", self.opcode, self.pc)
+    }
+}
+
+/// What went wrong inside an instruction
+#[derive(Debug)]
+pub enum Fault {
+    /// PC was incremented past 0xFFFF
+    PcOverflow,
+}
+
+type FaultResult<T> = Result<T, Fault>;
+
+/// An emulation fault, together with the state of the CPU at the start of the faulting
+/// instruction, so the caller can report it instead of crashing. Only PC is reset to that
+/// instruction: the registers, SP and the memory writes it changed before the fault stay
+/// changed. The state is partially executed and the emulation can't be resumed reliably.
+#[derive(Debug)]
+pub struct CpuError {
+    fault: Fault,
+    pc: u16,
+    opcode: u8,
+    registers: DebugStackInfo,
+}
+
+impl CpuError {
+    pub fn fault(&self) -> &Fault { &self.fault }
+    /// The address of the faulting instruction
+    pub fn pc(&self) -> u16 { self.pc }
+    pub fn opcode(&self) -> u8 { self.opcode }
+    /// The registers at the start of the faulting instruction
+    pub fn registers(&self) -> DebugStackInfo { self.registers }
+
+    /// The memory region the fault happened in
+    pub fn region(&self) -> MemRegion {
+        match self.fault {
+            Fault::PcOverflow => MemRegion::get_region(self.pc),
+        }
+    }
+}
+
+impl Display for CpuError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.fault {
+            Fault::PcOverflow => write!(f, "PC overflow")?,
+        }
+        write!(
+            f,
+            " in {:?} (opcode ${:02X} at ${:04X}, {})",
+            self.region(),
+            self.opcode,
+            self.pc,
+            self.registers
+        )
+    }
+}
+
 pub enum Condition {
     ZSet,
     ZNotSet,
@@ -122,10 +375,16 @@ pub enum ResetVec {
 
 impl Cpu {
     fn reg(&self, register: Register8) -> u8 {
-        self.registers[register.idx()]
+        match register {
+            Register8::F => self.f_reg(),
+            _ => self.registers[register.idx()],
+        }
     }
 
     fn reg_mut(&mut self, register: Register8) -> &mut u8 {
+        if let Register8::F = register {
+            self.evaluate_flags();
+        }
         &mut self.registers[register.idx()]
     }
 
@@ -138,7 +397,8 @@ impl Cpu {
     }
 
     fn f_reg(&self) -> u8 {
-        self.reg(Register8::F)
+        let f = self.registers[Register8::F.idx()];
+        self.lazy_flags.map_or(f, |flags| flags.apply(f))
     }
 
     fn f_reg_mut(&mut self) -> &mut u8 {
@@ -277,7 +537,35 @@ impl Cpu {
     }
 
     fn is_running(&self) -> bool {
-        !self.halted && !self.stopped
+        !self.halted && !self.stopped && self.locked_up.is_none()
+    }
+
+    /// Is the CPU only waiting for something to happen (HALT without a pending interrupt, STOP
+    /// or a lock-up)? Until then, cycles can be skipped.
+    pub fn is_waiting(&self) -> bool {
+        self.in_flight.is_none()
+            && (self.stopped
+                || self.locked_up.is_some()
+                || (self.halted && self.pending_interrupt().is_none()))
+    }
+
+    /// The lock-up, if the CPU executed an illegal opcode
+    pub fn locked_up(&self) -> Option<LockUp> {
+        self.locked_up
+    }
+
+    /// Is the CPU in STOP mode?
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Update the state of a button. Pressing a button on a selected P1 line requests the
+    /// joypad interrupt and ends STOP mode.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.mmu.set_button(button, pressed) {
+            self.request_interrupt(Interrupt::Input);
+            self.stopped = false;
+        }
     }
 }
 
@@ -286,26 +574,48 @@ impl Cpu {
         self.mmu.read_8(self.pc)
     }
 
-    fn read_u8(&mut self) -> u8 {
+    fn read_u8(&mut self) -> FaultResult<u8> {
         let ret = self.peek_u8();
-        let (res, carry) = self.pc.overflowing_add(1);
-        if carry {
-            self.mmu.dump_ram();
-            panic!("PC overflow");
-        }
-        self.pc = res;
-        ret
+        self.log_code(self.pc, 1, false);
+        self.pc = self.pc.checked_add(1).ok_or(Fault::PcOverflow)?;
+        Ok(ret)
     }
 
-    fn read_u16(&mut self) -> u16 {
-        // TODO don't unwrap
-        let ret = self.mmu.read_16(self.pc).unwrap();
-        self.pc += 2;
-        ret
+    /// Write to memory. Inside instruction functions the write is done in its own cycle later.
+    fn write_8(&mut self, address: u16, val: u8) {
+        if self.buffer_write(address, val) {
+            return;
+        }
+        self.mmu.write_8(address, val);
+        if let Some(hooks) = &mut self.hooks {
+            hooks.write(&MemoryEvent::new(address, val));
+        }
+        self.log_write(address, val);
+        self.with_breakpoints(|breakpoints, environment| breakpoints.check_access(Access::Write, address, val, environment));
     }
+}
 
-    fn read_i8(&mut self) -> i8 {
-        // TODO is the i8 read correctly?
-        i8::from_le_bytes([self.read_u8()])
+#[cfg(test)]
+impl Cpu {
+    /// A CPU after the boot ROM, about to run `program` at $0100 of a cartridge without MBC
+    pub(super) fn with_program(program: &[u8]) -> Cpu {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+        let mut mmu = MMU::load_from_bytes(rom.into_boxed_slice()).unwrap();
+        mmu.raw_write_8(super::memory::addresses::memory::BOOT_ROM_ENABLED, 1).unwrap();
+        let mut cpu = Cpu::new(mmu);
+        cpu.pc = 0x100;
+        cpu
+    }
+
+    /// Run the current instruction (or interrupt dispatch) to its end. Returns its M-cycles.
+    pub(super) fn step(&mut self) -> u32 {
+        let mut cycles = 1;
+        self.cycle().unwrap();
+        while self.in_flight.is_some() {
+            self.cycle().unwrap();
+            cycles += 1;
+        }
+        cycles
     }
 }
@@ -0,0 +1,685 @@
+//! A small SM83 assembler, that understands the RGBDS syntax (https://rgbds.gbdev.io/docs/v0.5.0/rgbasm.5)
+//! far enough to assemble single instructions typed into a debugger and the output of the
+//! disassembler.
+//!
+//! Supported are instructions, global and local (`.name`) labels, `DEF name EQU value`,
+//! `db`/`dw`/`ds` and `SECTION` with a fixed address. The I/O registers are predefined with
+//! their hardware.inc names. Expressions know numbers (`$FF`, `%1010`, `&17`, `42`), symbols,
+//! `@` and the usual C operators.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use super::opcodes::{Opcode, Operand, CB_OPCODES, OPCODES, PREFIX};
+use crate::game_boy::memory::addresses as adr;
+
+#[derive(Debug)]
+pub enum AsmErrorKind {
+    Syntax(String),
+    UnknownInstruction(String),
+    InvalidOperands(String),
+    UndefinedSymbol(String),
+    DuplicateSymbol(String),
+    ValueOutOfRange(i64),
+    JumpOutOfRange(i64),
+}
+
+/// An error in a line of the source
+#[derive(Debug)]
+pub struct AsmError {
+    /// 1-based
+    line: usize,
+    kind: AsmErrorKind,
+}
+
+impl AsmError {
+    pub fn line(&self) -> usize { self.line }
+    pub fn kind(&self) -> &AsmErrorKind { &self.kind }
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::Syntax(s) => write!(f, "syntax error: {}", s),
+            AsmErrorKind::UnknownInstruction(s) => write!(f, "unknown instruction {}", s),
+            AsmErrorKind::InvalidOperands(s) => write!(f, "invalid operands for {}", s),
+            AsmErrorKind::UndefinedSymbol(s) => write!(f, "undefined symbol {}", s),
+            AsmErrorKind::DuplicateSymbol(s) => write!(f, "symbol {} is defined twice", s),
+            AsmErrorKind::ValueOutOfRange(v) => write!(f, "value {} is out of range", v),
+            AsmErrorKind::JumpOutOfRange(v) => write!(f, "jump offset {} is out of range", v),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, AsmErrorKind>;
+
+/// A continuous block of assembled bytes
+#[derive(Debug)]
+pub struct Chunk {
+    /// Set for ROMX sections
+    bank: Option<u16>,
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn bank(&self) -> Option<u16> { self.bank }
+    pub fn address(&self) -> u16 { self.address }
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+}
+
+/// Assemble the source, that is placed at `origin` (until a `SECTION` says otherwise)
+pub fn assemble(source: &str, origin: u16) -> std::result::Result<Vec<Chunk>, AsmError> {
+    let mut assembler = Assembler::new(origin);
+    // The first pass only finds the addresses of the labels
+    assembler.pass(source, false)?;
+    assembler.pass(source, true)?;
+    Ok(assembler.chunks)
+}
+
+/// Assemble source without sections into the bytes to place at `origin`
+pub fn assemble_bytes(source: &str, origin: u16) -> std::result::Result<Vec<u8>, AsmError> {
+    Ok(assemble(source, origin)?
+        .into_iter()
+        .flat_map(|chunk| chunk.bytes)
+        .collect())
+}
+
+struct Assembler {
+    origin: u16,
+    symbols: HashMap<String, i64>,
+    /// The last global label, for local labels
+    scope: String,
+    address: u16,
+    chunks: Vec<Chunk>,
+    emit: bool,
+}
+
+impl Assembler {
+    fn new(origin: u16) -> Assembler {
+        Assembler {
+            origin,
+            symbols: HashMap::new(),
+            scope: String::new(),
+            address: origin,
+            chunks: Vec::new(),
+            emit: false,
+        }
+    }
+
+    fn pass(&mut self, source: &str, emit: bool) -> std::result::Result<(), AsmError> {
+        self.emit = emit;
+        self.address = self.origin;
+        self.scope.clear();
+        self.chunks = vec![Chunk { bank: None, address: self.origin, bytes: Vec::new() }];
+        for (i, line) in source.lines().enumerate() {
+            self.line(line).map_err(|kind| AsmError { line: i + 1, kind })?;
+        }
+        self.chunks.retain(|chunk| !chunk.bytes.is_empty());
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str) -> Result<()> {
+        let mut line = strip_comment(line).trim();
+        // Labels
+        if let Some(colon) = label_end(line) {
+            let name = line[..colon].trim_end_matches(':').trim();
+            let name = self.qualify(name);
+            if !name.starts_with('.') && !name.contains('.') {
+                self.scope = name.clone();
+            }
+            self.define(name, self.address as i64)?;
+            line = line[colon + 1..].trim_start_matches(':').trim();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (keyword, rest) = split_keyword(line);
+        let upper = keyword.to_ascii_uppercase();
+        match upper.as_str() {
+            "DEF" => {
+                let (name, rest) = split_keyword(rest);
+                let (equ, value) = split_keyword(rest);
+                if !equ.eq_ignore_ascii_case("EQU") {
+                    return Err(AsmErrorKind::Syntax(line.to_owned()));
+                }
+                let value = self.eval_now(&parse_expression(value)?)?;
+                self.define(name.to_owned(), value)
+            }
+            "SECTION" => self.section(rest),
+            "DB" => {
+                for arg in split_operands(rest) {
+                    if let Some(string) = arg.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                        self.push(string.as_bytes());
+                    } else {
+                        let value = self.eval(&parse_expression(&arg)?)?;
+                        self.push(&[check_range(value, -128, 0xFF)? as u8]);
+                    }
+                }
+                Ok(())
+            }
+            "DW" => {
+                for arg in split_operands(rest) {
+                    let value = self.eval(&parse_expression(&arg)?)?;
+                    self.push(&(check_range(value, -0x8000, 0xFFFF)? as u16).to_le_bytes());
+                }
+                Ok(())
+            }
+            "DS" => {
+                let args = split_operands(rest);
+                let count = self.eval_now(&parse_expression(args.get(0).map_or("", |s| s.as_str()))?)?;
+                let fill = match args.get(1) {
+                    Some(fill) => check_range(self.eval(&parse_expression(fill)?)?, -128, 0xFF)? as u8,
+                    None => 0,
+                };
+                self.push(&vec![fill; check_range(count, 0, 0xFFFF)? as usize]);
+                Ok(())
+            }
+            _ => {
+                // Old style `name EQU value`
+                let (equ, value) = split_keyword(rest);
+                if equ.eq_ignore_ascii_case("EQU") {
+                    let value = self.eval_now(&parse_expression(value)?)?;
+                    return self.define(keyword.to_owned(), value);
+                }
+                let bytes = self.instruction(&upper, rest)?;
+                self.push(&bytes);
+                Ok(())
+            }
+        }
+    }
+
+    /// `SECTION "name", ROM0[$0000]` or `SECTION "name", ROMX[$4000], BANK[1]`.
+    /// Only sections with a fixed address are supported.
+    fn section(&mut self, rest: &str) -> Result<()> {
+        let args = split_operands(rest);
+        let kind = args.get(1).ok_or_else(|| AsmErrorKind::Syntax(rest.to_owned()))?;
+        let address = bracketed(kind).ok_or_else(|| AsmErrorKind::Syntax(rest.to_owned()))?;
+        let address = check_range(self.eval_now(&parse_expression(address)?)?, 0, 0xFFFF)? as u16;
+        let bank = match args.get(2).and_then(|b| bracketed(b)) {
+            Some(bank) => Some(check_range(self.eval_now(&parse_expression(bank)?)?, 0, 0x1FF)? as u16),
+            None => None,
+        };
+        self.address = address;
+        self.chunks.push(Chunk { bank, address, bytes: Vec::new() });
+        Ok(())
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.address = self.address.wrapping_add(bytes.len() as u16);
+        if self.emit {
+            self.chunks.last_mut().unwrap().bytes.extend_from_slice(bytes);
+        }
+    }
+
+    fn qualify(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            format!("{}{}", self.scope, name)
+        } else {
+            name.to_owned()
+        }
+    }
+
+    fn define(&mut self, name: String, value: i64) -> Result<()> {
+        // Symbols are defined again in the second pass
+        if !self.emit && self.symbols.insert(name.clone(), value).is_some() {
+            return Err(AsmErrorKind::DuplicateSymbol(name));
+        }
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    /// Evaluate an expression, undefined symbols are only an error in the second pass
+    fn eval(&self, expression: &Expression) -> Result<i64> {
+        match self.eval_now(expression) {
+            Err(AsmErrorKind::UndefinedSymbol(_)) if !self.emit => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Evaluate an expression, that has to be known right now (constants, sections)
+    fn eval_now(&self, expression: &Expression) -> Result<i64> {
+        Ok(match expression {
+            Expression::Number(n) => *n,
+            Expression::Current => self.address as i64,
+            Expression::Symbol(name) => {
+                let name = self.qualify(name);
+                // The I/O registers are known like in hardware.inc, unless they are redefined
+                let io_register = adr::IO_REGISTER_NAMES.iter().find(|(_, n)| *n == name);
+                match (self.symbols.get(&name), io_register) {
+                    (Some(value), _) => *value,
+                    (None, Some((address, _))) => *address as i64,
+                    (None, None) => return Err(AsmErrorKind::UndefinedSymbol(name)),
+                }
+            }
+            Expression::Unary(op, e) => {
+                let e = self.eval_now(e)?;
+                match op {
+                    '-' => e.checked_neg().ok_or(AsmErrorKind::ValueOutOfRange(e))?,
+                    '~' => !e,
+                    _ => e,
+                }
+            }
+            Expression::Binary(op, l, r) => {
+                let (l, r) = (self.eval_now(l)?, self.eval_now(r)?);
+                // Overflows and division by zero
+                let out_of_range = || AsmErrorKind::ValueOutOfRange(if r == 0 { r } else { l });
+                match *op {
+                    "+" => l.checked_add(r).ok_or_else(out_of_range)?,
+                    "-" => l.checked_sub(r).ok_or_else(out_of_range)?,
+                    "*" => l.checked_mul(r).ok_or_else(out_of_range)?,
+                    "/" => l.checked_div(r).ok_or_else(out_of_range)?,
+                    "%" => l.checked_rem(r).ok_or_else(out_of_range)?,
+                    "&" => l & r,
+                    "|" => l | r,
+                    "^" => l ^ r,
+                    "<<" => l << (r & 63),
+                    ">>" => l >> (r & 63),
+                    _ => unreachable!(),
+                }
+            }
+        })
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &str) -> Result<Vec<u8>> {
+        let mut args = split_operands(operands)
+            .iter()
+            .map(|o| parse_operand(o))
+            .collect::<Result<Vec<Arg>>>()?;
+        let mut mnemonic = mnemonic;
+        // `LD [C], A` is an alias for `LDH [C], A`
+        if mnemonic == "LD" && args.iter().any(|a| matches!(a, Arg::Indirect(r) if r == "C")) {
+            mnemonic = "LDH";
+        }
+        // `SUB B` is short for `SUB A, B`
+        if args.len() == 1 && ["ADC", "ADD", "SUB", "SBC", "AND", "XOR", "OR", "CP"].contains(&mnemonic) {
+            args.insert(0, Arg::Name("A".to_owned()));
+        }
+        let known = OPCODES.iter().chain(CB_OPCODES.iter()).any(|o| o.mnemonic() == mnemonic);
+        if !known || mnemonic == "PREFIX" || mnemonic == "ILLEGAL" {
+            return Err(AsmErrorKind::UnknownInstruction(mnemonic.to_owned()));
+        }
+        for (prefixed, table) in [(false, &OPCODES), (true, &CB_OPCODES)] {
+            for (code, opcode) in table.iter().enumerate() {
+                if opcode.mnemonic() != mnemonic || opcode.operands().len() != args.len() {
+                    continue;
+                }
+                if let Some(bytes) = self.encode(opcode, code as u8, prefixed, &args)? {
+                    return Ok(bytes);
+                }
+            }
+        }
+        Err(AsmErrorKind::InvalidOperands(mnemonic.to_owned()))
+    }
+
+    /// Encode the instruction, if the arguments fit the operands of the opcode
+    fn encode(&self, opcode: &Opcode, code: u8, prefixed: bool, args: &[Arg]) -> Result<Option<Vec<u8>>> {
+        let mut bytes = if prefixed { vec![PREFIX, code] } else { vec![code] };
+        for (operand, arg) in opcode.operands().iter().zip(args) {
+            match (operand, arg) {
+                (Operand::Imm8, Arg::Expression(e)) => {
+                    bytes.push(check_range(self.eval(e)?, -128, 0xFF)? as u8)
+                }
+                (Operand::Offset8, Arg::Expression(e)) | (Operand::SpOffset, Arg::SpOffset(e)) => {
+                    bytes.push(check_range(self.eval(e)?, -128, 127)? as u8)
+                }
+                (Operand::Relative, Arg::Expression(e)) => {
+                    let offset = self.eval(e)? - (self.address as i64 + opcode.length() as i64);
+                    if self.emit && !(-128..=127).contains(&offset) {
+                        return Err(AsmErrorKind::JumpOutOfRange(offset));
+                    }
+                    bytes.push(offset as u8);
+                }
+                (Operand::Imm16, Arg::Expression(e)) | (Operand::Addr16, Arg::Memory(e)) => {
+                    let value = check_range(self.eval(e)?, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                (Operand::HighAddr8, Arg::Memory(e)) => {
+                    let value = self.eval(e)?;
+                    let value = if value >= 0xFF00 { value - 0xFF00 } else { value };
+                    bytes.push(check_range(value, 0, 0xFF)? as u8);
+                }
+                (Operand::Bit(bit), Arg::Expression(e)) => {
+                    if self.eval_now(e)? != *bit as i64 {
+                        return Ok(None);
+                    }
+                }
+                (Operand::RstVec(vec), Arg::Expression(e)) => {
+                    if self.eval_now(e)? != *vec as i64 {
+                        return Ok(None);
+                    }
+                }
+                (operand, Arg::Name(name)) if register_name(operand) == Some(name.as_str()) => {}
+                (operand, Arg::Indirect(name)) if indirect_name(operand) == Some(name.as_str()) => {}
+                _ => return Ok(None),
+            }
+        }
+        // STOP is followed by a padding byte
+        bytes.resize(opcode.length() as usize, 0);
+        Ok(Some(bytes))
+    }
+}
+
+fn check_range(value: i64, min: i64, max: i64) -> Result<i64> {
+    if value < min || value > max {
+        Err(AsmErrorKind::ValueOutOfRange(value))
+    } else {
+        Ok(value)
+    }
+}
+
+/// The name of register and condition operands
+fn register_name(operand: &Operand) -> Option<&'static str> {
+    Some(match operand {
+        Operand::A => "A",
+        Operand::B => "B",
+        Operand::C | Operand::CondC => "C",
+        Operand::D => "D",
+        Operand::E => "E",
+        Operand::H => "H",
+        Operand::L => "L",
+        Operand::AF => "AF",
+        Operand::BC => "BC",
+        Operand::DE => "DE",
+        Operand::HL => "HL",
+        Operand::SP => "SP",
+        Operand::CondNZ => "NZ",
+        Operand::CondZ => "Z",
+        Operand::CondNC => "NC",
+        _ => return None,
+    })
+}
+
+/// The (normalized) register in the brackets of indirect operands
+fn indirect_name(operand: &Operand) -> Option<&'static str> {
+    Some(match operand {
+        Operand::IndBC => "BC",
+        Operand::IndDE => "DE",
+        Operand::IndHL => "HL",
+        Operand::IndHLInc => "HL+",
+        Operand::IndHLDec => "HL-",
+        Operand::HighC => "C",
+        _ => return None,
+    })
+}
+
+/// A parsed operand
+#[derive(Debug)]
+enum Arg {
+    /// A register or condition, upper case
+    Name(String),
+    /// `[BC]`, `[HL+]`, `[C]`, ... (the normalized register)
+    Indirect(String),
+    /// `[expression]`
+    Memory(Expression),
+    /// `SP + expression`
+    SpOffset(Expression),
+    Expression(Expression),
+}
+
+/// Registers and conditions
+const NAMES: [&str; 15] = ["A", "B", "C", "D", "E", "H", "L", "AF", "BC", "DE", "HL", "SP", "NZ", "Z", "NC"];
+
+fn parse_operand(operand: &str) -> Result<Arg> {
+    let upper: String = operand.to_ascii_uppercase().chars().filter(|c| !c.is_whitespace()).collect();
+    if NAMES.contains(&upper.as_str()) {
+        return Ok(Arg::Name(upper));
+    }
+    if let Some(inner) = bracketed(operand) {
+        let inner_upper: String = inner.to_ascii_uppercase().chars().filter(|c| !c.is_whitespace()).collect();
+        let register = match inner_upper.as_str() {
+            "BC" | "DE" | "HL" | "HL+" | "HL-" | "C" => inner_upper.clone(),
+            "HLI" => "HL+".to_owned(),
+            "HLD" => "HL-".to_owned(),
+            "$FF00+C" => "C".to_owned(),
+            _ => return Ok(Arg::Memory(parse_expression(inner)?)),
+        };
+        return Ok(Arg::Indirect(register));
+    }
+    if upper.starts_with("SP+") || upper.starts_with("SP-") {
+        let offset = operand.trim()[2..].trim();
+        // Keep the sign, `SP-3` is `SP + -3`
+        let offset = offset.strip_prefix('+').unwrap_or(offset);
+        return Ok(Arg::SpOffset(parse_expression(offset)?));
+    }
+    Ok(Arg::Expression(parse_expression(operand)?))
+}
+
+/// The content of `[...]`, if the text is in brackets
+fn bracketed(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let start = text.find('[')?;
+    text.strip_suffix(']').map(|t| &t[start + 1..])
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The end of the label at the start of the line (the position of its colon)
+fn label_end(line: &str) -> Option<usize> {
+    let end = line.find(':')?;
+    let name = &line[..end];
+    let is_name = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if is_name { Some(end) } else { None }
+}
+
+fn split_keyword(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    }
+}
+
+/// Split at the commas, that aren't in brackets, parentheses or strings
+fn split_operands(operands: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    for c in operands.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '[' | '(' if !in_string => depth += 1,
+            ']' | ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                result.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        result.push(current.trim().to_owned());
+    }
+    result
+}
+
+#[derive(Debug)]
+enum Expression {
+    Number(i64),
+    Symbol(String),
+    /// `@`, the address of the current instruction
+    Current,
+    Unary(char, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+}
+
+/// Binary operators by precedence, lowest first
+const BINARY_OPERATORS: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+fn parse_expression(text: &str) -> Result<Expression> {
+    let tokens = tokenize(text)?;
+    let mut position = 0;
+    let expression = parse_binary(&tokens, &mut position, 0)?;
+    if position != tokens.len() {
+        return Err(AsmErrorKind::Syntax(text.to_owned()));
+    }
+    Ok(expression)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Operator(&'static str),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let (radix, skip) = match c {
+            '$' => (16, 1),
+            '%' if i + 1 < chars.len() && (chars[i + 1] == '0' || chars[i + 1] == '1') && tokens_end_with_operator(&tokens) => (2, 1),
+            '&' if i + 1 < chars.len() && chars[i + 1].is_digit(8) && tokens_end_with_operator(&tokens) => (8, 1),
+            '0'..='9' => (10, 0),
+            _ => (0, 0),
+        };
+        if radix != 0 {
+            let start = i + skip;
+            let mut end = start;
+            while end < chars.len() && (chars[end].is_digit(radix) || chars[end] == '_') {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().filter(|c| **c != '_').collect();
+            let value = i64::from_str_radix(&digits, radix).map_err(|_| AsmErrorKind::Syntax(text.to_owned()))?;
+            tokens.push(Token::Number(value));
+            i = end;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Symbol(chars[start..i].iter().collect()));
+        } else if c == '@' {
+            tokens.push(Token::Symbol("@".to_owned()));
+            i += 1;
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let operator = ["<<", ">>"].iter().find(|o| **o == two).copied().or_else(|| {
+                ["+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")"]
+                    .iter()
+                    .find(|o| o.starts_with(c))
+                    .copied()
+            });
+            let operator = operator.ok_or_else(|| AsmErrorKind::Syntax(text.to_owned()))?;
+            i += operator.len();
+            tokens.push(Token::Operator(operator));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Can a number start here? (`%` and `&` are operators after a value)
+fn tokens_end_with_operator(tokens: &[Token]) -> bool {
+    !matches!(tokens.last(), Some(Token::Number(_)) | Some(Token::Symbol(_)) | Some(Token::Operator(")")))
+}
+
+fn parse_binary(tokens: &[Token], position: &mut usize, level: usize) -> Result<Expression> {
+    if level == BINARY_OPERATORS.len() {
+        return parse_unary(tokens, position);
+    }
+    let mut left = parse_binary(tokens, position, level + 1)?;
+    while let Some(Token::Operator(op)) = tokens.get(*position) {
+        if !BINARY_OPERATORS[level].contains(op) {
+            break;
+        }
+        *position += 1;
+        let right = parse_binary(tokens, position, level + 1)?;
+        left = Expression::Binary(op, Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[Token], position: &mut usize) -> Result<Expression> {
+    let token = tokens.get(*position).ok_or_else(|| AsmErrorKind::Syntax("missing value".to_owned()))?;
+    *position += 1;
+    Ok(match token {
+        Token::Number(n) => Expression::Number(*n),
+        Token::Symbol(s) if s == "@" => Expression::Current,
+        Token::Symbol(s) => Expression::Symbol(s.clone()),
+        Token::Operator(op @ ("-" | "~" | "+")) => {
+            Expression::Unary(op.chars().next().unwrap(), Box::new(parse_unary(tokens, position)?))
+        }
+        Token::Operator("(") => {
+            let inner = parse_binary(tokens, position, 0)?;
+            if tokens.get(*position) != Some(&Token::Operator(")")) {
+                return Err(AsmErrorKind::Syntax("missing )".to_owned()));
+            }
+            *position += 1;
+            inner
+        }
+        Token::Operator(op) => return Err(AsmErrorKind::Syntax(format!("unexpected {}", op))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::cpu::disassembler::{Instruction, Location};
+
+    /// Operand bytes, that cover the edges of signed and unsigned values
+    const OPERANDS: [[u8; 2]; 5] = [[0x00, 0x00], [0x40, 0xFF], [0x7F, 0x80], [0x80, 0x12], [0xFE, 0xC0]];
+
+    #[test]
+    fn disassembly_assembles_into_the_same_bytes() {
+        for (prefixed, table) in [(false, &OPCODES), (true, &CB_OPCODES)] {
+            for (code, opcode) in table.iter().enumerate() {
+                // Not instructions, there is nothing to assemble
+                if opcode.is_illegal() || opcode.mnemonic() == "PREFIX" {
+                    continue;
+                }
+                for operand in OPERANDS {
+                    let mut bytes = if prefixed { vec![PREFIX, code as u8] } else { vec![code as u8] };
+                    bytes.extend_from_slice(&operand);
+                    // The padding byte of STOP isn't part of the source
+                    if !opcode.operands().iter().any(Operand::is_immediate) {
+                        bytes[1 + prefixed as usize..].fill(0);
+                    }
+                    bytes.truncate(opcode.length() as usize);
+                    let instruction = Instruction::decode(&bytes, Location::new(None, 0xC000)).unwrap();
+                    let source = instruction.format_with(&|_| None);
+                    let assembled = assemble_bytes(&source, 0xC000).unwrap_or_else(|e| panic!("{}: {}", source, e));
+                    assert_eq!(assembled, bytes, "{}", source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overflows_are_out_of_range() {
+        for source in ["db -(-9223372036854775807 - 1)", "db 9223372036854775807 + 1", "db -9223372036854775807 - 2", "db 9223372036854775807 * 2", "db (-9223372036854775807 - 1) / -1", "db (-9223372036854775807 - 1) % -1", "db 1 / 0", "db 1 % 0"] {
+            let error = assemble_bytes(source, 0).unwrap_err();
+            assert!(matches!(error.kind(), AsmErrorKind::ValueOutOfRange(_)), "{}: {}", source, error);
+        }
+    }
+
+    #[test]
+    fn labels_and_expressions() {
+        let source = "Start:\n    ld a, (3 + 4) * 2\n.loop:\n    dec a\n    jr nz, .loop\n    ldh [rLCDC], a\n    jp Start";
+        assert_eq!(
+            assemble_bytes(source, 0x150).unwrap(),
+            vec![0x3E, 14, 0x3D, 0x20, 0xFD, 0xE0, 0x40, 0xC3, 0x50, 0x01]
+        );
+    }
+}
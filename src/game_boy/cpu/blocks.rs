@@ -0,0 +1,300 @@
+//! A faster way to run instructions, for bulk (headless) runs. Straight-line runs of
+//! instructions are decoded once into blocks, that are cached by bank and address. Their
+//! instructions execute as a whole, without the M-cycle timing of [`Cpu::cycle`], so the PPU and
+//! the timers only catch up between instructions.
+//!
+//! Blocks in ROM are keyed by their bank, so a bank switch never runs stale code. Blocks in RAM
+//! are dropped, when their pages are written. Everything a block can't do (interrupts, HALT,
+//! STOP, illegal opcodes, code in HRAM) falls back to [`Cpu::tick`].
+
+use std::collections::HashMap;
+use super::*;
+
+/// Decoding stops after this many instructions
+const MAX_BLOCK_LENGTH: usize = 32;
+
+/// Instructions, after which the next one (probably) isn't the next in memory
+const BLOCK_ENDS: [&str; 8] = ["JP", "JR", "CALL", "RET", "RETI", "RST", "HALT", "STOP"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Bank {
+    BootRom,
+    Rom(u16),
+    /// Not in ROM, the block is dropped, when its memory is written
+    Ram,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Decoded {
+    pc: u16,
+    /// The address of the next instruction
+    next_pc: u16,
+    code: u8,
+    prefixed: bool,
+    operand: u16,
+    opcode: &'static Opcode,
+}
+
+impl Decoded {
+    /// The bytes of the instruction, padded with zeros
+    fn bytes(&self) -> [u8; 3] {
+        let [low, high] = self.operand.to_le_bytes();
+        if self.prefixed { [0xCB, self.code, 0] } else { [self.code, low, high] }
+    }
+}
+
+struct Block {
+    instructions: Vec<Decoded>,
+    /// The RAM pages, the instructions were decoded from (bit 0 is page 0x80)
+    pages: u128,
+}
+
+/// Decoded blocks by bank and start address
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: HashMap<(Bank, u16), Block>,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache::default()
+    }
+
+    /// Drop all blocks, e.g. after the ROM was patched
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// The number of cached blocks
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Drop the blocks, that were decoded from the written RAM pages
+    fn invalidate(&mut self, written: u128) {
+        self.blocks.retain(|_, block| block.pages & written == 0);
+    }
+}
+
+impl Cpu {
+    /// Run instructions of the block at PC, until the block ends or `budget` M-cycles are used
+    /// up. Returns the M-cycles it took.
+    ///
+    /// Falls back to [`Cpu::tick`] for an unfinished instruction, interrupts, HALT, STOP,
+    /// lock-ups and code, that can't be cached.
+    pub fn run_block(&mut self, cache: &mut BlockCache, budget: u32) -> Result<u32, CpuError> {
+        // Writes since the last block, e.g. by an interrupt dispatch
+        let written = self.mmu.take_written_code();
+        if written != 0 {
+            cache.invalidate(written);
+        }
+        if self.in_flight.is_some() || !self.is_running() || self.interrupt_ready() {
+            return self.tick();
+        }
+        let bank = match self.bank_at(self.pc) {
+            Some(bank) => bank,
+            None => return self.tick(),
+        };
+        let pc = self.pc;
+        let block = &*cache.blocks.entry((bank, pc)).or_insert_with(|| self.decode_block(bank, pc));
+        if block.instructions.is_empty() {
+            return self.tick();
+        }
+
+        let mut cycles = 0;
+        let mut written = 0;
+        for decoded in &block.instructions {
+            // Jumped away, the block was overwritten or an interrupt has to be dispatched
+            if cycles >= budget
+                || self.pc != decoded.pc
+                || written & block.pages != 0
+                || !self.is_running()
+                || self.interrupt_ready()
+                || self.breakpoint_hit().is_some()
+                || self.break_before_instruction()
+            {
+                break;
+            }
+            cycles += self.execute_decoded(decoded);
+            written |= self.mmu.take_written_code();
+        }
+        if written != 0 {
+            cache.invalidate(written);
+        }
+        Ok(cycles)
+    }
+
+    /// Is IME set and an interrupt pending, so it is dispatched before the next instruction?
+    fn interrupt_ready(&self) -> bool {
+        self.interrupts_enabled && self.pending_interrupt().is_some()
+    }
+
+    /// `None` for the IO page, it isn't cached
+    fn bank_at(&self, address: u16) -> Option<Bank> {
+        match address {
+            0x0000..=0x00FF if self.mmu.boot_rom_enabled() => Some(Bank::BootRom),
+            0x0000..=0x7FFF => Some(Bank::Rom(self.mmu.rom().bank_of(address))),
+            0xFF00..=0xFFFF => None,
+            _ => Some(Bank::Ram),
+        }
+    }
+
+    /// Decode instructions until one, that jumps (or stops the CPU). Illegal opcodes and
+    /// instructions, that reach into the next bank, aren't part of the block.
+    fn decode_block(&mut self, bank: Bank, start: u16) -> Block {
+        let mut block = Block { instructions: Vec::new(), pages: 0 };
+        let mut pc = start;
+        while block.instructions.len() < MAX_BLOCK_LENGTH {
+            let mut code = self.mmu.read_8(pc);
+            let prefixed = code == opcodes::PREFIX;
+            let opcode = if prefixed {
+                code = self.mmu.read_8(pc.wrapping_add(1));
+                &CB_OPCODES[code as usize]
+            } else {
+                &OPCODES[code as usize]
+            };
+            let last = pc.wrapping_add(opcode.length() as u16 - 1);
+            if opcode.is_illegal() || last < pc || self.bank_at(last) != Some(bank) {
+                break;
+            }
+            let operand = match opcode.length() {
+                _ if prefixed => 0,
+                2 => self.mmu.read_8(pc + 1) as u16,
+                3 => u16::from_le_bytes([self.mmu.read_8(pc + 1), self.mmu.read_8(pc + 2)]),
+                _ => 0,
+            };
+            if bank == Bank::Ram {
+                self.mmu.watch_code(pc);
+                self.mmu.watch_code(last);
+                block.pages |= MMU::code_page_bit(pc) | MMU::code_page_bit(last);
+            }
+            // Not in the IO page, so this can't overflow
+            let next_pc = last + 1;
+            block.instructions.push(Decoded { pc, next_pc, code, prefixed, operand, opcode });
+            if BLOCK_ENDS.contains(&opcode.mnemonic()) {
+                break;
+            }
+            pc = next_pc;
+        }
+        block
+    }
+
+    /// Execute a whole decoded instruction at once and return the M-cycles it took
+    fn execute_decoded(&mut self, decoded: &Decoded) -> u32 {
+        // EI only takes effect after the instruction following it
+        let enable_ime = self.ime_scheduled;
+        self.hook_execute();
+        self.record_history(Some(decoded.bytes()));
+        self.profile_start();
+        self.log_code(decoded.pc, decoded.next_pc.wrapping_sub(decoded.pc), true);
+        self.pc = decoded.next_pc;
+        let flags = cfg!(debug_assertions).then(|| self.peek_flags());
+        let taken = if decoded.prefixed {
+            self.execute_cb(decoded.code);
+            true
+        } else {
+            self.execute(decoded.code, decoded.operand)
+        };
+        if let Some(before) = flags {
+            self.check_flag_effects(decoded.opcode, before);
+        }
+        self.unwind_frames();
+        // DI right after EI cancels the scheduled enable
+        if enable_ime && self.ime_scheduled {
+            self.interrupts_enabled = true;
+            self.ime_scheduled = false;
+        }
+        let cycles = decoded.opcode.cycles(taken);
+        self.profile_cycles(cycles);
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stores doubled counters through a call, until B is 0, then halts
+    const LOOP: [u8; 23] = [
+        0x31, 0x00, 0xD0, // LD SP,$D000
+        0x21, 0x00, 0xC0, // LD HL,$C000
+        0x06, 0x10,       // LD B,$10
+        0x78,             // loop: LD A,B
+        0x87,             // ADD A,A
+        0x22,             // LD [HL+],A
+        0xCD, 0x13, 0x01, // CALL $0113
+        0x05,             // DEC B
+        0x20, 0xF7,       // JR NZ,loop
+        0x76,             // HALT
+        0x00,             // NOP
+        0xCB, 0x11,       // $0113: RL C
+        0x13,             // INC DE
+        0xC9,             // RET
+    ];
+
+    #[test]
+    fn blocks_run_like_single_instructions() {
+        let mut single = Cpu::with_program(&LOOP);
+        let mut single_cycles = 0;
+        while single.is_running() {
+            single_cycles += single.step();
+        }
+
+        let mut blocks = Cpu::with_program(&LOOP);
+        let mut cache = BlockCache::new();
+        let mut block_cycles = 0;
+        while blocks.is_running() {
+            block_cycles += blocks.run_block(&mut cache, 1000).unwrap();
+        }
+
+        assert!(cache.len() > 1);
+        assert_eq!(block_cycles, single_cycles);
+        assert_eq!(blocks.debug_stack_info(), single.debug_stack_info());
+        for address in 0xC000..0xC010 {
+            assert_eq!(blocks.mmu.read_8(address), single.mmu.read_8(address), "${:04X}", address);
+        }
+        assert_eq!(blocks.mmu.read_8(0xC00F), 2);
+    }
+
+    fn with_ram_program(program: &[u8]) -> Cpu {
+        let mut cpu = Cpu::with_program(&[]);
+        for (address, byte) in (0xC000..).zip(program) {
+            cpu.mmu.write_8(address, *byte);
+        }
+        cpu.pc = 0xC000;
+        cpu
+    }
+
+    #[test]
+    fn writes_to_ram_pages_drop_their_blocks() {
+        let mut cpu = with_ram_program(&[
+            0x3E, 0x01,       // LD A,$01
+            0xC3, 0x00, 0xC0, // JP $C000
+        ]);
+        let mut cache = BlockCache::new();
+        cpu.run_block(&mut cache, 1000).unwrap();
+        assert_eq!(cpu.reg(Register8::A), 0x01);
+        assert_eq!(cache.len(), 1);
+
+        // Patched from outside, e.g. by an interrupt handler
+        cpu.mmu.write_8(0xC001, 0x42);
+        cpu.run_block(&mut cache, 1000).unwrap();
+        assert_eq!(cpu.reg(Register8::A), 0x42);
+    }
+
+    #[test]
+    fn blocks_stop_after_overwriting_themselves() {
+        let mut cpu = with_ram_program(&[
+            0x3E, 0x42,       // LD A,$42
+            0xEA, 0x06, 0xC0, // LD [$C006],A
+            0x06, 0x00,       // LD B,$00, the operand is overwritten
+            0xC3, 0x00, 0xC0, // JP $C000
+        ]);
+        let mut cache = BlockCache::new();
+        cpu.run_block(&mut cache, 1000).unwrap();
+        assert_eq!(cpu.pc, 0xC005);
+        assert_eq!(cache.len(), 0);
+        cpu.run_block(&mut cache, 1000).unwrap();
+        assert_eq!(cpu.reg(Register8::B), 0x42);
+    }
+}
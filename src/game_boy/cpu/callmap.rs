@@ -1,211 +1,140 @@
 use super::*;
-
-static mut FLAG: bool = false;
-const FLAG_SET_PC: u16 = 0x69E0;
+use crate::game_boy::hooks::{ExecuteEvent, MemoryEvent};
 
 impl Cpu {
-    pub fn tick(&mut self) -> u32 {
-        if self.handle_interrupts() {
-            println!("Handled Interrupt");
-            return 5;
-        }
-        if !self.is_running() {
-            // println!("HALTED");
-            return 1;
+    /// Execute the rest of the current instruction (or interrupt dispatch) or the whole next one
+    /// at once and return the cycles it took. Nothing else runs in between, see [`Cpu::cycle`]
+    /// for that.
+    ///
+    /// On a fault PC is reset to the faulting instruction (the rest of its changes stay) and the
+    /// error contains the state of the CPU at its start.
+    pub fn tick(&mut self) -> Result<u32, CpuError> {
+        let mut cycles = 1;
+        self.cycle()?;
+        while self.in_flight.is_some() {
+            self.cycle()?;
+            cycles += 1;
         }
-        // println!("PC: {}", self.pc);
+        Ok(cycles)
+    }
 
-        if self.pc == FLAG_SET_PC {
-            // self.dump_flags();
-            // println!("HL register {:#06X}", self.reg16(Register16::HL));
-            // println!("Instruction: {:#04X} PC: {:#06X} LY: {:#04X}", instruction, self.pc - 1, self.mmu.read_ly());
-            // println!("Another thing");
-            unsafe {
-                FLAG = true;
-            }
+    /// Call the execute hooks with the instruction at PC, if there are any
+    pub(super) fn hook_execute(&mut self) {
+        if self.hooks.as_ref().is_some_and(|hooks| hooks.wants_execute()) {
+            let event = self.peek_execute(self.pc);
+            self.hooks.as_mut().unwrap().execute(&event);
         }
-        if self.pc == 0x69E0 {
-            println!("Memory dump!");
-            for add in 0xDFE0..0xDFEF {
-                println!("{:#06X}: {:#04X}", add, self.mmu.read_8(add));
-            }
-            println!(
-                "DE is {:#06X} [DE] is {:#04X}",
-                self.reg16(Register16::DE),
-                self.mmu.read_8(self.reg16(Register16::DE))
-            )
-        }
-        // if self.pc >= 0x69F0 && self.pc <= 0x69FC
-        if self.pc <= 0x8000 && self.pc >= 0x6000 {
-            // println!("Instruction: {:#04X} PC: {:#06X} A reg {:#04X} Z {} N {} H {} C {}",
-            //          instruction, self.pc,
-            //          self.reg(Register8::A),
-            //          self.zero_bit(),
-            //          self.negative_bit(),
-            //          self.half_carry_bit(),
-            //          self.carry_bit()
-            // );
+    }
+
+    /// The instruction at the address, without side effects
+    fn peek_execute(&self, pc: u16) -> ExecuteEvent {
+        let mut code = self.mmu.read_8(pc);
+        let prefixed = code == opcodes::PREFIX;
+        let opcode = if prefixed {
+            code = self.mmu.read_8(pc.wrapping_add(1));
+            &CB_OPCODES[code as usize]
+        } else {
+            &OPCODES[code as usize]
+        };
+        let operand = match opcode.length() {
+            _ if prefixed => 0,
+            2 => self.mmu.read_8(pc.wrapping_add(1)) as u16,
+            3 => u16::from_le_bytes([self.mmu.read_8(pc.wrapping_add(1)), self.mmu.read_8(pc.wrapping_add(2))]),
+            _ => 0,
+        };
+        ExecuteEvent::new(pc, self.rom_bank_of(pc), code, prefixed, operand)
+    }
+
+    /// The ROM bank of the address, 0 outside of ROM
+    pub(super) fn rom_bank_of(&self, address: u16) -> u16 {
+        match address {
+            0x0000..=0x7FFF => self.mmu.rom().bank_of(address),
+            _ => 0,
         }
-        // if self.pc - 1 == 0x0386 {
-        //     self.dump_flags();
-        //     println!("Instruction: {:#04X} PC: {:#06X} LY: {:#04X}", instruction, self.pc - 1, self.mmu.read_ly());
-        //     println!("Another thing");
-        // }
-        // if self.pc - 1 == 0x0392 {
-        //     println!("This is happening!");
-        // } else if self.pc >= 0x0380 - 1 && self.pc - 1 <= 0x0392 {
-        //     self.dump_flags();
-        //     println!("Instruction: {:#04X} PC: {:#06X} LY: {:#04X}", instruction, self.pc - 1, self.mmu.read_ly());
-        //     println!("This is reached");
-        // }
-        // println!("Instruction: {:#04X} Program counter: {:#06X}", instruction, self.pc);
-        unsafe {
-            if FLAG {
-                // println!("Instruction: {:#04X} PC: {:#06X} Z {} N {} H {} C {}",
-                //          instruction, self.pc,
-                //          self.zero_bit(),
-                //          self.negative_bit(),
-                //          self.half_carry_bit(),
-                //          self.carry_bit()
-                // );
-                // // println!("Instruction: {:#04X} PC: {:#06X} LY: {:#04X}", instruction, self.pc, self.mmu.read_ly());
-                // print!("");
-            }
+    }
+
+    /// A data read of an instruction (or interrupt dispatch), that the read hooks see
+    pub(super) fn data_read(&mut self, address: u16) -> u8 {
+        let val = self.mmu.read_8(address);
+        if let Some(hooks) = &mut self.hooks {
+            hooks.read(&MemoryEvent::new(address, val));
         }
-        let instruction = self.read_u8();
+        self.log_read(address, val);
+        self.with_breakpoints(|breakpoints, environment| breakpoints.check_access(Access::Read, address, val, environment));
+        val
+    }
 
-        let cycle_count = match instruction {
+    /// Execute an unprefixed instruction, its immediate operand was already read.
+    ///
+    /// Returns, whether a conditional instruction was taken
+    pub(super) fn execute(&mut self, instruction: u8, operand: u16) -> bool {
+        let n8 = operand as u8;
+        let e8 = n8 as i8;
+        let n16 = operand;
+        match instruction {
             0x00 => self.nop(),
-            0x01 => {
-                let param = self.read_u16();
-                self.ld_const16_to_r16(Register16::BC, param)
-            }
+            0x01 => self.ld_const16_to_r16(Register16::BC, n16),
             0x02 => self.ld_a_to_r16addr(Register16::BC),
             0x03 => self.inc_r16(Register16::BC),
             0x04 => self.inc_r8(Register8::B),
             0x05 => self.dec_r8(Register8::B),
-            0x06 => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::B, param)
-            }
+            0x06 => self.ld_const8_to_r8(Register8::B, n8),
             0x07 => self.rlca(),
-            0x08 => {
-                let param = self.read_u16();
-                self.ld_sp_to_const16addr(param)
-            }
+            0x08 => self.ld_sp_to_const16addr(n16),
             0x09 => self.add_r16_to_hl(Register16::BC),
             0x0A => self.ld_r16addr_to_a(Register16::BC),
             0x0B => self.dec_r16(Register16::BC),
             0x0C => self.inc_r8(Register8::C),
             0x0D => self.dec_r8(Register8::C),
-            0x0E => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::C, param)
-            }
+            0x0E => self.ld_const8_to_r8(Register8::C, n8),
             0x0F => self.rrca(),
-            0x10 => {
-                self.pc += 1; // STOP reads two bytes
-                self.stop()
-                // TODO I think this also has to pop off the next byte of the memory
-                // -> increase program counter twice maybe?
-            }
-            0x11 => {
-                let param = self.read_u16();
-                self.ld_const16_to_r16(Register16::DE, param)
-            }
+            0x10 => return self.stop(),
+            0x11 => self.ld_const16_to_r16(Register16::DE, n16),
             0x12 => self.ld_a_to_r16addr(Register16::DE),
             0x13 => self.inc_r16(Register16::DE),
             0x14 => self.inc_r8(Register8::D),
             0x15 => self.dec_r8(Register8::D),
-            0x16 => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::D, param)
-            }
+            0x16 => self.ld_const8_to_r8(Register8::D, n8),
             0x17 => self.rla(),
-            0x18 => {
-                let param = self.read_i8();
-                self.jr(param)
-            }
+            0x18 => self.jr(e8),
             0x19 => self.add_r16_to_hl(Register16::DE),
             0x1A => self.ld_r16addr_to_a(Register16::DE),
             0x1B => self.dec_r16(Register16::DE),
             0x1C => self.inc_r8(Register8::E),
             0x1D => self.dec_r8(Register8::E),
-            0x1E => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::E, param)
-            }
+            0x1E => self.ld_const8_to_r8(Register8::E, n8),
             0x1F => self.rra(),
-            0x20 => {
-                let param = self.read_i8();
-                // if self.pc > 0xe0 {
-                //     println!("Jump relative by {} on {:04X}", param, self.pc);
-                // }
-                self.jr_cc(Condition::ZNotSet, param)
-            }
-            0x21 => {
-                let param = self.read_u16();
-                self.ld_const16_to_r16(Register16::HL, param)
-            }
+            0x20 => return self.jr_cc(Condition::ZNotSet, e8),
+            0x21 => self.ld_const16_to_r16(Register16::HL, n16),
             0x22 => self.ld_a_to_hl_and_inc(),
             0x23 => self.inc_r16(Register16::HL),
             0x24 => self.inc_r8(Register8::H),
             0x25 => self.dec_r8(Register8::H),
-            0x26 => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::H, param)
-            }
+            0x26 => self.ld_const8_to_r8(Register8::H, n8),
             0x27 => self.daa(),
-            0x28 => {
-                // I think this is not firing correctly
-                let param = self.read_i8();
-                // println!("Jumping by {}", param);
-                // println!("Zero flag is set: {}", self.zero_bit());
-                // println!("Will jump: {}", self.check_condition(Condition::ZSet));
-                let res = self.jr_cc(Condition::ZSet, param);
-                // println!("Shit");
-                res
-            }
+            0x28 => return self.jr_cc(Condition::ZSet, e8),
             0x29 => self.add_r16_to_hl(Register16::HL),
             0x2A => self.ld_hl_to_a_and_inc(),
             0x2B => self.dec_r16(Register16::HL),
             0x2C => self.inc_r8(Register8::L),
             0x2D => self.dec_r8(Register8::L),
-            0x2E => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::L, param)
-            }
+            0x2E => self.ld_const8_to_r8(Register8::L, n8),
             0x2F => self.cpl(),
-            0x30 => {
-                let param = self.read_i8();
-                self.jr_cc(Condition::CNotSet, param)
-            }
-            0x31 => {
-                let param = self.read_u16();
-                self.ld_const16_to_sp(param)
-            }
+            0x30 => return self.jr_cc(Condition::CNotSet, e8),
+            0x31 => self.ld_const16_to_sp(n16),
             0x32 => self.ld_a_to_hl_and_dec(),
             0x33 => self.inc_sp(),
             0x34 => self.inc_hl(),
             0x35 => self.dec_hl(),
-            0x36 => {
-                let param = self.read_u8();
-                self.ld_const8_to_hl(param)
-            }
+            0x36 => self.ld_const8_to_hl(n8),
             0x37 => self.scf(),
-            0x38 => {
-                let param = self.read_i8();
-                self.jr_cc(Condition::CSet, param)
-            }
+            0x38 => return self.jr_cc(Condition::CSet, e8),
             0x39 => self.add_sp_to_hl(),
             0x3A => self.ld_hl_to_a_and_dec(),
             0x3B => self.dec_sp(),
             0x3C => self.inc_r8(Register8::A),
             0x3D => self.dec_r8(Register8::A),
-            0x3E => {
-                let param = self.read_u8();
-                self.ld_const8_to_r8(Register8::A, param)
-            }
+            0x3E => self.ld_const8_to_r8(Register8::A, n8),
             0x3F => self.ccf(),
             0x40 => self.ld_r8_to_r8(Register8::B, Register8::B),
             0x41 => self.ld_r8_to_r8(Register8::B, Register8::C),
@@ -335,142 +264,68 @@ impl Cpu {
             0xBD => self.cp_r8(Register8::L),
             0xBE => self.cp_hl(),
             0xBF => self.cp_r8(Register8::A),
-            0xC0 => self.ret_cc(Condition::ZNotSet),
+            0xC0 => return self.ret_cc(Condition::ZNotSet),
             0xC1 => self.pop_r16(Register16::BC),
-            0xC2 => {
-                let param = self.read_u16();
-                self.jp_cc(Condition::ZNotSet, param)
-            }
-            0xC3 => {
-                let param = self.read_u16();
-                self.jp(param)
-            }
-            0xC4 => {
-                let param = self.read_u16();
-                self.call_cc(Condition::ZNotSet, param)
-            }
+            0xC2 => return self.jp_cc(Condition::ZNotSet, n16),
+            0xC3 => self.jp(n16),
+            0xC4 => return self.call_cc(Condition::ZNotSet, n16),
             0xC5 => self.push_r16(Register16::BC),
-            0xC6 => {
-                let param = self.read_u8();
-                self.add(param)
-            }
+            0xC6 => self.add(n8),
             0xC7 => self.rst(ResetVec::Vec1),
-            0xC8 => self.ret_cc(Condition::ZSet),
+            0xC8 => return self.ret_cc(Condition::ZSet),
             0xC9 => self.ret(),
-            0xCA => {
-                let param = self.read_u16();
-                self.jp_cc(Condition::ZSet, param)
-            }
-            // Cycle should be added due to additional read_u8 in execute_cb()
-            0xCB => self.execute_cb(), // TODO Should there be one cycle added?
-            0xCC => {
-                let param = self.read_u16();
-                self.call_cc(Condition::ZSet, param)
-            }
-            0xCD => {
-                let param = self.read_u16();
-                self.call(param)
-            }
-            0xCE => {
-                let param = self.read_u8();
-                self.adc(param)
-            }
+            0xCA => return self.jp_cc(Condition::ZSet, n16),
+            opcodes::PREFIX => unreachable!("prefixed opcodes are run by execute_cb"),
+            0xCC => return self.call_cc(Condition::ZSet, n16),
+            0xCD => self.call(n16),
+            0xCE => self.adc(n8),
             0xCF => self.rst(ResetVec::Vec2),
-            0xD0 => self.ret_cc(Condition::CNotSet),
+            0xD0 => return self.ret_cc(Condition::CNotSet),
             0xD1 => self.pop_r16(Register16::DE),
-            0xD2 => {
-                let param = self.read_u16();
-                self.jp_cc(Condition::CNotSet, param)
-            }
-            0xD4 => {
-                let param = self.read_u16();
-                self.call_cc(Condition::CNotSet, param)
-            }
+            0xD2 => return self.jp_cc(Condition::CNotSet, n16),
+            0xD4 => return self.call_cc(Condition::CNotSet, n16),
             0xD5 => self.push_r16(Register16::DE),
-            0xD6 => {
-                let param = self.read_u8();
-                self.sub(param)
-            }
+            0xD6 => self.sub(n8),
             0xD7 => self.rst(ResetVec::Vec3),
-            0xD8 => self.ret_cc(Condition::CSet),
+            0xD8 => return self.ret_cc(Condition::CSet),
             0xD9 => self.reti(),
-            0xDA => {
-                let param = self.read_u16();
-                self.jp_cc(Condition::CSet, param)
-            }
-            0xDC => {
-                let param = self.read_u16();
-                self.call_cc(Condition::CSet, param)
-            }
-            0xDE => {
-                let param = self.read_u8();
-                self.sbc(param)
-            }
+            0xDA => return self.jp_cc(Condition::CSet, n16),
+            0xDC => return self.call_cc(Condition::CSet, n16),
+            0xDE => self.sbc(n8),
             0xDF => self.rst(ResetVec::Vec4),
-            0xE0 => {
-                let param = self.read_u8();
-                self.ldh_a_to_const16addr(0xFF00 + (param as u16))
-            }
+            0xE0 => self.ldh_a_to_const16addr(0xFF00 + (n8 as u16)),
             0xE1 => self.pop_r16(Register16::HL),
             0xE2 => self.ldh_a_to_ff00_plus_c(),
             0xE5 => self.push_r16(Register16::HL),
-            0xE6 => {
-                let param = self.read_u8();
-                self.and(param)
-            }
+            0xE6 => self.and(n8),
             0xE7 => self.rst(ResetVec::Vec5),
-            0xE8 => {
-                let param = self.read_i8();
-                self.add_e8_to_sp(param)
-            }
+            0xE8 => self.add_e8_to_sp(e8),
             0xE9 => self.jp_hl(),
-            0xEA => {
-                let param = self.read_u16();
-                self.ld_a_to_const16addr(param)
-            }
-            0xEE => {
-                let param = self.read_u8();
-                self.xor(param)
-            }
+            0xEA => self.ld_a_to_const16addr(n16),
+            0xEE => self.xor(n8),
             0xEF => self.rst(ResetVec::Vec6),
-            0xF0 => {
-                let param = self.read_u8();
-                self.ldh_const16addr_to_a(0xFF00 + (param as u16))
-            }
+            0xF0 => self.ldh_const16addr_to_a(0xFF00 + (n8 as u16)),
             0xF1 => self.pop_af(),
             0xF2 => self.ldh_ff00_plus_c_to_a(),
             0xF3 => self.di(),
             0xF5 => self.push_af(),
-            0xF6 => {
-                let param = self.read_u8();
-                self.or(param)
-            }
+            0xF6 => self.or(n8),
             0xF7 => self.rst(ResetVec::Vec7),
-            0xF8 => {
-                let param = self.read_i8();
-                println!("Loading SP plus e8 to HL");
-                self.ld_sp_plus_e8_to_hl(param)
-            }
+            0xF8 => self.ld_sp_plus_e8_to_hl(e8),
             0xF9 => self.ld_hl_to_sp(),
-            0xFA => {
-                let param = self.read_u16();
-                self.ld_const16addr_to_a(param)
-            }
+            0xFA => self.ld_const16addr_to_a(n16),
             0xFB => self.ei(),
-            0xFE => {
-                let param = self.read_u8();
-                self.cp(param)
-            }
+            0xFE => self.cp(n8),
             0xFF => self.rst(ResetVec::Vec8),
-            _ => unreachable!("{:#04X} is not a valid instruction code", instruction),
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                self.lock_up(instruction)
+            }
         };
-        cycle_count
+        false
     }
 
-    fn execute_cb(&mut self) -> u32 {
-        let instruction: u8 = self.read_u8();
-
-        let cycle_count = match instruction {
+    pub(super) fn execute_cb(&mut self, instruction: u8) {
+        match instruction {
             0x00 => self.rlc(Register8::B),
             0x01 => self.rlc(Register8::C),
             0x02 => self.rlc(Register8::D),
@@ -727,8 +582,6 @@ impl Cpu {
             0xFD => self.set_r8(Register8::L, 7),
             0xFE => self.set_hl(7),
             0xFF => self.set_r8(Register8::A, 7),
-            _ => unreachable!("{:#04X} is not a valid CB instruction code", instruction),
-        };
-        cycle_count
+        }
     }
 }
@@ -0,0 +1,248 @@
+//! The call stack of the emulated program. CALL, RST and interrupt dispatches enter a frame,
+//! that remembers SP right after the return address was pushed. The frame is left, once SP is
+//! above that again. That is what RET and RETI do, but it also catches manual stack
+//! manipulation, like popping the return address and jumping back, or resetting SP in the main
+//! loop. A RET to an address, that the program pushed itself (a jump through the stack), doesn't
+//! leave a frame.
+
+use std::fmt::{Display, Formatter};
+use crate::game_boy::interrupt::Interrupt;
+use super::disassembler::Location;
+use super::Cpu;
+
+/// How a frame was entered
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Entry {
+    Call,
+    Rst,
+    Interrupt(Interrupt),
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Call => write!(f, "CALL"),
+            Entry::Rst => write!(f, "RST"),
+            Entry::Interrupt(interrupt) => write!(f, "{:?} interrupt", interrupt),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Frame {
+    entry: Entry,
+    /// The CALL or RST, or the instruction the interrupt was dispatched before
+    caller: Location,
+    /// The function, that was entered
+    function: Location,
+    return_address: u16,
+    /// Where the return address is on the stack
+    sp: u16,
+}
+
+impl Frame {
+    pub fn entry(&self) -> Entry { self.entry }
+    pub fn caller(&self) -> Location { self.caller }
+    pub fn function(&self) -> Location { self.function }
+    pub fn return_address(&self) -> u16 { self.return_address }
+    pub fn sp(&self) -> u16 { self.sp }
+}
+
+/// The frames, outermost first
+#[derive(Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    /// Counts the frames entered and left, to notice changes cheaply
+    changes: u64,
+}
+
+impl CallStack {
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    fn enter(&mut self, frame: Frame) {
+        self.frames.push(frame);
+        self.changes += 1;
+    }
+
+    /// Drop the frames, whose return address is below SP
+    fn unwind(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|frame| frame.sp < sp) {
+            self.frames.pop();
+            self.changes += 1;
+        }
+    }
+}
+
+/// A frame of [`Cpu::backtrace`]
+#[derive(Copy, Clone, Debug)]
+pub struct BacktraceFrame {
+    /// PC in the innermost frame, the caller in the others
+    location: Location,
+    /// How the frame above was entered from here, `None` for the innermost
+    entry: Option<Entry>,
+}
+
+impl BacktraceFrame {
+    pub fn location(&self) -> Location { self.location }
+    pub fn entry(&self) -> Option<Entry> { self.entry }
+}
+
+impl Cpu {
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Where the CPU is and how it got there, innermost first
+    pub fn backtrace(&self) -> Vec<BacktraceFrame> {
+        let mut backtrace = vec![BacktraceFrame { location: self.location_of(self.pc), entry: None }];
+        backtrace.extend(
+            self.call_stack
+                .frames
+                .iter()
+                .rev()
+                .map(|frame| BacktraceFrame { location: frame.caller, entry: Some(frame.entry) }),
+        );
+        backtrace
+    }
+
+    /// After CALL, RST or an interrupt dispatch pushed the return address (PC), before the jump
+    pub(super) fn enter_frame(&mut self, entry: Entry, caller: u16, function: u16) {
+        let frame = Frame {
+            entry,
+            caller: self.location_of(caller),
+            function: self.location_of(function),
+            return_address: self.pc,
+            sp: self.sp,
+        };
+        self.call_stack.enter(frame);
+    }
+
+    /// After each instruction (and whenever SP is set from outside)
+    pub(super) fn unwind_frames(&mut self) {
+        self.call_stack.unwind(self.sp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_boy::memory::addresses as adr;
+    use super::*;
+
+    const RET: u8 = 0xC9;
+
+    /// `program` at $0100 and `routines` (address and bytes) anywhere else in the ROM
+    fn with_routines(program: &[u8], routines: &[(u16, &[u8])]) -> Cpu {
+        let mut cpu = Cpu::with_program(program);
+        for (address, bytes) in routines {
+            for (address, byte) in (*address..).zip(*bytes) {
+                cpu.mmu.poke_8(address, *byte);
+            }
+        }
+        cpu
+    }
+
+    /// (entry, caller, function, return address, SP) of each frame, outermost first
+    fn frames(cpu: &Cpu) -> Vec<(Entry, u16, u16, u16, u16)> {
+        cpu.call_stack()
+            .frames()
+            .iter()
+            .map(|f| (f.entry(), f.caller().address(), f.function().address(), f.return_address(), f.sp()))
+            .collect()
+    }
+
+    #[test]
+    fn call_and_rst_enter_frames_ret_leaves_them() {
+        let mut cpu = with_routines(
+            &[
+                0x31, 0x00, 0xD0, // LD SP,$D000
+                0xCD, 0x10, 0x01, // CALL $0110
+                0xCF,             // RST $08
+            ],
+            &[(0x0008, &[RET]), (0x0110, &[0xCD, 0x20, 0x01, RET]), (0x0120, &[RET])],
+        );
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+        cpu.step();
+        assert_eq!(frames(&cpu), vec![(Entry::Call, 0x0103, 0x0110, 0x0106, 0xCFFE)]);
+        cpu.step();
+        assert_eq!(frames(&cpu)[1], (Entry::Call, 0x0110, 0x0120, 0x0113, 0xCFFC));
+        let backtrace: Vec<u16> = cpu.backtrace().iter().map(|frame| frame.location().address()).collect();
+        assert_eq!(backtrace, vec![0x0120, 0x0110, 0x0103]);
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 1);
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.pc, 0x0106);
+
+        cpu.step();
+        assert_eq!(frames(&cpu), vec![(Entry::Rst, 0x0106, 0x0008, 0x0107, 0xCFFE)]);
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.call_stack().changes(), 6);
+    }
+
+    #[test]
+    fn interrupts_enter_frames_reti_leaves_them() {
+        let mut cpu = with_routines(&[0x31, 0x00, 0xD0, 0xFB, 0x00], &[(0x0040, &[0xD9])]); // LD SP, EI, NOP / RETI
+        cpu.mmu.write_8(adr::interrupts::ENABLE, 1 << Interrupt::VBlank.if_ie_bit());
+        cpu.request_interrupt(Interrupt::VBlank);
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+        cpu.step();
+        assert_eq!(frames(&cpu), vec![(Entry::Interrupt(Interrupt::VBlank), 0x0105, 0x0040, 0x0105, 0xCFFE)]);
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.pc, 0x0105);
+    }
+
+    #[test]
+    fn manual_stack_changes_leave_frames() {
+        let mut cpu = with_routines(
+            &[
+                0x31, 0x00, 0xD0, // LD SP,$D000
+                0xCD, 0x10, 0x01, // CALL $0110
+                0xCD, 0x20, 0x01, // CALL $0120
+                0xCD, 0x30, 0x01, // CALL $0130
+            ],
+            &[
+                // Pops the return address and jumps back
+                (0x0110, &[0xE1, 0xE9]), // POP HL, JP HL
+                // Resets the stack
+                (0x0120, &[0x31, 0x00, 0xD0]), // LD SP,$D000
+                // Jumps through the stack, that isn't a return
+                (0x0130, &[0x21, 0x40, 0x01, 0xE5, RET]), // LD HL,$0140, PUSH HL, RET
+            ],
+        );
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 1);
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0106);
+
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 1);
+        cpu.step();
+        assert_eq!(cpu.call_stack().depth(), 0);
+
+        cpu.pc = 0x0109;
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.pc, 0x0140);
+        assert_eq!(frames(&cpu), vec![(Entry::Call, 0x0109, 0x0130, 0x010C, 0xCFFE)]);
+    }
+}
@@ -0,0 +1,182 @@
+//! The code/data log: what each byte of the ROM was used for. The flags of a byte only ever get
+//! set, so logs of several sessions can be merged with a bitwise or. Stored as a `.cdl` file,
+//! one byte of flags per ROM byte.
+//!
+//! Code are the opcode and operand fetches, data the reads of instructions. There is no DMA
+//! from ROM into VRAM, so tiles are found by the usual copy loop: a byte written to the tile data
+//! right after the same byte was read from ROM came from there.
+
+use crate::game_boy::memory::MemRegion;
+use super::Cpu;
+
+/// Part of an executed instruction
+pub const CODE: u8 = 0x01;
+/// Read by an instruction
+pub const DATA: u8 = 0x02;
+/// Copied into the tile data in VRAM
+pub const TILES: u8 = 0x04;
+/// The first byte of an executed instruction
+pub const INSTRUCTION: u8 = 0x08;
+
+pub struct CodeDataLog {
+    flags: Vec<u8>,
+    /// The ROM offset and value of the last data read from ROM, if it was the last read
+    last_read: Option<(usize, u8)>,
+}
+
+impl CodeDataLog {
+    /// An empty log for a ROM of the size
+    pub fn new(rom_size: usize) -> CodeDataLog {
+        CodeDataLog { flags: vec![0; rom_size], last_read: None }
+    }
+
+    /// The flags by ROM offset
+    pub fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    /// Add the flags of another log (of a `.cdl` file). Returns `false`, if it was made for a
+    /// ROM of a different size.
+    pub fn merge(&mut self, flags: &[u8]) -> bool {
+        if flags.len() != self.flags.len() {
+            return false;
+        }
+        for (own, other) in self.flags.iter_mut().zip(flags) {
+            *own |= other;
+        }
+        true
+    }
+
+    fn set(&mut self, offset: usize, flags: u8) {
+        if let Some(byte) = self.flags.get_mut(offset) {
+            *byte |= flags;
+        }
+    }
+}
+
+impl Cpu {
+    /// Log how the ROM is used, into the given log (that may have flags already)
+    pub fn start_code_data_log(&mut self, log: CodeDataLog) {
+        self.code_data_log = Some(Box::new(log));
+    }
+
+    /// Stop logging and return the log, if there is one
+    pub fn take_code_data_log(&mut self) -> Option<CodeDataLog> {
+        self.code_data_log.take().map(|log| *log)
+    }
+
+    /// The ROM offset, the address is mapped to right now. `None` outside of ROM.
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        let in_boot_rom = self.mmu.boot_rom_enabled() && address < 0x100;
+        if address < 0x8000 && !in_boot_rom { Some(self.mmu.rom().offset_of(address)) } else { None }
+    }
+
+    /// The bytes of an instruction (or only its opcode) were fetched
+    pub(super) fn log_code(&mut self, address: u16, length: u16, first: bool) {
+        if self.code_data_log.is_none() {
+            return;
+        }
+        for i in 0..length {
+            if let Some(offset) = self.rom_offset(address.wrapping_add(i)) {
+                let flags = if first && i == 0 { CODE | INSTRUCTION } else { CODE };
+                self.code_data_log.as_mut().unwrap().set(offset, flags);
+            }
+        }
+    }
+
+    /// A data read of an instruction
+    pub(super) fn log_read(&mut self, address: u16, val: u8) {
+        if self.code_data_log.is_none() {
+            return;
+        }
+        let offset = self.rom_offset(address);
+        let log = self.code_data_log.as_mut().unwrap();
+        log.last_read = offset.map(|offset| (offset, val));
+        if let Some(offset) = offset {
+            log.set(offset, DATA);
+        }
+    }
+
+    /// A write of an instruction
+    pub(super) fn log_write(&mut self, address: u16, val: u8) {
+        if let Some(log) = &mut self.code_data_log {
+            let is_tile_data = matches!(MemRegion::get_region(address), MemRegion::VRam) && address < 0x9800;
+            match log.last_read.take() {
+                Some((offset, read)) if is_tile_data && read == val => log.set(offset, TILES),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::GameBoy;
+
+    /// Copies $0200 to the tile data, $0201 to the tile map and $0202 + 1 to the tile data
+    const COPY: [u8; 18] = [
+        0x21, 0x00, 0x02, // LD HL,$0200
+        0x7E,             // LD A,[HL]
+        0xEA, 0x00, 0x80, // LD [$8000],A
+        0x2C,             // INC L
+        0x7E,             // LD A,[HL]
+        0xEA, 0x00, 0x98, // LD [$9800],A
+        0x2C,             // INC L
+        0x7E,             // LD A,[HL]
+        0x3C,             // INC A
+        0xEA, 0x10, 0x80, // LD [$8010],A
+    ];
+
+    fn run(gb: &mut GameBoy) -> CodeDataLog {
+        for (address, val) in (0x0200..).zip([0x42, 0x43, 0x44]) {
+            gb.cpu.mmu.poke_8(address, val);
+        }
+        while gb.cpu.pc < 0x0100 + COPY.len() as u16 {
+            gb.cpu.step();
+        }
+        gb.take_code_data_log().unwrap()
+    }
+
+    #[test]
+    fn bytes_copied_to_the_tile_data_are_tiles() {
+        let mut gb = GameBoy::with_program(&COPY);
+        assert!(gb.start_code_data_log(None));
+        let log = run(&mut gb);
+        let flags = log.flags();
+        assert_eq!(flags[0x0100], CODE | INSTRUCTION);
+        assert_eq!(flags[0x0101..0x0103], [CODE, CODE]);
+        assert_eq!(flags[0x0103], CODE | INSTRUCTION);
+        assert_eq!(flags[0x0200], DATA | TILES);
+        // Into the tile map, and not the byte that was read
+        assert_eq!(flags[0x0201], DATA);
+        assert_eq!(flags[0x0202], DATA);
+        assert_eq!(flags[0x0100 + COPY.len()], 0);
+    }
+
+    #[test]
+    fn earlier_logs_are_merged() {
+        let mut earlier = vec![0u8; 0x8000];
+        earlier[0x0200] = CODE;
+        earlier[0x0300] = DATA | TILES;
+        let mut gb = GameBoy::with_program(&COPY);
+        assert!(gb.start_code_data_log(Some(&earlier)));
+        let log = run(&mut gb);
+        assert_eq!(log.flags()[0x0200], CODE | DATA | TILES);
+        assert_eq!(log.flags()[0x0300], DATA | TILES);
+        assert_eq!(log.flags()[0x0100], CODE | INSTRUCTION);
+    }
+
+    #[test]
+    fn logs_of_other_roms_are_ignored() {
+        let mut log = CodeDataLog::new(0x8000);
+        log.set(0x0100, CODE);
+        assert!(!log.merge(&[DATA; 0x4000]));
+        assert_eq!(log.flags()[0x0100], CODE);
+        assert_eq!(log.flags()[0x0101], 0);
+
+        let mut gb = GameBoy::with_program(&COPY);
+        assert!(!gb.start_code_data_log(Some(&[DATA; 0x10000])));
+        assert_eq!(run(&mut gb).flags()[0x0300], 0);
+    }
+}
@@ -0,0 +1,189 @@
+//! Turn machine code back into (RGBDS) assembly.
+
+use std::fmt::{Display, Formatter};
+use super::opcodes::{self, Opcode, Operand};
+use crate::game_boy::memory::addresses as adr;
+use crate::game_boy::memory::rom::Rom;
+use crate::game_boy::memory::MMU;
+
+/// An address, together with the ROM bank for addresses in the ROM region
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    /// `None` outside of ROM (or if the bank isn't known)
+    bank: Option<u16>,
+    address: u16,
+}
+
+impl Location {
+    pub fn new(bank: Option<u16>, address: u16) -> Location {
+        Location { bank, address }
+    }
+
+    pub fn bank(&self) -> Option<u16> { self.bank }
+    pub fn address(&self) -> u16 { self.address }
+
+    /// The location of `address`, when it is jumped to from here. Bank 0 is fixed, the
+    /// switchable bank is only known, if we already are in it.
+    pub fn relative(&self, address: u16) -> Location {
+        let bank = match address {
+            0x0000..=0x3FFF => Some(0),
+            0x4000..=0x7FFF if self.address >= 0x4000 && self.address < 0x8000 => self.bank,
+            _ => None,
+        };
+        Location::new(bank, address)
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address),
+            None => write!(f, "{:04X}", self.address),
+        }
+    }
+}
+
+/// A single decoded instruction
+#[derive(Copy, Clone, Debug)]
+pub struct Instruction {
+    location: Location,
+    bytes: [u8; 3],
+    opcode: &'static Opcode,
+}
+
+impl Instruction {
+    /// Decode the instruction at the start of `bytes`. `None`, if there are not enough bytes.
+    pub fn decode(bytes: &[u8], location: Location) -> Option<Instruction> {
+        let opcode = opcodes::decode(bytes)?;
+        let length = opcode.length() as usize;
+        if bytes.len() < length {
+            return None;
+        }
+        let mut instruction_bytes = [0; 3];
+        instruction_bytes[..length].copy_from_slice(&bytes[..length]);
+        Some(Instruction {
+            location,
+            bytes: instruction_bytes,
+            opcode,
+        })
+    }
+
+    pub fn location(&self) -> Location { self.location }
+    pub fn address(&self) -> u16 { self.location.address }
+    pub fn opcode(&self) -> &'static Opcode { self.opcode }
+    pub fn length(&self) -> u16 { self.opcode.length() as u16 }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.opcode.length() as usize]
+    }
+
+    /// The immediate operand, if the instruction has one
+    pub fn immediate(&self) -> Option<u16> {
+        self.opcode.immediate(self.bytes())
+    }
+
+    /// The address of the next instruction
+    pub fn next_address(&self) -> u16 {
+        self.address().wrapping_add(self.length())
+    }
+
+    /// Where JR, JP, CALL and RST go, if it is known without running the code
+    pub fn target(&self) -> Option<Location> {
+        let address = match (self.opcode.mnemonic(), self.opcode.operands().last()?) {
+            ("JR", Operand::Relative) => {
+                let offset = self.immediate()? as u8 as i8;
+                self.next_address().wrapping_add(offset as u16)
+            }
+            ("JP" | "CALL", Operand::Imm16) => self.immediate()?,
+            ("RST", Operand::RstVec(vec)) => *vec as u16,
+            _ => return None,
+        };
+        Some(self.location.relative(address))
+    }
+
+    /// Does the execution never continue with the next instruction? (Unconditional jumps and
+    /// returns, illegal opcodes)
+    pub fn ends_block(&self) -> bool {
+        match self.opcode.mnemonic() {
+            "JP" | "JR" | "RET" | "RETI" => !self.opcode.is_conditional(),
+            _ => self.opcode.is_illegal(),
+        }
+    }
+
+    /// Format the instruction, `names` gives the name (label) of an address, if it has one.
+    /// I/O registers are named like in hardware.inc, if `names` doesn't know them.
+    pub fn format_with(&self, names: &dyn Fn(Location) -> Option<String>) -> String {
+        let operands: Vec<String> = self
+            .opcode
+            .operands()
+            .iter()
+            .map(|operand| self.format_operand(operand, names))
+            .collect();
+        if operands.is_empty() {
+            self.opcode.mnemonic().to_owned()
+        } else {
+            format!("{} {}", self.opcode.mnemonic(), operands.join(", "))
+        }
+    }
+
+    fn format_operand(&self, operand: &Operand, names: &dyn Fn(Location) -> Option<String>) -> String {
+        let value = self.immediate();
+        // RST vectors stay numbers, RST needs a constant
+        let is_target = matches!(operand, Operand::Relative | Operand::Imm16);
+        if let Some(target) = self.target().filter(|_| is_target) {
+            return names(target).unwrap_or_else(|| match operand {
+                // Show where the jump goes, not the offset
+                Operand::Relative => format!("${:04X}", target.address),
+                _ => operand.format(value),
+            });
+        }
+        match operand {
+            Operand::Addr16 => match value.and_then(|v| names(self.location.relative(v))) {
+                Some(name) => format!("[{}]", name),
+                None => operand.format(value),
+            },
+            Operand::HighAddr8 => {
+                let address = value.map(|v| 0xFF00 | v);
+                let name = address.and_then(|a| {
+                    names(Location::new(None, a)).or_else(|| adr::io_register_name(a).map(str::to_owned))
+                });
+                match name {
+                    Some(name) => format!("[{}]", name),
+                    None => operand.format(value),
+                }
+            }
+            _ => operand.format(value),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format_with(&|_| None))
+    }
+}
+
+/// Disassemble the instruction at the address, as the CPU would see it right now
+pub fn disassemble(mmu: &MMU, address: u16) -> Option<Instruction> {
+    let bytes: Vec<u8> = (0..3)
+        .map_while(|i| address.checked_add(i))
+        .map(|a| mmu.read_8(a))
+        .collect();
+    let bank = if address < 0x8000 && !(mmu.boot_rom_enabled() && address < 0x100) {
+        Some(mmu.rom().bank_of(address))
+    } else {
+        None
+    };
+    Instruction::decode(&bytes, Location::new(bank, address))
+}
+
+/// Disassemble the instruction at the address in a specific bank of the ROM image, no matter
+/// which bank is mapped right now
+pub fn disassemble_rom(rom: &[u8], bank: u16, address: u16) -> Option<Instruction> {
+    let bank_start = bank as usize * Rom::BANK_SIZE;
+    let offset = bank_start + address as usize % Rom::BANK_SIZE;
+    // Instructions can't cross the end of the bank
+    let end = (offset + 3).min(bank_start + Rom::BANK_SIZE).min(rom.len());
+    let bytes = rom.get(offset..end)?;
+    Instruction::decode(bytes, Location::new(Some(bank), address))
+}
@@ -0,0 +1,224 @@
+//! Lazy flag evaluation. The 8 bit ALU instructions don't compute Z, N, H and C right away, they
+//! only remember their operands. The flags are evaluated, when they are read (conditional jumps,
+//! PUSH AF, DAA, the debugger, ...) or when an instruction changes single flags.
+//!
+//! The flags have to come out exactly like the eager versions in `instructions.rs` did, see
+//! `affected_flags.txt`.
+
+use super::opcodes::{FlagEffect, Opcode};
+use super::{Cpu, Register8};
+
+const ZERO: u8 = 1 << 7;
+const NEGATIVE: u8 = 1 << 6;
+const HALF_CARRY: u8 = 1 << 5;
+const CARRY: u8 = 1 << 4;
+
+/// The last ALU operation, that set the flags
+#[derive(Copy, Clone, Debug)]
+pub(super) enum LazyFlags {
+    /// ADD, ADC: `Z 0 H C`
+    Add { a: u8, n8: u8, carry: bool },
+    /// SUB, SBC, CP: `Z 1 H C`
+    Sub { a: u8, n8: u8, carry: bool },
+    /// AND: `Z 0 1 0`, OR, XOR: `Z 0 0 0`
+    Logic { result: u8, half_carry: bool },
+    /// INC: `Z 0 H -`, C is kept
+    Inc { result: u8, carry: bool },
+    /// DEC: `Z 1 H -`, C is kept
+    Dec { result: u8, carry: bool },
+}
+
+impl LazyFlags {
+    /// The upper nibble of F
+    fn evaluate(self) -> u8 {
+        let (zero, negative, half_carry, carry) = match self {
+            LazyFlags::Add { a, n8, carry } => {
+                let c = carry as u16;
+                let result = a.wrapping_add(n8).wrapping_add(carry as u8);
+                let half_carry = (a & 0xF) + (n8 & 0xF) + carry as u8 > 0xF;
+                (result == 0, false, half_carry, a as u16 + n8 as u16 + c > 0xFF)
+            }
+            LazyFlags::Sub { a, n8, carry } => {
+                let c = carry as u16;
+                let result = a.wrapping_sub(n8).wrapping_sub(carry as u8);
+                let half_carry = ((a & 0xF) as u16) < (n8 & 0xF) as u16 + c;
+                (result == 0, true, half_carry, (a as u16) < n8 as u16 + c)
+            }
+            LazyFlags::Logic { result, half_carry } => (result == 0, false, half_carry, false),
+            // The lower nibble overflowed to 0
+            LazyFlags::Inc { result, carry } => (result == 0, false, result & 0xF == 0, carry),
+            // The lower nibble had to borrow
+            LazyFlags::Dec { result, carry } => (result == 0, true, result & 0xF == 0xF, carry),
+        };
+        (zero as u8 * ZERO) | (negative as u8 * NEGATIVE) | (half_carry as u8 * HALF_CARRY) | (carry as u8 * CARRY)
+    }
+
+    /// F with these flags, the lower nibble is kept
+    pub(super) fn apply(self, f: u8) -> u8 {
+        (f & 0x0F) | self.evaluate()
+    }
+}
+
+impl Cpu {
+    /// Let the flags be evaluated from the operation, once they are read
+    pub(super) fn set_lazy_flags(&mut self, flags: LazyFlags) {
+        self.lazy_flags = Some(flags);
+    }
+
+    /// Store the pending flags in F
+    pub(super) fn evaluate_flags(&mut self) {
+        if let Some(flags) = self.lazy_flags.take() {
+            let f = &mut self.registers[Register8::F.idx()];
+            *f = flags.apply(*f);
+        }
+    }
+
+    /// F with the pending flags, without storing them, so checking doesn't change what is lazy
+    pub(super) fn peek_flags(&self) -> u8 {
+        let f = self.registers[Register8::F.idx()];
+        self.lazy_flags.map_or(f, |flags| flags.apply(f))
+    }
+
+    /// Check, that the instruction changed the flags like the opcode table says. `before` is F
+    /// before it ran.
+    pub(super) fn check_flag_effects(&self, opcode: &Opcode, before: u8) {
+        let after = self.peek_flags();
+        for (i, effect) in opcode.flags().iter().enumerate() {
+            let flag = ZERO >> i;
+            let expected = match effect {
+                FlagEffect::Unaffected => before & flag,
+                FlagEffect::Reset => 0,
+                FlagEffect::Set => flag,
+                FlagEffect::Affected => continue,
+            };
+            assert_eq!(after & flag, expected, "{} set {} wrong", opcode.mnemonic(), &"ZNHC"[i..=i]);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The eager flag code the ALU instructions had before, as `(result, F)`
+    mod eager {
+        use super::*;
+
+        fn flags(zero: bool, negative: bool, half_carry: bool, carry: bool) -> u8 {
+            (zero as u8 * ZERO) | (negative as u8 * NEGATIVE) | (half_carry as u8 * HALF_CARRY) | (carry as u8 * CARRY)
+        }
+
+        pub fn adc(a: u8, n8: u8, carry: bool) -> (u8, u8) {
+            let (temp, overflow) = n8.overflowing_add(carry as u8);
+            let (res, overflow2) = a.overflowing_add(temp);
+            let half_overflow = ((a & 0xF) + (n8 & 0xF) + carry as u8) > 0xF;
+            (res, flags(res == 0, false, half_overflow, overflow || overflow2))
+        }
+
+        pub fn add(a: u8, n8: u8) -> (u8, u8) {
+            let (res, overflow) = a.overflowing_add(n8);
+            (res, flags(res == 0, false, ((a & 0xF) + (n8 & 0xF)) > 0xF, overflow))
+        }
+
+        pub fn sub(a: u8, n8: u8) -> (u8, u8) {
+            let half_carry = ((a & 0xF) as i16 - (n8 & 0xF) as i16) < 0;
+            (a.overflowing_sub(n8).0, flags(a == n8, true, half_carry, n8 > a))
+        }
+
+        pub fn sbc(a: u8, n8: u8, carry: bool) -> (u8, u8) {
+            let carry = carry as i16;
+            let result = a as i16 - n8 as i16 - carry;
+            let half_carry = ((a & 0xF) as i16 - (n8 & 0xF) as i16 - carry) < 0;
+            (result as u8, flags(result as u8 == 0, true, half_carry, result < 0))
+        }
+
+        pub fn cp(a: u8, n8: u8) -> (u8, u8) {
+            let (res, underflow) = a.overflowing_sub(n8);
+            (a, flags(res == 0, true, a & 0xF < n8 & 0xF, underflow))
+        }
+
+        pub fn and(a: u8, n8: u8) -> (u8, u8) {
+            (a & n8, flags(a & n8 == 0, false, true, false))
+        }
+
+        pub fn or(a: u8, n8: u8) -> (u8, u8) {
+            (a | n8, flags(a | n8 == 0, false, false, false))
+        }
+
+        pub fn xor(a: u8, n8: u8) -> (u8, u8) {
+            (a ^ n8, flags(a ^ n8 == 0, false, false, false))
+        }
+
+        pub fn inc(val: u8, carry: bool) -> (u8, u8) {
+            let res = val.wrapping_add(1);
+            (res, flags(res == 0, false, (val & 0xF) == 0xF, carry))
+        }
+
+        pub fn dec(val: u8, carry: bool) -> (u8, u8) {
+            let res = val.wrapping_sub(1);
+            (res, flags(res == 0, true, (val & 0xF) == 0, carry))
+        }
+    }
+
+    /// Run `lazy` for every A, operand and carry and compare A and F with `eager`
+    fn compare(name: &str, lazy: fn(&mut Cpu, u8), eager: fn(u8, u8, bool) -> (u8, u8)) {
+        let mut cpu = Cpu::with_program(&[]);
+        for a in 0..=0xFF {
+            for n8 in 0..=0xFF {
+                for carry in [false, true] {
+                    // The other flags are set, so flags, that aren't written, would show
+                    let f = ZERO | NEGATIVE | HALF_CARRY | if carry { CARRY } else { 0 };
+                    cpu.lazy_flags = None;
+                    *cpu.a_reg_mut() = a;
+                    *cpu.f_reg_mut() = f;
+                    lazy(&mut cpu, n8);
+                    cpu.evaluate_flags();
+                    assert_eq!(
+                        (cpu.a_reg(), cpu.f_reg()),
+                        eager(a, n8, carry),
+                        "{} A={:02X} n8={:02X} carry={}",
+                        name,
+                        a,
+                        n8,
+                        carry
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_and_adc() {
+        compare("ADD", |cpu, n8| cpu.add(n8), |a, n8, _| eager::add(a, n8));
+        compare("ADC", |cpu, n8| cpu.adc(n8), eager::adc);
+    }
+
+    #[test]
+    fn sub_sbc_and_cp() {
+        compare("SUB", |cpu, n8| cpu.sub(n8), |a, n8, _| eager::sub(a, n8));
+        compare("SBC", |cpu, n8| cpu.sbc(n8), eager::sbc);
+        compare("CP", |cpu, n8| cpu.cp(n8), |a, n8, _| eager::cp(a, n8));
+    }
+
+    #[test]
+    fn logic() {
+        compare("AND", |cpu, n8| cpu.and(n8), |a, n8, _| eager::and(a, n8));
+        compare("OR", |cpu, n8| cpu.or(n8), |a, n8, _| eager::or(a, n8));
+        compare("XOR", |cpu, n8| cpu.xor(n8), |a, n8, _| eager::xor(a, n8));
+    }
+
+    #[test]
+    fn inc_and_dec() {
+        // The operand is ignored, INC A and DEC A work on A
+        compare("INC", |cpu, _| cpu.inc_r8(Register8::A), |a, _, carry| eager::inc(a, carry));
+        compare("DEC", |cpu, _| cpu.dec_r8(Register8::A), |a, _, carry| eager::dec(a, carry));
+    }
+
+    #[test]
+    fn the_lower_nibble_of_f_is_kept() {
+        for f in 0..=0xFF {
+            assert_eq!(LazyFlags::Logic { result: 0, half_carry: true }.apply(f), ZERO | HALF_CARRY | (f & 0x0F));
+        }
+    }
+}
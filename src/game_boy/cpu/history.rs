@@ -0,0 +1,144 @@
+//! The last instructions the CPU executed, to see how it got into a lock-up or fault. Always
+//! recorded, into a ring buffer of fixed size, so it stays cheap. Only the raw state is stored,
+//! it is decoded and formatted, when the history is shown.
+
+use super::debug::DebugStackInfo;
+use super::disassembler::{Instruction, Location};
+use super::flags::LazyFlags;
+use super::{Cpu, Register8};
+use crate::game_boy::memory::MMU;
+
+/// Instructions kept in the history
+pub const HISTORY_LENGTH: usize = 4096;
+
+#[derive(Copy, Clone, Debug)]
+pub struct HistoryEntry {
+    pc: u16,
+    sp: u16,
+    /// Before the instruction, F without the lazy flags
+    registers: [u8; 8],
+    lazy_flags: Option<LazyFlags>,
+    /// The ROM bank at $4000
+    bank: u16,
+    boot_rom: bool,
+    /// The instruction, if it was decoded already, otherwise only the opcode
+    bytes: [u8; 3],
+    decoded: bool,
+}
+
+impl HistoryEntry {
+    pub fn opcode(&self) -> u8 { self.bytes[0] }
+
+    pub fn location(&self) -> Location {
+        let bank = match self.pc {
+            0x0000..=0x00FF if self.boot_rom => None,
+            0x0000..=0x3FFF => Some(0),
+            0x4000..=0x7FFF => Some(self.bank),
+            _ => None,
+        };
+        Location::new(bank, self.pc)
+    }
+
+    /// Before the instruction
+    pub fn registers(&self) -> DebugStackInfo {
+        let mut registers = self.registers;
+        if let Some(flags) = self.lazy_flags {
+            let f = &mut registers[Register8::F.idx()];
+            *f = flags.apply(*f);
+        }
+        DebugStackInfo::from_registers(registers, self.pc, self.sp)
+    }
+
+    /// `None` for illegal opcodes. Operands, that weren't decoded while running, are read from
+    /// `memory`, see [`MMU::read_code`].
+    pub fn instruction(&self, memory: &MMU) -> Option<Instruction> {
+        let mut bytes = self.bytes;
+        if !self.decoded {
+            bytes[1] = memory.read_code(self.pc.wrapping_add(1), self.boot_rom, self.bank);
+            bytes[2] = memory.read_code(self.pc.wrapping_add(2), self.boot_rom, self.bank);
+        }
+        Instruction::decode(&bytes, self.location())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    /// Where the next entry goes, once the buffer is full
+    next: usize,
+}
+
+impl History {
+    /// Oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        let (newer, older) = self.entries.split_at(self.next);
+        older.iter().chain(newer)
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        if self.entries.len() < HISTORY_LENGTH {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.next] = entry;
+            self.next = (self.next + 1) % HISTORY_LENGTH;
+        }
+    }
+}
+
+impl Cpu {
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Right before an instruction at PC starts. `bytes`, if it was decoded already, otherwise
+    /// only the opcode is read.
+    pub(super) fn record_history(&mut self, bytes: Option<[u8; 3]>) {
+        let (bytes, decoded) = match bytes {
+            Some(bytes) => (bytes, true),
+            None => ([self.mmu.read_8(self.pc), 0, 0], false),
+        };
+        let entry = HistoryEntry {
+            pc: self.pc,
+            sp: self.sp,
+            registers: self.registers,
+            lazy_flags: self.lazy_flags,
+            bank: self.mmu.rom().bank_of(0x4000),
+            boot_rom: self.pc < 0x100 && self.mmu.boot_rom_enabled(),
+            bytes,
+            decoded,
+        };
+        self.history.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_decoded_when_shown() {
+        // LD A, $0F; ADD A, $01; NOP
+        let mut cpu = Cpu::with_program(&[0x3E, 0x0F, 0xC6, 0x01, 0x00]);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        let entries: Vec<_> = cpu.history().entries().collect();
+        let shown: Vec<_> = entries.iter().map(|entry| entry.instruction(cpu.memory()).unwrap().to_string()).collect();
+        assert_eq!(shown, ["LD A, $0F", "ADD A, $01", "NOP"]);
+        assert_eq!(entries[2].location(), Location::new(Some(0), 0x0104));
+        // The lazy flags of ADD: H
+        assert!(entries[2].registers().to_string().contains("AF=1020"));
+    }
+
+    #[test]
+    fn only_the_last_entries_are_kept() {
+        let mut cpu = Cpu::with_program(&[]);
+        for _ in 0..HISTORY_LENGTH + 10 {
+            cpu.step();
+        }
+        let entries: Vec<_> = cpu.history().entries().collect();
+        assert_eq!(entries.len(), HISTORY_LENGTH);
+        assert_eq!(entries[0].location().address(), 0x100 + 10);
+        assert_eq!(entries[HISTORY_LENGTH - 1].registers().pc(), (0x100 + HISTORY_LENGTH + 9) as u16);
+    }
+}
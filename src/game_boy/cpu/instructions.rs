@@ -1,89 +1,74 @@
 use crate::game_boy::helpers::check_bit;
+use crate::game_boy::memory::addresses as adr;
 use super::*;
+use super::callstack::Entry;
 
 impl Cpu {
     /// Add the value in <reg> to A plus the carry flag
     ///
     /// Takes 1 cycle
-    pub(super) fn adc_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn adc_r8(&mut self, reg: Register8) {
         self.adc(self.reg(reg));
-        1
     }
 
     /// Add the value that HL points to, to A plus the carry flag
     ///
     /// 2 cycles
-    pub(super) fn adc_hl(&mut self) -> u32 {
-        self.adc(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn adc_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.adc(val);
     }
 
     /// Add a byte (u8) to A
     ///
     /// 2 cycles
-    pub(super) fn adc(&mut self, n8: u8) -> u32 {
-        let (temp, overflow) = n8.overflowing_add(self.carry_bit() as u8);
-        let (res, overflow2) = self.a_reg().overflowing_add(temp);
-        let overflow = overflow || overflow2;
-
-        let half_overflow = ((self.a_reg() & 0xF) + (n8 & 0xF) + self.carry_bit() as u8) > 0xF; // Does adding the lower half of the numbers (plus carry) overflow?
-
-        self.set_carry_bit(overflow); // Did the calculation overflow
-        self.set_half_carry_bit(half_overflow); // See half_overflow
-        self.set_zero_bit(res == 0); // Is the result zero
-        self.set_negative_bit(false); // By definition
+    pub(super) fn adc(&mut self, n8: u8) {
+        let a = self.a_reg();
+        let carry = self.carry_bit();
+        // The flags are only evaluated, when they are read
+        self.set_lazy_flags(LazyFlags::Add { a, n8, carry });
 
-        *self.a_reg_mut() = res;
-        2
+        *self.a_reg_mut() = a.wrapping_add(n8).wrapping_add(carry as u8);
     }
 
     /// Add the value in the register to A
     ///
     /// 1 cycle
-    pub(super) fn add_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn add_r8(&mut self, reg: Register8) {
         self.add(self.reg(reg));
-        1
     }
 
     /// Add the value HL points to to A
     ///
     /// 2 cycles
-    pub(super) fn add_hl(&mut self) -> u32 {
-        self.add(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn add_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.add(val);
     }
 
     /// Add a u8 to A
     ///
     /// 2 cycles
-    pub(super) fn add(&mut self, n8: u8) -> u32 {
-        let (res, overflow) = self.a_reg().overflowing_add(n8);
-
-        let half_overflow = ((self.a_reg() & 0xF) + (n8 & 0xF)) > 0xF; // Does adding the lower half of the numbers overflow the lower nibble?
-
-        self.set_carry_bit(overflow); // Did the calculation overflow
-        self.set_half_carry_bit(half_overflow); // See half_overflow
-        self.set_zero_bit(res == 0); // Is the result zero
-        self.set_negative_bit(false); // By definition
+    pub(super) fn add(&mut self, n8: u8) {
+        let a = self.a_reg();
+        // The flags are only evaluated, when they are read
+        self.set_lazy_flags(LazyFlags::Add { a, n8, carry: false });
 
-        *self.a_reg_mut() = res;
-        2
+        *self.a_reg_mut() = a.wrapping_add(n8);
     }
 
     /// Add a 16 bit register to HL
     ///
     /// 2 cycles
-    pub(super) fn add_r16_to_hl(&mut self, reg: Register16) -> u32 {
+    pub(super) fn add_r16_to_hl(&mut self, reg: Register16) {
         self.add_n16_to_hl(self.reg16(reg));
-        2
     }
 
     /// Add the value in SP to HL
     ///
     /// 2 cycles
-    pub(super) fn add_sp_to_hl(&mut self) -> u32 {
+    pub(super) fn add_sp_to_hl(&mut self) {
         self.add_n16_to_hl(self.sp);
-        2
     }
 
     /// Add a u16 to HL
@@ -108,7 +93,7 @@ impl Cpu {
     /// Add the signed value e8 to SP
     ///
     /// 4 cycles
-    pub(super) fn add_e8_to_sp(&mut self, e8: i8) -> u32 {
+    pub(super) fn add_e8_to_sp(&mut self, e8: i8) {
         // https://github.com/aidan-clyens/GBExperience/blob/master/src/cpu/cpu_alu.cpp#L375-L387
         let res = (self.sp as i32 + e8 as i32) as u16;
 
@@ -123,57 +108,48 @@ impl Cpu {
         self.set_half_carry_bit((self.sp ^ (e8_byte as u16) ^ (res & 0xFFFF)) & 0x10 == 0x10);
 
         self.sp = res;
-        4
     }
 
     /// Calculate the bitwise and between the register and A and store it in A
     ///
     /// 1 cycle
-    pub(super) fn and_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn and_r8(&mut self, reg: Register8) {
         self.and(self.reg(reg));
-        1
     }
 
     /// Calculate the bitwise and between the byte pointed to by HL and A and store it in A
     ///
     /// 2 Cycles
-    pub(super) fn and_hl(&mut self) -> u32 {
-        self.and(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn and_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.and(val);
     }
 
     /// Calculate the bitwise and between the number and A and store it in A
     ///
     /// 2 cycles
-    pub(super) fn and(&mut self, n8: u8) -> u32 {
+    pub(super) fn and(&mut self, n8: u8) {
         let res = self.a_reg() & n8;
-
-        // Reset flag register
-        self.set_negative_bit(false); // By definition
-        self.set_zero_bit(res == 0); // Set if result is zero
-        self.set_half_carry_bit(true); // By definition
-        self.set_carry_bit(false); // By definition
-
+        // Half carry is set by definition
+        self.set_lazy_flags(LazyFlags::Logic { result: res, half_carry: true });
         *self.a_reg_mut() = res;
-        2
     }
 
     /// Check if the specified bit is set in the register.
     /// The zero flag is set, if the bit was not set.
     ///
     /// 2 cycles
-    pub(super) fn bit_r8(&mut self, reg: Register8, bit: u8) -> u32 {
+    pub(super) fn bit_r8(&mut self, reg: Register8, bit: u8) {
         self.bit(self.reg(reg), bit);
-        2
     }
 
     /// Check if the specified bit is set in the byte that HL points to.
     /// The zero flag is set, if the bit was not set.
     ///
     /// 3 cycles
-    pub(super) fn bit_hl(&mut self, bit: u8) -> u32 {
-        self.bit(self.mmu.read_8(self.reg16(Register16::HL)), bit);
-        3
+    pub(super) fn bit_hl(&mut self, bit: u8) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.bit(val, bit);
     }
 
     /// Test if the specified bit of the byte is set and set the zero flag IF NOT set
@@ -191,88 +167,79 @@ impl Cpu {
     /// such that RET can pop it later; then, it executes an implicit JP n16.
     ///
     /// 6 cycles
-    pub(super) fn call(&mut self, n16: u16) -> u32 {
+    pub(super) fn call(&mut self, n16: u16) {
         // Call address n16. This pushes the address of the instruction after
         // the CALL on the stack, such that RET can pop it later; then,
         // it executes an implicit JP n16.
         // TODO check if correct
         // println!("Call {:04X} return to {:04X}", n16, self.pc);
         self.push_n16(self.pc);
+        self.enter_frame(Entry::Call, self.pc.wrapping_sub(3), n16);
         self.pc = n16;
-        6
     }
 
     /// Call address n16 if condition cc is met. (See call)
     ///
     /// 6 cycles taken / 3 cycles untaken
-    pub(super) fn call_cc(&mut self, cc: Condition, n16: u16) -> u32 {
+    ///
+    /// Returns, whether the call was taken
+    pub(super) fn call_cc(&mut self, cc: Condition, n16: u16) -> bool {
         // Call address n16, if condition cc is met (see call)
         if self.check_condition(cc) {
             self.call(n16);
-            6
+            true
         } else {
-            3
+            false
         }
     }
 
     /// Complement (invert) the carry flag.
     ///
     /// 1 cycle
-    pub(super) fn ccf(&mut self) -> u32 {
+    pub(super) fn ccf(&mut self) {
         self.set_negative_bit(false); // By definition
         self.set_half_carry_bit(false); // By definition
         self.set_carry_bit(!self.carry_bit());
-        1
     }
 
     /// Subtract the value in reg from A, but only set the flags and don't store the result
     ///
     /// 1 cycle
-    pub(super) fn cp_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn cp_r8(&mut self, reg: Register8) {
         self.cp(self.reg(reg));
-        1
     }
 
     /// Subtract the value of the byte pointed to by HL from A,
     /// but only set the flags and don't store the result
     ///
     /// 2 cycles
-    pub(super) fn cp_hl(&mut self) -> u32 {
-        self.cp(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn cp_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.cp(val);
     }
 
     /// Subtract n8 from A, but only set the flags and don't store the result
     ///
     /// 2 cycles
-    pub(super) fn cp(&mut self, n8: u8) -> u32 {
-        // TODO This one doesn't set the flags correctly
-        // specifically the half carry and the carry flag
+    pub(super) fn cp(&mut self, n8: u8) {
         let a = self.a_reg();
-        let (res, underflow) = a.overflowing_sub(n8);
-        self.set_zero_bit(res == 0); // Result is only zero, if A == n8
-        self.set_negative_bit(true);
-        // self.set_half_carry_bit(((a & 0xF) as i16 - (n8 & 0xF) as i16) < 0); // old approach
-        self.set_half_carry_bit(a & 0xF < n8 & 0xF); // new approach
-        self.set_carry_bit(underflow); // Result would have to borrow
-        2
+        self.set_lazy_flags(LazyFlags::Sub { a, n8, carry: false });
     }
 
     /// Complement the Accumulator/A register (A = ~A)
     ///
     /// 1 cycle
-    pub(super) fn cpl(&mut self) -> u32 {
+    pub(super) fn cpl(&mut self) {
         *self.a_reg_mut() = !self.a_reg();
         self.set_negative_bit(true); // By definition
         self.set_half_carry_bit(true); // By definition
-        1
     }
 
     /// Decimal Adjust Accumulator (A register) to get correct BCD representation
     /// (see https://ehaskins.com/2018-01-30%20Z80%20DAA/)
     ///
     /// 1 cycle
-    pub(super) fn daa(&mut self) -> u32 {
+    pub(super) fn daa(&mut self) {
         // TODO understand
         // No idea how this works xD see link above
         let mut correction: u8 = 0;
@@ -303,142 +270,109 @@ impl Cpu {
 
         *self.a_reg_mut() = val;
 
-        1
     }
 
     /// Decrement the value of the specified register by 1
     ///
     /// 1 cycle
-    pub(super) fn dec_r8(&mut self, reg: Register8) -> u32 {
-        // Set the half carry bit if borrowing from bit 4
-        // This is the case if the lower nibble is zero
-        self.set_half_carry_bit((self.reg(reg) & 0xF) == 0);
-        *self.reg_mut(reg) = self.reg(reg).overflowing_sub(1).0;
-        self.set_zero_bit(self.reg(reg) == 0); // Set flag if result is zero
-        self.set_negative_bit(true); // By definition
-        1
+    pub(super) fn dec_r8(&mut self, reg: Register8) {
+        let result = self.reg(reg).overflowing_sub(1).0;
+        // The carry flag isn't affected
+        let carry = self.carry_bit();
+        self.set_lazy_flags(LazyFlags::Dec { result, carry });
+        *self.reg_mut(reg) = result;
     }
 
     /// Decrement the value of the byte pointed to by HL by one
     ///
     /// 3 cycles
-    pub(super) fn dec_hl(&mut self) -> u32 {
+    pub(super) fn dec_hl(&mut self) {
         let hl = self.reg16(Register16::HL);
-        let mut val = self.mmu.read_8(hl);
-
-        // Set the half carry bit if borrowing from bit 4
-        // This is the case if the lower nibble is zero
-        self.set_half_carry_bit((val & 0xF) == 0);
-        val = val.overflowing_sub(1).0;
-
-        self.set_zero_bit(val == 0);
-        self.set_negative_bit(true); // By definition
-        self.mmu.write_8(hl, val);
-        3
+        let val = self.bus_read(hl).overflowing_sub(1).0;
+        let carry = self.carry_bit();
+        self.set_lazy_flags(LazyFlags::Dec { result: val, carry });
+        self.write_8(hl, val);
     }
 
     /// Decrement the value of the specified 16 bit register
     ///
     /// 2 cycles
-    pub(super) fn dec_r16(&mut self, reg: Register16) -> u32 {
+    pub(super) fn dec_r16(&mut self, reg: Register16) {
         self.write_reg16(reg, self.reg16(reg) - 1);
-        2
     }
 
     /// Decrement SP by 1
     ///
     /// 2 cycles
-    pub(super) fn dec_sp(&mut self) -> u32 {
+    pub(super) fn dec_sp(&mut self) {
         self.sp = self.sp.overflowing_sub(1).0;
-        2
     }
 
     /// Disable interrupts by clearing the IME flag
     ///
     /// 1 cycle
-    pub(super) fn di(&mut self) -> u32 {
-        if !self.mmu.boot_rom_enabled() {
-            println!("DI -> Disable Interrupts");
-        }
+    pub(super) fn di(&mut self) {
         self.interrupts_enabled = false;
-        1
+        self.ime_scheduled = false;
     }
 
     /// Enable interrupts by setting the IME flag.
-    /// TODO: Normally only set AFTER the instruction following this one
+    /// IME is only set AFTER the instruction following this one (see `Cpu::tick`)
     ///
     /// 1 cycle
-    pub(super) fn ei(&mut self) -> u32 {
-        println!("EI -> Enable interrupts");
-        self.interrupts_enabled = true;
-        1
+    pub(super) fn ei(&mut self) {
+        self.ime_scheduled = true;
     }
 
     /// Halt the CPU and set it in low power mode until an interrupt occurs.
     ///
     /// - cycles
-    pub(super) fn halt(&mut self) -> u32 {
+    pub(super) fn halt(&mut self) {
         // TODO check if this is the correct behavior
         self.halted = true;
-        1 // Is this correct?
     }
 
     /// Increment the specified register by 1.
     ///
     /// 1 cycle
-    pub(super) fn inc_r8(&mut self, reg: Register8) -> u32 {
-        // Set half carry, if the instruction would overflow after the 3rd bit
-        // This means the first nibble is completely set, so 0xF
-        self.set_half_carry_bit((self.reg(reg) & 0xF) == 0xF);
-        self.set_negative_bit(false); // By definition
-
-        *self.reg_mut(reg) = self.reg(reg) + 1;
-
-        self.set_zero_bit(self.reg(reg) == 0);
-        1
+    pub(super) fn inc_r8(&mut self, reg: Register8) {
+        let result = self.reg(reg).wrapping_add(1);
+        // The carry flag isn't affected
+        let carry = self.carry_bit();
+        self.set_lazy_flags(LazyFlags::Inc { result, carry });
+        *self.reg_mut(reg) = result;
     }
 
     /// Increment the byte pointed to by HL by 1
     ///
     /// 3 cycles
-    pub(super) fn inc_hl(&mut self) -> u32 {
+    pub(super) fn inc_hl(&mut self) {
         let hl = self.reg16(Register16::HL);
-        let mut val = self.mmu.read_8(hl);
-        // Set half carry, if the instruction would overflow after the 3rd bit
-        // This means the first nibble is completely set, so 0xF
-        self.set_half_carry_bit((val & 0xF) == 0xF);
-        self.set_negative_bit(false); // By definition
-
-        val = val.overflowing_add(1).0;
-
-        self.set_zero_bit(val == 0);
-        // TODO so far unused MemResult
-        self.mmu.write_8(hl, val);
-        3
+        let val = self.bus_read(hl).overflowing_add(1).0;
+        let carry = self.carry_bit();
+        self.set_lazy_flags(LazyFlags::Inc { result: val, carry });
+        self.write_8(hl, val);
     }
 
     /// Increment the value of the specified 16 bit register by 1
     ///
     /// 2 cycles
-    pub(super) fn inc_r16(&mut self, reg: Register16) -> u32 {
+    pub(super) fn inc_r16(&mut self, reg: Register16) {
         self.write_reg16(reg, self.reg16(reg).overflowing_add(1).0);
-        2
     }
 
     /// Increment SP by 1
     ///
     /// 2 cycles
-    pub(super) fn inc_sp(&mut self) -> u32 {
+    pub(super) fn inc_sp(&mut self) {
         self.sp += 1;
-        2
     }
 
     /// Jump to address n16 by setting PC to n16
     ///
     /// 4 cycles
-    pub(super) fn jp(&mut self, n16: u16) -> u32 {
+    pub(super) fn jp(&mut self, n16: u16) {
         self.pc = n16;
-        4
     }
 
     /// Jump to the address n16 by setting PC to n16, if the condition cc is met
@@ -447,35 +381,35 @@ impl Cpu {
     /// -> I'm pretty sure this means:
     ///    - 4 cycles if condition is met
     ///    - 3 cycles if condition is not met
-    pub(super) fn jp_cc(&mut self, cc: Condition, n16: u16) -> u32 {
+    ///
+    /// Returns, whether the jump was taken
+    pub(super) fn jp_cc(&mut self, cc: Condition, n16: u16) -> bool {
         if self.check_condition(cc) {
             self.pc = n16;
-            4
+            true
         } else {
-            3
+            false
         }
     }
 
     /// Jump to the value of the HL register, effectively setting PC to HL
     ///
     /// 1 cycle
-    pub(super) fn jp_hl(&mut self) -> u32 {
+    pub(super) fn jp_hl(&mut self) {
         self.pc = self.reg16(Register16::HL);
-        1
     }
 
     /// Jump relative by adding e8 to the address of the instruction FOLLOWING JR.
     /// `e8 == 0` would be equivalent to no jump
     ///
     /// 3 cycles
-    pub(super) fn jr(&mut self, e8: i8) -> u32 {
+    pub(super) fn jr(&mut self, e8: i8) {
         if e8 < 0 {
             let jump = (-e8) as u16;
             self.pc = self.pc.overflowing_sub(jump).0;
         } else {
             self.pc = self.pc.overflowing_add(e8 as u16).0;
         }
-        3
     }
 
     /// Jump relative by adding e8 to the address of the instruction FOLLOWING JR,
@@ -484,219 +418,202 @@ impl Cpu {
     ///
     /// 3 cycles if condition is met
     /// 2 cycles if condition is not met
-    pub(super) fn jr_cc(&mut self, cc: Condition, e8: i8) -> u32 {
+    ///
+    /// Returns, whether the jump was taken
+    pub(super) fn jr_cc(&mut self, cc: Condition, e8: i8) -> bool {
         if self.check_condition(cc) {
             self.jr(e8);
-            3
+            true
         } else {
-            2
+            false
         }
     }
 
     /// Load (copy) the value from the register on the right to the register on the left.
     ///
     /// 1 cycle
-    pub(super) fn ld_r8_to_r8(&mut self, to: Register8, from: Register8) -> u32 {
+    pub(super) fn ld_r8_to_r8(&mut self, to: Register8, from: Register8) {
         self.ld_const8_to_r8(to, self.reg(from));
-        1
     }
 
     /// Load the constant into the specified register
     ///
     /// 2 cycles
-    pub(super) fn ld_const8_to_r8(&mut self, to: Register8, n8: u8) -> u32 {
+    pub(super) fn ld_const8_to_r8(&mut self, to: Register8, n8: u8) {
         *self.reg_mut(to) = n8;
-        2
     }
 
     /// Load n16 value into specified 16 bit register
     ///
     /// 3 cycles
-    pub(super) fn ld_const16_to_r16(&mut self, to: Register16, n16: u16) -> u32 {
+    pub(super) fn ld_const16_to_r16(&mut self, to: Register16, n16: u16) {
         self.write_reg16(to, n16);
-        3
     }
 
     /// Store value from specified register into byte pointed to by HL
     ///
     /// 2 cycles
-    pub(super) fn ld_r8_to_hl(&mut self, from: Register8) -> u32 {
+    pub(super) fn ld_r8_to_hl(&mut self, from: Register8) {
         self.ld_const8_to_hl(self.reg(from));
-        2
     }
 
     /// Store the specified byte into the byte pointed to by HL
     ///
     /// 3 cycles
-    pub(super) fn ld_const8_to_hl(&mut self, n8: u8) -> u32 {
+    pub(super) fn ld_const8_to_hl(&mut self, n8: u8) {
         self.ld_const8_to_const16addr(n8, self.reg16(Register16::HL));
-        3
     }
 
     /// Basically LD [n16],[n8]
     ///
     /// Does not exist on GB Classic
     fn ld_const8_to_const16addr(&mut self, n8: u8, n16: u16) {
-        self.mmu.write_8(n16, n8);
+        self.write_8(n16, n8);
     }
 
     /// Store the value pointed to by HL into the specified register
     ///
     /// 2 cycles
-    pub(super) fn ld_hl_to_r8(&mut self, to: Register8) -> u32 {
-        *self.reg_mut(to) = self.mmu.read_8(self.reg16(Register16::HL));
-        2
+    pub(super) fn ld_hl_to_r8(&mut self, to: Register8) {
+        *self.reg_mut(to) = self.bus_read(self.reg16(Register16::HL));
     }
 
     /// Store the value in the A register into the address pointed to by the specified register
     ///
     /// 2 cycles
-    pub(super) fn ld_a_to_r16addr(&mut self, reg: Register16) -> u32 {
+    pub(super) fn ld_a_to_r16addr(&mut self, reg: Register16) {
         self.ld_a_to_const16addr(self.reg16(reg));
-        2
     }
 
     /// Store the value in the A register into the byte at the specified address
     ///
     /// 4 cycles
-    pub(super) fn ld_a_to_const16addr(&mut self, n16: u16) -> u32 {
-        self.mmu.write_8(n16, self.a_reg());
-        4
+    pub(super) fn ld_a_to_const16addr(&mut self, n16: u16) {
+        self.write_8(n16, self.a_reg());
     }
 
     /// Store the value in the A register into the byte at the specified address, provided
     /// the address is between 0xFF00 and 0xFFFF (I'm pretty sure both inclusive)
     ///
     /// 3 cycles
-    pub(super) fn ldh_a_to_const16addr(&mut self, n16: u16) -> u32 {
+    pub(super) fn ldh_a_to_const16addr(&mut self, n16: u16) {
         // I'm pretty sure this is meant as a guarantee and not as a noop if
         // the condition is not met
         assert!(n16 >= 0xFF00u16 && n16 <= 0xFFFFu16);
         self.ld_a_to_const16addr(n16);
-        3
     }
 
     /// Store the value in register A into the byte at address 0xFF00 + C (register)
     ///
     /// 2 cycles
-    pub(super) fn ldh_a_to_ff00_plus_c(&mut self) -> u32 {
+    pub(super) fn ldh_a_to_ff00_plus_c(&mut self) {
         self.ldh_a_to_const16addr(0xFF00 + self.c_reg() as u16);
-        2
     }
 
     /// Load value into register A from byte pointed to by the specified register
     ///
     /// 2 cycles
-    pub(super) fn ld_r16addr_to_a(&mut self, reg: Register16) -> u32 {
+    pub(super) fn ld_r16addr_to_a(&mut self, reg: Register16) {
         self.ld_const16addr_to_a(self.reg16(reg));
-        2
     }
 
     /// Load value into register A from byte pointed to by the specified address
     ///
     /// 4 cycles
-    pub(super) fn ld_const16addr_to_a(&mut self, n16: u16) -> u32 {
+    pub(super) fn ld_const16addr_to_a(&mut self, n16: u16) {
         self.ld_const16addr_to_r8(n16, Register8::A);
-        4
     }
 
     /// Load value into specified register from byte pointed to by the specified address
     ///
     /// Does not exist in GB classic
     fn ld_const16addr_to_r8(&mut self, n16: u16, to: Register8) {
-        *self.reg_mut(to) = self.mmu.read_8(n16);
+        *self.reg_mut(to) = self.bus_read(n16);
     }
 
     /// Load value from specified register into byte pointed to by the specified address
     ///
     /// Does not exist on the GB classic
     fn ld_r8_to_const16addr(&mut self, from: Register8, n16: u16) {
-        self.mmu.write_8(n16, self.reg(from));
+        self.write_8(n16, self.reg(from));
     }
 
     /// Load value into register A from byte pointed to by the specified address, provided, the
     /// address is between 0xFF00 and 0xFFFF (both inclusive)
     ///
     /// 3 cycles
-    pub(super) fn ldh_const16addr_to_a(&mut self, n16: u16) -> u32 {
+    pub(super) fn ldh_const16addr_to_a(&mut self, n16: u16) {
         // I'm pretty sure this is meant as a guarantee and not as a noop if
         // the condition is not met
         assert!(n16 >= 0xFF00u16 && n16 <= 0xFFFFu16);
         self.ld_const16addr_to_a(n16);
-        3
     }
 
     /// Load value into register A from the byte at address 0xFF00 + C (register)
     ///
     /// 2 cycles
-    pub(super) fn ldh_ff00_plus_c_to_a(&mut self) -> u32 {
+    pub(super) fn ldh_ff00_plus_c_to_a(&mut self) {
         self.ldh_const16addr_to_a(0xFF00 + self.c_reg() as u16);
-        2
     }
 
     /// Load value from register A into byte pointed to by HL and increment HL
     ///
     /// 2 cycles
-    pub(super) fn ld_a_to_hl_and_inc(&mut self) -> u32 {
+    pub(super) fn ld_a_to_hl_and_inc(&mut self) {
         self.ld_r8_to_hl(Register8::A);
         // self.inc_hl();
         let new_val = self.reg16(Register16::HL).overflowing_add(1).0;
         self.write_reg16(Register16::HL, new_val);
-        2
     }
 
     /// Load value from register A into byte pointed to by HL and decrement HL
     ///
     /// 2 cycles
-    pub(super) fn ld_a_to_hl_and_dec(&mut self) -> u32 {
+    pub(super) fn ld_a_to_hl_and_dec(&mut self) {
         let before = self.reg16(Register16::HL);
         self.ld_r8_to_hl(Register8::A);
         self.write_reg16(Register16::HL, before.overflowing_sub(1).0);
-        2
     }
 
     /// Load value into register A from byte pointed to by HL and increment HL
     ///
     /// 2 cycles
-    pub(super) fn ld_hl_to_a_and_inc(&mut self) -> u32 {
+    pub(super) fn ld_hl_to_a_and_inc(&mut self) {
+        let before = self.reg16(Register16::HL);
         self.ld_hl_to_r8(Register8::A);
-        self.inc_hl();
-        2
+        self.write_reg16(Register16::HL, before.overflowing_add(1).0);
     }
 
     /// Load value into register A from byte pointed to by HL and decrement HL
     ///
     /// 2 cycles
-    pub(super) fn ld_hl_to_a_and_dec(&mut self) -> u32 {
+    pub(super) fn ld_hl_to_a_and_dec(&mut self) {
         let before = self.reg16(Register16::HL);
         self.ld_hl_to_r8(Register8::A);
         self.write_reg16(Register16::HL, before.overflowing_sub(1).0);
-        2
     }
 
     /// Load specified value into SP
     ///
     /// 3 cycles
-    pub(super) fn ld_const16_to_sp(&mut self, n16: u16) -> u32 {
+    pub(super) fn ld_const16_to_sp(&mut self, n16: u16) {
         self.sp = n16;
-        3
     }
 
     /// Store SP & $FF at address n16 and SP >> 8 at address n16 + 1.
     /// This is a weird one. xD
     ///
     /// 5 cycles
-    pub(super) fn ld_sp_to_const16addr(&mut self, n16: u16) -> u32 {
+    pub(super) fn ld_sp_to_const16addr(&mut self, n16: u16) {
         // TODO ORDER???
-        self.mmu.write_8(n16, (self.sp >> 8) as u8);
-        self.mmu.write_8(n16 + 1, (self.sp & 0xFF) as u8);
-        5
+        self.write_8(n16, (self.sp >> 8) as u8);
+        self.write_8(n16 + 1, (self.sp & 0xFF) as u8);
     }
 
     /// Add the signed value e8 to SP and store the result in HL.
     ///
     /// 3 cycles
-    pub(super) fn ld_sp_plus_e8_to_hl(&mut self, e8: i8) -> u32 {
+    pub(super) fn ld_sp_plus_e8_to_hl(&mut self, e8: i8) {
         let e8_byte = e8.to_le_bytes()[0];
         let res = if e8 < 0 {
             let add = e8 as u16;
@@ -718,102 +635,100 @@ impl Cpu {
         self.set_negative_bit(false); // By definition
 
         self.write_reg16(Register16::HL, res);
-        3
     }
 
     /// Load register HL into SP
     ///
     /// 2 cycles
-    pub(super) fn ld_hl_to_sp(&mut self) -> u32 {
+    pub(super) fn ld_hl_to_sp(&mut self) {
         self.sp = self.reg16(Register16::HL);
-        2
     }
 
-    /// For completeness
+    /// Execute one of the illegal opcodes. The CPU locks up and stops executing instructions
+    /// for good, while the rest of the system keeps running.
     ///
     /// 1 cycle
-    pub(super) fn nop(&self) -> u32 {
-        1
+    pub(super) fn lock_up(&mut self, opcode: u8) {
+        self.locked_up = Some(LockUp {
+            pc: self.pc.wrapping_sub(1),
+            opcode,
+        });
     }
 
+    /// For completeness
+    ///
+    /// 1 cycle
+    pub(super) fn nop(&self) {}
+
     /// Calculate the bitwise or between register A and the specified register and
     /// store the result in register A.
     ///
     /// 1 cycle
-    pub(super) fn or_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn or_r8(&mut self, reg: Register8) {
         self.or(self.reg(reg));
-        1
     }
 
     /// Calculate the bitwise or between register A and the byte pointed to by HL
     /// and store the result in register A.
     ///
     /// 2 cycles
-    pub(super) fn or_hl(&mut self) -> u32 {
-        self.or(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn or_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.or(val);
     }
 
     /// Calculate the bitwise or between register A and the specified byte and
     /// store the result in register A.
     ///
     /// 2 cycles
-    pub(super) fn or(&mut self, n8: u8) -> u32 {
+    pub(super) fn or(&mut self, n8: u8) {
         let res = self.a_reg() | n8;
-        self.set_zero_bit(res == 0);
-        self.set_half_carry_bit(false); // By definition
-        self.set_carry_bit(false); // By definition
-        self.set_negative_bit(false); // By definition
+        self.set_lazy_flags(LazyFlags::Logic { result: res, half_carry: false });
         *self.a_reg_mut() = res;
-        2
     }
 
     /// Pop register AF from the stack
     ///
     /// 3 cycles
-    pub(super) fn pop_af(&mut self) -> u32 {
+    pub(super) fn pop_af(&mut self) {
         // Flags should automatically be set, by loading this byte
         self.ld_const16addr_to_r8(self.sp, Register8::F);
         self.inc_sp();
         self.ld_const16addr_to_r8(self.sp, Register8::A);
         self.inc_sp();
-        3
     }
 
     /// Pop specified register from stack
     ///
     /// 3 cycles
-    pub(super) fn pop_r16(&mut self, reg: Register16) -> u32 {
+    pub(super) fn pop_r16(&mut self, reg: Register16) {
         let (low_reg, high_reg) = reg.split();
         self.ld_const16addr_to_r8(self.sp, low_reg);
         self.inc_sp();
         self.ld_const16addr_to_r8(self.sp, high_reg);
         self.inc_sp();
-        3
     }
 
     /// Push register AF into the stack.
     ///
     /// 4 cycles
-    pub(super) fn push_af(&mut self) -> u32 {
+    pub(super) fn push_af(&mut self) {
         self.dec_sp();
         self.ld_r8_to_const16addr(Register8::A, self.sp);
         self.dec_sp();
         // Should automatically handle pushing the flags
         self.ld_r8_to_const16addr(Register8::F, self.sp);
-        4
     }
 
     /// Push the specified register into the stack
     ///
     /// 4 cycles
-    pub(super) fn push_r16(&mut self, reg: Register16) -> u32 {
+    pub(super) fn push_r16(&mut self, reg: Register16) {
         let (low_reg, high_reg) = reg.split();
         self.dec_sp();
         self.ld_r8_to_const16addr(high_reg, self.sp);
         self.dec_sp();
         self.ld_r8_to_const16addr(low_reg, self.sp);
-        4
     }
 
     /// Push the specified 16 bit value into the stack
@@ -832,45 +747,44 @@ impl Cpu {
     /// Set the specified bit of the register to 0
     ///
     /// 2 cycles
-    pub(super) fn res_r8(&mut self, reg: Register8, bit: u8) -> u32 {
+    pub(super) fn res_r8(&mut self, reg: Register8, bit: u8) {
         *self.reg_mut(reg) &= !(1 << bit);
-        2
     }
 
     /// Set the specified bit of the byte pointed to by HL to 0
     ///
     /// 4 cycles
-    pub(super) fn res_hl(&mut self, bit: u8) -> u32 {
-        let mut val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn res_hl(&mut self, bit: u8) {
+        let mut val = self.bus_read(self.reg16(Register16::HL));
         val &= !(1 << bit);
-        self.mmu.write_8(self.reg16(Register16::HL), val);
-        4
+        self.write_8(self.reg16(Register16::HL), val);
     }
 
     /// Return from subroutine. This is basically POP PC, if it had existed.
     ///
     /// 4 cycles
-    pub(super) fn ret(&mut self) -> u32 {
-        let low_byte = self.mmu.read_8(self.sp);
+    pub(super) fn ret(&mut self) {
+        let low_byte = self.bus_read(self.sp);
         self.inc_sp();
-        let high_byte = self.mmu.read_8(self.sp);
+        let high_byte = self.bus_read(self.sp);
         self.inc_sp();
         // self.pc = u16::from_le_bytes([low_byte, high_byte]);
         // self.pc = u16::from_le_bytes([high_byte, low_byte]);
         self.pc = ((high_byte as u16) << 8) | low_byte as u16;
-        4
     }
 
     /// Return from subroutine if condition is met.
     ///
     /// 5 cycles if condition is met.
     /// 2 cycles if condition is not met.
-    pub(super) fn ret_cc(&mut self, cc: Condition) -> u32 {
+    ///
+    /// Returns, whether the CPU returned
+    pub(super) fn ret_cc(&mut self, cc: Condition) -> bool {
         if self.check_condition(cc) {
             self.ret();
-            5
+            true
         } else {
-            2
+            false
         }
     }
 
@@ -878,41 +792,35 @@ impl Cpu {
     /// This IMMEDIATELY enables interrupts after the instruction in contrast to EI
     ///
     /// 4 cycles
-    pub(super) fn reti(&mut self) -> u32 {
-        println!("RETI");
-        self.ei();
+    pub(super) fn reti(&mut self) {
+        self.interrupts_enabled = true;
         self.ret();
-        4
     }
 
     /// Rotate the register left through the carry bit
     ///
     /// 2 cycles
-    pub(super) fn rl(&mut self, reg: Register8) -> u32 {
+    pub(super) fn rl(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.rl_helper(self.reg(reg));
-        2
     }
 
     /// Rotate the byte pointed to by HL to the left through the carry bit
     ///
     /// 4 cycles
-    pub(super) fn rl_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn rl_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.rl_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     /// Rotate the A register to the left through the carry bit.
     /// The resulting flags are a bit different.
     ///
     /// 1 cycle
-    pub(super) fn rla(&mut self) -> u32 {
+    pub(super) fn rla(&mut self) {
         self.rl(Register8::A);
-        // self.set_zero_bit(false); // By definition
-        //                              by which definition?
-        1
+        self.set_zero_bit(false); // By definition
     }
 
     /// A small helper to rotate the specified byte to the left through the carry bit
@@ -943,29 +851,26 @@ impl Cpu {
     /// Rotate the specified register
     ///
     /// 2 cycles
-    pub(super) fn rlc(&mut self, reg: Register8) -> u32 {
+    pub(super) fn rlc(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.rlc_helper(self.reg(reg));
-        2
     }
 
     /// Rotate the byte pointed to by HL
     ///
     /// 4 cycles
-    pub(super) fn rlc_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn rlc_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.rlc_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     /// Rotate the A register to the left. The resulting flags are a bit different.
     ///
     /// 1 cycle
-    pub(super) fn rlca(&mut self) -> u32 {
+    pub(super) fn rlca(&mut self) {
         self.rlc(Register8::A);
         self.set_zero_bit(false); // By definition
-        1
     }
 
     /// Rotate the specified byte to the left
@@ -996,30 +901,27 @@ impl Cpu {
     /// Rotate the specified register to the right through the carry bit
     ///
     /// 2 cycles
-    pub(super) fn rr(&mut self, reg: Register8) -> u32 {
+    pub(super) fn rr(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.rr_helper(self.reg(reg));
-        2
     }
 
     /// Rotate the byte pointed to by HL to the right throught the carry bit
     ///
     /// 4 cycles
-    pub(super) fn rr_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn rr_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.rr_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     /// Rotate the A register to the right through the carry bit.
     /// The resulting flags are a bit different.
     ///
     /// 1 cycle
-    pub(super) fn rra(&mut self) -> u32 {
+    pub(super) fn rra(&mut self) {
         self.rr(Register8::A);
         self.set_zero_bit(false); // By definition
-        1
     }
 
     /// A helper to rotate the specified byte to the right
@@ -1050,29 +952,26 @@ impl Cpu {
     /// Rotate the specified register to the right
     ///
     /// 2 cycles
-    pub(super) fn rrc(&mut self, reg: Register8) -> u32 {
+    pub(super) fn rrc(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.rrc_helper(self.reg(reg));
-        2
     }
 
     /// Rotate the byte pointed to by HL to the right
     ///
     /// 4 cycles
-    pub(super) fn rrc_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn rrc_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.rrc_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     /// Rotate the A register to the right. The resulting flags are a bit different
     ///
     /// 1 cycle
-    pub(super) fn rrca(&mut self) -> u32 {
+    pub(super) fn rrca(&mut self) {
         self.rrc(Register8::A);
         self.set_zero_bit(false); // By definition
-        1
     }
 
     /// Rotate the specified byte to the right
@@ -1103,9 +1002,9 @@ impl Cpu {
     /// Call the address associated with the reset vector. This is faster than a normal call
     ///
     /// 4 cycles
-    pub(super) fn rst(&mut self, vec: ResetVec) -> u32 {
+    pub(super) fn rst(&mut self, vec: ResetVec) {
         self.push_n16(self.pc);
-        self.pc = match vec {
+        let address = match vec {
             ResetVec::Vec1 => 0x00,
             ResetVec::Vec2 => 0x08,
             ResetVec::Vec3 => 0x10,
@@ -1115,89 +1014,76 @@ impl Cpu {
             ResetVec::Vec7 => 0x30,
             ResetVec::Vec8 => 0x38,
         };
-        4
+        self.enter_frame(Entry::Rst, self.pc.wrapping_sub(1), address);
+        self.pc = address;
     }
 
     /// Subtract the value in the specified register + the carry from the A register
     ///
     /// 1 cycle
-    pub(super) fn sbc_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn sbc_r8(&mut self, reg: Register8) {
         self.sbc(self.reg(reg));
-        1
     }
 
     /// Subtract the value in the byte pointed to by HL + the carry from the A register
     ///
     /// 2 cycles
-    pub(super) fn sbc_hl(&mut self) -> u32 {
-        self.sbc(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn sbc_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.sbc(val);
     }
 
     /// Subtract the byte + the carry from the A register
     ///
     /// 2 cycles
-    pub(super) fn sbc(&mut self, n8: u8) -> u32 {
-        let carry = if self.carry_bit() { 1u8 } else { 0u8 };
-        let result = self.a_reg() as i16 - n8 as i16 - carry as i16;
-
-        self.set_carry_bit(result < 0);
-        let result = result as u8; // Same behavior as static_cast in C++
-        self.set_half_carry_bit(
-            ((self.a_reg() & 0xF) as i16 - (n8 & 0xF) as i16 - carry as i16) < 0,
-        );
-        self.set_negative_bit(true); // By definition
-        self.set_zero_bit(result == 0);
-        *self.a_reg_mut() = result;
-        2
+    pub(super) fn sbc(&mut self, n8: u8) {
+        let a = self.a_reg();
+        let carry = self.carry_bit();
+        self.set_lazy_flags(LazyFlags::Sub { a, n8, carry });
+        *self.a_reg_mut() = a.wrapping_sub(n8).wrapping_sub(carry as u8);
     }
 
     /// Set the carry flag
     ///
     /// 1 cycle
-    pub(super) fn scf(&mut self) -> u32 {
+    pub(super) fn scf(&mut self) {
         self.set_carry_bit(true); // By definition
         self.set_negative_bit(false); // By definition
         self.set_half_carry_bit(false); // By definition
-        1
     }
 
     /// Set the specified bit in the specified register to high
     ///
     /// 2 cycles
-    pub(super) fn set_r8(&mut self, reg: Register8, bit: u8) -> u32 {
+    pub(super) fn set_r8(&mut self, reg: Register8, bit: u8) {
         // TODO check if set methods work correctly
         *self.reg_mut(reg) |= 1 << bit;
-        2
     }
 
     /// Set the specified bit in the byte pointed to by HL to high
     ///
     /// 4 cycles
-    pub(super) fn set_hl(&mut self, bit: u8) -> u32 {
-        let mut read = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn set_hl(&mut self, bit: u8) {
+        let mut read = self.bus_read(self.reg16(Register16::HL));
         read |= 1 << bit;
-        self.mmu.write_8(self.reg16(Register16::HL), read);
-        4
+        self.write_8(self.reg16(Register16::HL), read);
     }
 
     /// Shift the specified register to the left arithmetically
     ///
     /// 2 cycles
-    pub(super) fn sla(&mut self, reg: Register8) -> u32 {
+    pub(super) fn sla(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.sla_helper(self.reg(reg));
-        2
     }
 
     /// Shift the byte pointed to by HL to the left arithmetically
     ///
     /// 4 cycles
-    pub(super) fn sla_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn sla_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.sla_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     /// A helper for shifting left arithmetically
@@ -1230,20 +1116,18 @@ impl Cpu {
     /// Shift the specified register to the right arithmetically
     ///
     /// 2 cycles
-    pub(super) fn sra(&mut self, reg: Register8) -> u32 {
+    pub(super) fn sra(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.sra_helper(self.reg(reg));
-        2
     }
 
     /// Shift the byte pointed to by HL to the right arithmetically
     ///
     /// 4 cycles
-    pub(super) fn sra_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn sra_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.sra_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     fn sra_helper(&mut self, mut n8: u8) -> u8 {
@@ -1275,20 +1159,18 @@ impl Cpu {
     /// Shift specified register to the right logically
     ///
     /// 2 cycles
-    pub(super) fn srl(&mut self, reg: Register8) -> u32 {
+    pub(super) fn srl(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.srl_helper(self.reg(reg));
-        2
     }
 
     /// Shift the byte pointed to by HL to the right logically
     ///
     /// 4 cycles
-    pub(super) fn srl_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn srl_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.srl_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     fn srl_helper(&mut self, mut n8: u8) -> u8 {
@@ -1315,62 +1197,65 @@ impl Cpu {
         n8
     }
 
-    /// Enter CPU very low power mode
+    /// Enter CPU very low power mode. DIV is reset and the CPU and LCD stay stopped, until a
+    /// button is pressed (see `Cpu::set_button`).
+    /// On the CGB a prepared speed switch (KEY1) is done instead.
     ///
-    /// - cycles
-    pub(super) fn stop(&mut self) -> u32 {
+    /// 1 cycle / 2050 cycles for a speed switch
+    ///
+    /// Returns, whether the speed was switched
+    pub(super) fn stop(&mut self) -> bool {
+        // Not a memory access of the instruction, DIV is reset internally
+        let div = adr::timer::DIVIDER_REGISTER;
+        self.mmu.write_8(div, 0);
+        if self.mmu.speed_switch_armed() {
+            self.mmu.switch_speed();
+            // The CPU pauses while the clock is switched
+            return true;
+        }
         self.stopped = true;
-        1
+        false
     }
 
     /// Subtract the value in the specified register from the A register
     ///
     /// 1 cycle
-    pub(super) fn sub_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn sub_r8(&mut self, reg: Register8) {
         self.sub(self.reg(reg));
-        1
     }
 
     /// Subtract the value from the byte pointed to by HL from the A register
     ///
     /// 2 cycles
-    pub(super) fn sub_hl(&mut self) -> u32 {
-        self.sub(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn sub_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.sub(val);
     }
 
     /// Subtract the specified value from the A register
     ///
     /// 2 cycles
-    pub(super) fn sub(&mut self, n8: u8) -> u32 {
+    pub(super) fn sub(&mut self, n8: u8) {
         let a = self.a_reg();
-        self.set_zero_bit(a == n8); // Result is only zero, if A == n8
-        self.set_negative_bit(true); // By definition
-                                     // Result of lower nibble would have to borrow
-                                     // Very straight forward (and maybe not too fast) solution
-        self.set_half_carry_bit(((a & 0xF) as i16 - (n8 & 0xF) as i16) < 0);
-        self.set_carry_bit(n8 > a); // Result would have to borrow
+        self.set_lazy_flags(LazyFlags::Sub { a, n8, carry: false });
         *self.reg_mut(Register8::A) = a.overflowing_sub(n8).0;
-        2
     }
 
     /// Swap the higher and lower 4 bits in the specified register
     ///
     /// 2 cycles
-    pub(super) fn swap(&mut self, reg: Register8) -> u32 {
+    pub(super) fn swap(&mut self, reg: Register8) {
         *self.reg_mut(reg) = self.swap_helper(self.reg(reg));
-        2
     }
 
     /// Swap the higher and lower 4 bits in the byte pointed to by HL
     ///
     /// 4 cycles
-    pub(super) fn swap_hl(&mut self) -> u32 {
-        let val = self.mmu.read_8(self.reg16(Register16::HL));
+    pub(super) fn swap_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
         let val = self.swap_helper(val);
         let reg_val = self.reg16(Register16::HL);
-        self.mmu.write_8(reg_val, val);
-        4
+        self.write_8(reg_val, val);
     }
 
     /// Swap the lower and higher 4 bits, set flags as expected and return the result
@@ -1389,30 +1274,25 @@ impl Cpu {
     /// register.
     ///
     /// 1 cycle
-    pub(super) fn xor_r8(&mut self, reg: Register8) -> u32 {
+    pub(super) fn xor_r8(&mut self, reg: Register8) {
         self.xor(self.reg(reg));
-        1
     }
 
     /// Bitwise XOR between the byte pointed to by HL and the A register. Store the result in the
     /// A register.
     ///
     /// 2 cycles
-    pub(super) fn xor_hl(&mut self) -> u32 {
-        self.xor(self.mmu.read_8(self.reg16(Register16::HL)));
-        2
+    pub(super) fn xor_hl(&mut self) {
+        let val = self.bus_read(self.reg16(Register16::HL));
+        self.xor(val);
     }
 
     /// Bitwise XOR between the value in n8 and the A register. Store the result in the A register.
     ///
     /// 2 cycles
-    pub(super) fn xor(&mut self, n8: u8) -> u32 {
+    pub(super) fn xor(&mut self, n8: u8) {
         let res = self.reg(Register8::A) ^ n8;
-        self.set_zero_bit(res == 0);
-        self.set_carry_bit(false); // By definition
-        self.set_half_carry_bit(false); // By definition
-        self.set_negative_bit(false); // By definition
+        self.set_lazy_flags(LazyFlags::Logic { result: res, half_carry: false });
         *self.a_reg_mut() = res;
-        2
     }
 }
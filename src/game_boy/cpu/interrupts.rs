@@ -1,4 +1,6 @@
 use crate::game_boy::interrupt::Interrupt;
+use crate::game_boy::hooks::InterruptEvent;
+use super::callstack::Entry;
 use super::Cpu;
 
 impl Cpu {
@@ -21,44 +23,160 @@ impl Cpu {
         self.mmu.interrupt_requested(interrupt)
     }
 
-    fn execute_interrupt(&mut self, interrupt: Interrupt) -> bool {
-        // TODO keine Ahnung, ob das so passt
-        if self.interrupt_requested(interrupt) {
-            println!("Interrupted: {:?}", interrupt);
-            // IME disabled
-            self.interrupts_enabled = false;
-            // IF disabled
-            self.reset_requested_interrupt(interrupt);
-            // Regular call to jump address
-            self.call(interrupt.jump_address());
-            return true;
+    /// The interrupt with the highest priority, that is both requested (IF) and enabled (IE)
+    pub(super) fn pending_interrupt(&self) -> Option<Interrupt> {
+        Interrupt::PRIORITY
+            .iter()
+            .copied()
+            .find(|interrupt| self.interrupt_requested(*interrupt))
+    }
+
+    /// One M-cycle of dispatching the pending interrupt with the highest priority
+    /// (see https://gbdev.io/pandocs/Interrupts.html#interrupt-handling)
+    ///
+    /// - 2 wait states
+    /// - push high byte of PC
+    /// - push low byte of PC
+    /// - set PC to the jump address
+    ///
+    /// The interrupt to jump to is only decided after the high byte was pushed. If that push
+    /// overwrote IE (SP was 0x0000) and no enabled interrupt is left, the dispatch is cancelled
+    /// and PC is set to 0x0000 instead.
+    ///
+    /// 5 cycles
+    pub(super) fn dispatch_interrupt(&mut self, cycle: u16, target: &mut u16) {
+        let [low, high] = self.pc.to_le_bytes();
+        match cycle {
+            3 => {
+                self.sp = self.sp.wrapping_sub(1);
+                self.write_8(self.sp, high);
+            }
+            4 => {
+                let interrupt = self.pending_interrupt();
+                *target = match interrupt {
+                    Some(interrupt) => {
+                        // IF disabled
+                        self.reset_requested_interrupt(interrupt);
+                        if let Some(hooks) = &mut self.hooks {
+                            hooks.interrupt(&InterruptEvent::new(interrupt, self.pc));
+                        }
+                        let pc = self.pc;
+                        self.with_breakpoints(|breakpoints, environment| breakpoints.check_interrupt(interrupt, pc, environment));
+                        interrupt.jump_address()
+                    }
+                    None => 0x0000,
+                };
+                self.sp = self.sp.wrapping_sub(1);
+                self.write_8(self.sp, low);
+                if let Some(interrupt) = interrupt {
+                    self.enter_frame(Entry::Interrupt(interrupt), self.pc, *target);
+                }
+            }
+            5 => self.pc = *target,
+            _ => {}
         }
-        false
     }
 
-    // Handles all interrupts in order of priority. Returns true, if one was handled
-    //
-    // If one was handled, the GB waits for 5 cycles
+    /// Checks for pending interrupts before an instruction. Returns, whether the one with the
+    /// highest priority will be dispatched.
+    ///
+    /// A pending interrupt always wakes the CPU from HALT, even if IME is not set.
     pub fn handle_interrupts(&mut self) -> bool {
-        // TODO https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
-        if !self.interrupts_enabled {
+        if self.pending_interrupt().is_none() {
             return false;
         }
-        if self.execute_interrupt(Interrupt::VBlank) {
-            return true;
-        }
-        if self.execute_interrupt(Interrupt::LcdcStatus) {
-            return true;
-        }
-        if self.execute_interrupt(Interrupt::TimerOverflow) {
-            return true;
-        }
-        if self.execute_interrupt(Interrupt::SerialTransferCompletion) {
-            return true;
-        }
-        if self.execute_interrupt(Interrupt::Input) {
-            return true;
+        self.halted = false;
+        if !self.interrupts_enabled {
+            return false;
         }
-        false
+        self.interrupts_enabled = false;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_boy::memory::addresses as adr;
+    use super::*;
+
+    const NOP: u8 = 0x00;
+    const EI: u8 = 0xFB;
+    const DI: u8 = 0xF3;
+
+    /// A CPU running `program` with the interrupt requested and enabled
+    fn with_pending(program: &[u8], interrupt: Interrupt) -> Cpu {
+        let mut cpu = Cpu::with_program(program);
+        cpu.mmu.write_8(adr::interrupts::ENABLE, 1 << interrupt.if_ie_bit());
+        cpu.request_interrupt(interrupt);
+        cpu
+    }
+
+    #[test]
+    fn ei_takes_effect_after_the_next_instruction() {
+        let mut cpu = with_pending(&[EI, NOP, NOP], Interrupt::VBlank);
+        cpu.step();
+        assert!(!cpu.interrupts_enabled);
+        assert_eq!(cpu.pc, 0x101);
+        // The NOP after EI still runs
+        cpu.step();
+        assert!(cpu.interrupts_enabled);
+        assert_eq!(cpu.pc, 0x102);
+        assert_eq!(cpu.step(), 5);
+        assert_eq!(cpu.pc, Interrupt::VBlank.jump_address());
+        assert!(!cpu.interrupts_enabled);
+        assert!(!cpu.interrupt_requested(Interrupt::VBlank));
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(cpu.mmu.read_16(cpu.sp).unwrap(), 0x102);
+    }
+
+    #[test]
+    fn di_after_ei_cancels_it() {
+        let mut cpu = with_pending(&[EI, DI, NOP, NOP], Interrupt::VBlank);
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert!(!cpu.interrupts_enabled);
+        assert_eq!(cpu.pc, 0x103);
+    }
+
+    #[test]
+    fn pushing_over_ie_cancels_the_dispatch() {
+        // The high byte of PC ($01) overwrites IE, so the timer interrupt isn't enabled anymore
+        let mut cpu = with_pending(&[NOP], Interrupt::TimerOverflow);
+        cpu.interrupts_enabled = true;
+        cpu.sp = 0x0000;
+        assert_eq!(cpu.step(), 5);
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.mmu.read_8(adr::interrupts::ENABLE), 0x01);
+        assert_eq!(cpu.mmu.read_8(0xFFFE), 0x00);
+        // Not acknowledged
+        assert_eq!(cpu.mmu.read_8(adr::interrupts::FLAGS) & 0x1F, 1 << Interrupt::TimerOverflow.if_ie_bit());
+        assert!(!cpu.interrupts_enabled);
+    }
+
+    #[test]
+    fn pushing_over_ie_can_change_the_interrupt() {
+        // The high byte of PC ($08) leaves only serial enabled, the timer would have won before
+        let mut cpu = with_pending(&[], Interrupt::TimerOverflow);
+        cpu.mmu.write_8(adr::interrupts::ENABLE, 0x0C);
+        cpu.request_interrupt(Interrupt::SerialTransferCompletion);
+        cpu.interrupts_enabled = true;
+        cpu.pc = 0x0800;
+        cpu.sp = 0x0000;
+        cpu.step();
+        assert_eq!(cpu.pc, Interrupt::SerialTransferCompletion.jump_address());
+        assert_eq!(cpu.mmu.read_8(adr::interrupts::FLAGS) & 0x1F, 1 << Interrupt::TimerOverflow.if_ie_bit());
+    }
+
+    #[test]
+    fn upper_bits_of_if_read_as_one() {
+        // LDH A, [$FF0F]
+        let mut cpu = Cpu::with_program(&[0xF0, 0x0F]);
+        cpu.mmu.write_8(adr::interrupts::FLAGS, 0x01);
+        cpu.step();
+        assert_eq!(cpu.a_reg(), 0xE1);
+        cpu.mmu.write_8(adr::interrupts::FLAGS, 0x00);
+        assert_eq!(cpu.mmu.read_8(adr::interrupts::FLAGS), 0xE0);
     }
 }
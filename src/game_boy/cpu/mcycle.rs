@@ -0,0 +1,551 @@
+//! Execute instructions one M-cycle at a time, so their memory accesses happen at the right time
+//! relative to the PPU, the timers and DMA.
+//!
+//! Every SM83 instruction first reads (opcode, immediate operand, then memory) and writes after
+//! that. So the instruction functions still run at once, in the cycle of the last read: the reads
+//! before are done in their own cycles and latched on the [`Bus`], the writes of the function are
+//! buffered and done one per cycle afterwards. Which cycle does what is derived from the opcode
+//! table, see [`Schedule`].
+
+use super::*;
+use super::opcodes::Operand;
+
+/// The memory a read of an instruction goes to (apart from the opcode and immediate operand)
+#[derive(Copy, Clone, Debug)]
+enum ReadAddress {
+    BC,
+    DE,
+    HL,
+    /// $FF00 + C
+    HighC,
+    /// The immediate operand
+    Addr16,
+    /// $FF00 + the immediate operand
+    HighAddr8,
+    /// SP, SP + 1
+    Stack,
+}
+
+impl ReadAddress {
+    fn of(operand: &Operand) -> Option<ReadAddress> {
+        Some(match operand {
+            Operand::IndBC => ReadAddress::BC,
+            Operand::IndDE => ReadAddress::DE,
+            Operand::IndHL | Operand::IndHLInc | Operand::IndHLDec => ReadAddress::HL,
+            Operand::HighC => ReadAddress::HighC,
+            Operand::Addr16 => ReadAddress::Addr16,
+            Operand::HighAddr8 => ReadAddress::HighAddr8,
+            _ => return None,
+        })
+    }
+}
+
+/// What the CPU does in one M-cycle of an instruction
+#[derive(Copy, Clone, Debug)]
+enum Step {
+    Fetch,
+    /// Fetch the opcode after the CB prefix
+    Prefix,
+    /// Fetch a byte of the immediate operand
+    Immediate,
+    Read(ReadAddress),
+    Write,
+    Internal,
+}
+
+/// The M-cycles of an instruction. All cycles after the steps are internal.
+#[derive(Copy, Clone, Debug)]
+struct Schedule {
+    steps: [Step; 6],
+    len: usize,
+    /// The instruction function runs in the cycle of this step, after it
+    execute_at: usize,
+    total: u16,
+}
+
+/// Instructions, that read [HL] and write the result back
+const READ_MODIFY_WRITE: [&str; 12] = ["INC", "DEC", "RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL", "RES", "SET"];
+
+impl Schedule {
+    fn empty(total: u16) -> Schedule {
+        Schedule { steps: [Step::Internal; 6], len: 0, execute_at: usize::MAX, total }
+    }
+
+    /// The CB prefix, until the second byte says, what the instruction is
+    fn prefix() -> Schedule {
+        let mut schedule = Schedule::empty(2);
+        schedule.push(Step::Fetch);
+        schedule.push(Step::Prefix);
+        schedule
+    }
+
+    /// `taken` has to be true for all unconditional instructions
+    fn new(opcode: &Opcode, prefixed: bool, taken: bool) -> Schedule {
+        let mut schedule = Schedule::empty(0);
+        schedule.push(Step::Fetch);
+        if prefixed {
+            schedule.push(Step::Prefix);
+        }
+        schedule.total = opcode.cycles(taken) as u16;
+        let mnemonic = opcode.mnemonic();
+        let operands = opcode.operands();
+
+        // STOP fetches its padding byte together with the opcode
+        if mnemonic != "STOP" && !prefixed {
+            for _ in 1..opcode.length() {
+                schedule.push(Step::Immediate);
+            }
+        }
+        // The condition of RET cc is checked in its own cycle
+        if mnemonic == "RET" && opcode.is_conditional() {
+            schedule.push(Step::Internal);
+        }
+        let is_load = mnemonic == "LD" || mnemonic == "LDH";
+        match mnemonic {
+            "POP" | "RET" | "RETI" if taken => {
+                schedule.push(Step::Read(ReadAddress::Stack));
+                schedule.push(Step::Read(ReadAddress::Stack));
+            }
+            _ => {
+                for (i, operand) in operands.iter().enumerate() {
+                    // The destination of a load isn't read
+                    if let Some(address) = ReadAddress::of(operand).filter(|_| !(is_load && i == 0)) {
+                        schedule.push(Step::Read(address));
+                    }
+                }
+            }
+        }
+        schedule.execute_at = schedule.len - 1;
+
+        let writes_memory = operands.iter().enumerate().any(|(i, operand)| {
+            ReadAddress::of(operand).is_some() && ((is_load && i == 0) || READ_MODIFY_WRITE.contains(&mnemonic))
+        });
+        match mnemonic {
+            "PUSH" | "RST" | "CALL" if taken => {
+                // SP is decremented before the first push
+                schedule.push(Step::Internal);
+                schedule.push(Step::Write);
+                schedule.push(Step::Write);
+            }
+            // LD [n16], SP
+            "LD" if matches!(operands, [Operand::Addr16, Operand::SP]) => {
+                schedule.push(Step::Write);
+                schedule.push(Step::Write);
+            }
+            _ if writes_memory => schedule.push(Step::Write),
+            _ => {}
+        }
+        debug_assert!(schedule.len as u16 <= schedule.total, "{} takes too few cycles", mnemonic);
+        schedule
+    }
+
+    fn push(&mut self, step: Step) {
+        self.steps[self.len] = step;
+        self.len += 1;
+    }
+
+    fn step(&self, index: usize) -> Step {
+        if index < self.len { self.steps[index] } else { Step::Internal }
+    }
+}
+
+/// What the CPU is busy with
+#[derive(Copy, Clone, Debug)]
+enum Work {
+    Instruction,
+    Interrupt,
+    /// HALT, STOP or locked up
+    Idle,
+}
+
+/// The instruction (or interrupt dispatch), that is currently executed
+#[derive(Copy, Clone, Debug)]
+pub(super) struct InFlight {
+    work: Work,
+    /// The state at the start, for errors
+    pc: u16,
+    registers: DebugStackInfo,
+    /// Cycles done, including the current one
+    done: u16,
+    schedule: Schedule,
+    code: u8,
+    opcode: &'static Opcode,
+    prefixed: bool,
+    /// The immediate operand (or the interrupt vector)
+    operand: u16,
+    immediates: u8,
+    /// EI was executed right before this instruction
+    enable_ime: bool,
+}
+
+impl InFlight {
+    fn total(&self) -> u16 {
+        match self.work {
+            Work::Instruction => self.schedule.total,
+            Work::Interrupt => 5,
+            Work::Idle => 1,
+        }
+    }
+}
+
+/// Memory accesses of the instruction, that is executed right now
+#[derive(Clone, Default)]
+pub(super) struct Bus {
+    /// Reads done in the cycles before the instruction function runs
+    reads: [(u16, u8); 2],
+    read_count: usize,
+    next_read: usize,
+    /// Writes of the instruction function, that still have to be done
+    writes: [(u16, u8); 2],
+    write_count: usize,
+    next_write: usize,
+    /// Set while the instruction function runs
+    executing: bool,
+}
+
+impl Cpu {
+    /// Do one M-cycle of the current instruction (or interrupt dispatch) or start the next one.
+    /// Returns `true`, if a new one started.
+    ///
+    /// On a fault PC is reset to the faulting instruction (the rest of its changes stay) and the
+    /// error contains the state of the CPU at its start.
+    pub fn cycle(&mut self) -> Result<bool, CpuError> {
+        let started = self.in_flight.is_none();
+        let mut in_flight = match self.in_flight.take() {
+            Some(in_flight) => in_flight,
+            None => {
+                let in_flight = self.start();
+                if let Work::Instruction = in_flight.work {
+                    // Paused right before the instruction, it starts with the next cycle
+                    if self.break_before_instruction() {
+                        return Ok(false);
+                    }
+                    self.hook_execute();
+                    self.record_history(None);
+                    self.log_code(self.pc, 1, true);
+                }
+                self.profile_start();
+                in_flight
+            }
+        };
+        in_flight.done += 1;
+        self.profile_cycles(1);
+        let result = match in_flight.work {
+            Work::Instruction => self.instruction_cycle(&mut in_flight),
+            Work::Interrupt => {
+                self.dispatch_interrupt(in_flight.done, &mut in_flight.operand);
+                Ok(())
+            }
+            Work::Idle => Ok(()),
+        };
+        if let Err(fault) = result {
+            self.pc = in_flight.pc;
+            self.bus = Bus::default();
+            return Err(CpuError {
+                fault,
+                pc: in_flight.pc,
+                opcode: self.mmu.read_8(in_flight.pc),
+                registers: in_flight.registers,
+            });
+        }
+        if in_flight.done < in_flight.total() {
+            self.in_flight = Some(in_flight);
+        } else {
+            self.finish(&in_flight);
+        }
+        Ok(started)
+    }
+
+    /// The M-cycles left in the current instruction (or interrupt dispatch)
+    pub fn cycles_left(&self) -> u32 {
+        self.in_flight.map_or(0, |in_flight| (in_flight.total() - in_flight.done) as u32)
+    }
+
+    fn start(&mut self) -> InFlight {
+        let work = if self.locked_up.is_some() {
+            // Not even interrupts get the CPU out of this
+            Work::Idle
+        } else if self.handle_interrupts() {
+            Work::Interrupt
+        } else if !self.is_running() {
+            Work::Idle
+        } else {
+            Work::Instruction
+        };
+        InFlight {
+            work,
+            pc: self.pc,
+            registers: self.debug_stack_info(),
+            done: 0,
+            schedule: Schedule::prefix(),
+            code: 0,
+            opcode: &OPCODES[0],
+            prefixed: false,
+            operand: 0,
+            immediates: 0,
+            // EI only takes effect after the instruction following it
+            enable_ime: self.ime_scheduled,
+        }
+    }
+
+    fn instruction_cycle(&mut self, in_flight: &mut InFlight) -> FaultResult<()> {
+        let index = in_flight.done as usize - 1;
+        match in_flight.schedule.step(index) {
+            Step::Fetch => {
+                in_flight.code = self.read_u8()?;
+                if in_flight.code == opcodes::PREFIX {
+                    in_flight.schedule = Schedule::prefix();
+                } else {
+                    let opcode = &OPCODES[in_flight.code as usize];
+                    if opcode.mnemonic() == "STOP" {
+                        in_flight.operand = self.read_u8()? as u16;
+                    }
+                    in_flight.opcode = opcode;
+                    in_flight.schedule = Schedule::new(opcode, false, self.condition_holds(opcode));
+                }
+            }
+            Step::Prefix => {
+                in_flight.code = self.read_u8()?;
+                in_flight.prefixed = true;
+                in_flight.opcode = &CB_OPCODES[in_flight.code as usize];
+                in_flight.schedule = Schedule::new(in_flight.opcode, true, true);
+            }
+            Step::Immediate => {
+                in_flight.operand |= (self.read_u8()? as u16) << (8 * in_flight.immediates);
+                in_flight.immediates += 1;
+            }
+            Step::Read(address) => {
+                let address = match address {
+                    ReadAddress::BC => self.reg16(Register16::BC),
+                    ReadAddress::DE => self.reg16(Register16::DE),
+                    ReadAddress::HL => self.reg16(Register16::HL),
+                    ReadAddress::HighC => 0xFF00 | self.c_reg() as u16,
+                    ReadAddress::Addr16 => in_flight.operand,
+                    ReadAddress::HighAddr8 => 0xFF00 | in_flight.operand,
+                    ReadAddress::Stack => self.sp.wrapping_add(self.bus.read_count as u16),
+                };
+                let val = self.data_read(address);
+                self.bus.reads[self.bus.read_count] = (address, val);
+                self.bus.read_count += 1;
+            }
+            Step::Write => {
+                let (address, val) = self.bus.writes[self.bus.next_write];
+                self.bus.next_write += 1;
+                self.write_8(address, val);
+            }
+            Step::Internal => {}
+        }
+        if index == in_flight.schedule.execute_at {
+            self.bus.executing = true;
+            let flags = cfg!(debug_assertions).then(|| self.peek_flags());
+            if in_flight.prefixed {
+                self.execute_cb(in_flight.code);
+            } else {
+                self.execute(in_flight.code, in_flight.operand);
+            }
+            if let Some(before) = flags {
+                self.check_flag_effects(in_flight.opcode, before);
+            }
+            self.bus.executing = false;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, in_flight: &InFlight) {
+        debug_assert_eq!(self.bus.next_write, self.bus.write_count, "writes of {:?} left", in_flight.opcode);
+        self.bus = Bus::default();
+        self.unwind_frames();
+        // DI right after EI cancels the scheduled enable
+        if let Work::Instruction = in_flight.work {
+            if in_flight.enable_ime && self.ime_scheduled {
+                self.interrupts_enabled = true;
+                self.ime_scheduled = false;
+            }
+        }
+    }
+
+    /// Is the condition of the instruction met? Always `true` for unconditional instructions,
+    /// for STOP it says, whether the speed will be switched.
+    fn condition_holds(&self, opcode: &Opcode) -> bool {
+        match opcode.operands().first() {
+            _ if opcode.mnemonic() == "STOP" => self.mmu.speed_switch_armed(),
+            Some(Operand::CondNZ) => self.check_condition(Condition::ZNotSet),
+            Some(Operand::CondZ) => self.check_condition(Condition::ZSet),
+            Some(Operand::CondNC) => self.check_condition(Condition::CNotSet),
+            Some(Operand::CondC) => self.check_condition(Condition::CSet),
+            _ => true,
+        }
+    }
+
+    /// A memory read of an instruction function. Returns the value, that was read in the cycle
+    /// of the access.
+    pub(super) fn bus_read(&mut self, address: u16) -> u8 {
+        if self.bus.executing && self.bus.next_read < self.bus.read_count {
+            let (read_address, val) = self.bus.reads[self.bus.next_read];
+            debug_assert_eq!(read_address, address, "reads out of order");
+            self.bus.next_read += 1;
+            if read_address == address {
+                return val;
+            }
+        }
+        self.data_read(address)
+    }
+
+    /// Buffer a write of the instruction function for its cycle. Returns `false`, if it has to
+    /// be done right away (outside of instruction functions).
+    pub(super) fn buffer_write(&mut self, address: u16, val: u8) -> bool {
+        if !self.bus.executing || self.bus.write_count == self.bus.writes.len() {
+            return false;
+        }
+        self.bus.writes[self.bus.write_count] = (address, val);
+        self.bus.write_count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+    use super::super::opcodes::{CB_OPCODES, OPCODES, PREFIX};
+
+    /// The M-cycles of the unprefixed opcodes, from "Game Boy: Complete Technical Reference":
+    /// `F` opcode fetch, `I` immediate, `R` read, `W` write, `-` internal, `taken/not taken`
+    /// (for STOP without the stall of a speed switch). Empty for illegal opcodes and the prefix.
+    const ACCESSES: [[&str; 16]; 16] = [
+        ["F", "FII", "FW", "F-", "F", "F", "FI", "F", "FIIWW", "F-", "FR", "F-", "F", "F", "FI", "F"],
+        ["F/F", "FII", "FW", "F-", "F", "F", "FI", "F", "FI-", "F-", "FR", "F-", "F", "F", "FI", "F"],
+        ["FI-/FI", "FII", "FW", "F-", "F", "F", "FI", "F", "FI-/FI", "F-", "FR", "F-", "F", "F", "FI", "F"],
+        ["FI-/FI", "FII", "FW", "F-", "FRW", "FRW", "FIW", "F", "FI-/FI", "F-", "FR", "F-", "F", "F", "FI", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["FW", "FW", "FW", "FW", "FW", "FW", "F", "FW", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F", "F", "F", "F", "F", "F", "FR", "F", "F", "F", "F", "F", "F", "F", "FR", "F"],
+        ["F-RR-/F-", "FRR", "FII-/FII", "FII-", "FII-WW/FII", "F-WW", "FI", "F-WW", "F-RR-/F-", "FRR-", "FII-/FII", "", "FII-WW/FII", "FII-WW", "FI", "F-WW"],
+        ["F-RR-/F-", "FRR", "FII-/FII", "", "FII-WW/FII", "F-WW", "FI", "F-WW", "F-RR-/F-", "FRR-", "FII-/FII", "", "FII-WW/FII", "", "FI", "F-WW"],
+        ["FIW", "FRR", "FW", "", "", "F-WW", "FI", "F-WW", "FI--", "F", "FIIW", "", "", "", "FI", "F-WW"],
+        ["FIR", "FRR", "FR", "F", "", "F-WW", "FI", "F-WW", "FI-", "F-", "FIIR", "F", "", "", "FI", "F-WW"],
+    ];
+
+    /// `P` is the fetch of the opcode after the prefix. Only [HL] is accessed, BIT only reads it.
+    fn cb_accesses(code: u8) -> &'static str {
+        match code {
+            _ if code & 7 != 6 => "FP",
+            0x40..=0x7F => "FPR",
+            _ => "FPRW",
+        }
+    }
+
+    /// The accesses of the opcode, `taken` or not
+    fn expected(code: u8, prefixed: bool, taken: bool) -> &'static str {
+        let accesses = if prefixed { cb_accesses(code) } else { ACCESSES[code as usize >> 4][code as usize & 0xF] };
+        match accesses.split_once('/') {
+            Some((taken_accesses, _)) if taken => taken_accesses,
+            Some((_, not_taken)) => not_taken,
+            None => accesses,
+        }
+    }
+
+    fn render(schedule: &Schedule) -> String {
+        (0..schedule.total as usize)
+            .map(|index| match schedule.step(index) {
+                Step::Fetch => 'F',
+                Step::Prefix => 'P',
+                Step::Immediate => 'I',
+                Step::Read(_) => 'R',
+                Step::Write => 'W',
+                Step::Internal => '-',
+            })
+            .collect()
+    }
+
+    /// Every opcode, with `taken` for both outcomes of the conditional ones
+    fn opcodes() -> impl Iterator<Item = (u8, bool, bool)> {
+        (0..=0xFFu8).flat_map(|code| {
+            let opcode = &OPCODES[code as usize];
+            let outcomes: &[bool] = if opcode.is_conditional() { &[true, false] } else { &[true] };
+            let unprefixed = outcomes.iter().map(move |taken| (code, false, *taken));
+            let skip = opcode.is_illegal() || code == PREFIX;
+            unprefixed.filter(move |_| !skip).chain(std::iter::once((code, true, true)))
+        })
+    }
+
+    #[test]
+    fn schedules_match_the_table() {
+        for (code, prefixed, taken) in opcodes() {
+            let opcode = if prefixed { &CB_OPCODES[code as usize] } else { &OPCODES[code as usize] };
+            let schedule = Schedule::new(opcode, prefixed, taken);
+            let mut accesses = expected(code, prefixed, taken).to_owned();
+            if opcode.mnemonic() == "STOP" && taken {
+                // The speed switch stalls the CPU
+                accesses += &"-".repeat(2049);
+            }
+            assert_eq!(render(&schedule), accesses, "{:?}, prefixed {}, taken {}", opcode, prefixed, taken);
+        }
+    }
+
+    /// Runs the opcode cycle by cycle. `.` for cycles without a data access.
+    fn run(code: u8, prefixed: bool, flags: u8) -> String {
+        let program = if prefixed { [PREFIX, code, 0xC0] } else { [code, 0x00, 0xC0] };
+        let mut cpu = Cpu::with_program(&program);
+        let mut registers = cpu.registers();
+        registers.set_f(flags);
+        registers.set_bc(0xC000);
+        registers.set_de(0xC000);
+        registers.set_hl(0xC000);
+        registers.set_sp(0xD000);
+        cpu.set_registers(registers);
+        let accesses = Rc::new(RefCell::new(String::new()));
+        let reads = accesses.clone();
+        cpu.hooks().on_read(move |_| reads.borrow_mut().push('R'));
+        let writes = accesses.clone();
+        cpu.hooks().on_write(move |_| writes.borrow_mut().push('W'));
+        let mut cycles = String::new();
+        loop {
+            let before = accesses.borrow().len();
+            cpu.cycle().unwrap();
+            let accesses = accesses.borrow();
+            assert!(accesses.len() <= before + 1, "more than one access in a cycle");
+            cycles.push(accesses[before..].chars().next().unwrap_or('.'));
+            if cpu.in_flight.is_none() {
+                return cycles;
+            }
+        }
+    }
+
+    #[test]
+    fn execution_accesses_memory_in_the_scheduled_cycles() {
+        for (code, prefixed, taken) in opcodes() {
+            // Speed switches and the halt of STOP are tested elsewhere
+            if !prefixed && code == 0x10 {
+                continue;
+            }
+            // NZ and NC hold with cleared flags, Z and C with set ones
+            let negated = (code >> 3) & 1 == 0;
+            let flags = if taken == negated { 0x00 } else { 0xF0 };
+            let accesses: String = expected(code, prefixed, taken)
+                .chars()
+                .map(|access| if access == 'R' || access == 'W' { access } else { '.' })
+                .collect();
+            assert_eq!(run(code, prefixed, flags), accesses, "{:02X}, prefixed {}, taken {}", code, prefixed, taken);
+        }
+    }
+
+    #[test]
+    fn ld_a_hl_inc_increments_hl() {
+        // LD A, [HL+] at $0100 reads its own opcode
+        let mut cpu = Cpu::with_program(&[0x2A]);
+        let mut registers = cpu.registers();
+        registers.set_hl(0x0100);
+        cpu.set_registers(registers);
+        assert_eq!(cpu.step(), 2);
+        assert_eq!(cpu.registers().a(), 0x2A);
+        assert_eq!(cpu.registers().hl(), 0x0101);
+        assert_eq!(cpu.memory().read_8(0x0100), 0x2A);
+    }
+}
@@ -0,0 +1,804 @@
+//! The SM83 opcode table. Decoding, execution, cycle counting and disassembly all go through
+//! these tables, so they can't disagree.
+//!
+//! Cycles are M-cycles, like everywhere else in the CPU.
+//! https://gbdev.io/gb-opcodes/optables/
+
+use Operand::*;
+
+/// The prefix of the extended (CB) opcodes
+pub const PREFIX: u8 = 0xCB;
+
+/// What the operand of an instruction is.
+/// Names follow the RGBDS docs (https://rgbds.gbdev.io/docs/v0.5.0/gbz80.7)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    /// [BC]
+    IndBC,
+    /// [DE]
+    IndDE,
+    /// [HL]
+    IndHL,
+    /// [HL+]
+    IndHLInc,
+    /// [HL-]
+    IndHLDec,
+    /// [$FF00 + C]
+    HighC,
+    CondNZ,
+    CondZ,
+    CondNC,
+    CondC,
+    /// n8, an immediate byte
+    Imm8,
+    /// n16, an immediate word
+    Imm16,
+    /// [n16]
+    Addr16,
+    /// [$FF00 + n8]
+    HighAddr8,
+    /// e8, a signed offset added to SP
+    Offset8,
+    /// e8, a signed jump offset relative to the next instruction
+    Relative,
+    /// SP + e8
+    SpOffset,
+    /// u3, the bit of BIT, RES and SET
+    Bit(u8),
+    /// The target of RST
+    RstVec(u8),
+}
+
+impl Operand {
+    /// Is the operand read from the bytes following the opcode?
+    pub fn is_immediate(&self) -> bool {
+        matches!(self, Imm8 | Imm16 | Addr16 | HighAddr8 | Offset8 | Relative | SpOffset)
+    }
+}
+
+impl Operand {
+    /// Format the operand like RGBDS expects it. `value` is the immediate value, `None` if the
+    /// operand bytes are missing.
+    pub fn format(&self, value: Option<u16>) -> String {
+        let value = match value {
+            Some(value) => value,
+            None if self.is_immediate() => return "?".to_owned(),
+            None => 0,
+        };
+        match self {
+            A => "A".to_owned(),
+            B => "B".to_owned(),
+            C => "C".to_owned(),
+            D => "D".to_owned(),
+            E => "E".to_owned(),
+            H => "H".to_owned(),
+            L => "L".to_owned(),
+            AF => "AF".to_owned(),
+            BC => "BC".to_owned(),
+            DE => "DE".to_owned(),
+            HL => "HL".to_owned(),
+            SP => "SP".to_owned(),
+            IndBC => "[BC]".to_owned(),
+            IndDE => "[DE]".to_owned(),
+            IndHL => "[HL]".to_owned(),
+            IndHLInc => "[HL+]".to_owned(),
+            IndHLDec => "[HL-]".to_owned(),
+            HighC => "[C]".to_owned(),
+            CondNZ => "NZ".to_owned(),
+            CondZ => "Z".to_owned(),
+            CondNC => "NC".to_owned(),
+            CondC => "C".to_owned(),
+            Imm8 => format!("${:02X}", value),
+            Imm16 => format!("${:04X}", value),
+            Addr16 => format!("[${:04X}]", value),
+            HighAddr8 => format!("[$FF{:02X}]", value),
+            Offset8 | Relative => format!("{}", value as u8 as i8),
+            SpOffset => format!("SP{:+}", value as u8 as i8),
+            Bit(bit) => format!("{}", bit),
+            RstVec(vec) => format!("${:02X}", vec),
+        }
+    }
+}
+
+/// How an instruction changes a flag
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlagEffect {
+    Unaffected,
+    Reset,
+    Set,
+    /// Depends on the result
+    Affected,
+}
+
+#[derive(Debug)]
+pub struct Opcode {
+    mnemonic: &'static str,
+    operands: &'static [Operand],
+    length: u8,
+    cycles: u16,
+    cycles_taken: u16,
+    /// Z, N, H, C
+    flags: [FlagEffect; 4],
+}
+
+impl Opcode {
+    /// `flags` is written like in the opcode tables: `Z`, `N`, `H` or `C` if the flag depends on
+    /// the result, `0`/`1` if it is reset/set and `-` if it isn't touched.
+    const fn new(mnemonic: &'static str, operands: &'static [Operand], length: u8, cycles: u16, cycles_taken: u16, flags: &[u8; 4]) -> Opcode {
+        let mut effects = [FlagEffect::Unaffected; 4];
+        let mut i = 0;
+        while i < 4 {
+            effects[i] = match flags[i] {
+                b'-' => FlagEffect::Unaffected,
+                b'0' => FlagEffect::Reset,
+                b'1' => FlagEffect::Set,
+                _ => FlagEffect::Affected,
+            };
+            i += 1;
+        }
+        Opcode {
+            mnemonic,
+            operands,
+            length,
+            cycles,
+            cycles_taken,
+            flags: effects,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str { self.mnemonic }
+    pub fn operands(&self) -> &'static [Operand] { self.operands }
+    /// The length in bytes, including opcode and prefix
+    pub fn length(&self) -> u8 { self.length }
+    pub fn flags(&self) -> [FlagEffect; 4] { self.flags }
+
+    /// The cycles the instruction takes. `taken` only matters for conditional instructions
+    /// (and STOP, which takes longer when it switches the CGB speed).
+    pub fn cycles(&self, taken: bool) -> u32 {
+        if taken { self.cycles_taken as u32 } else { self.cycles as u32 }
+    }
+
+    /// Does the instruction take a different amount of cycles depending on a condition?
+    pub fn is_conditional(&self) -> bool {
+        self.cycles != self.cycles_taken
+    }
+
+    /// The immediate value in the bytes of the instruction (starting with the opcode)
+    pub fn immediate(&self, bytes: &[u8]) -> Option<u16> {
+        match self.length {
+            2 if !self.operands.iter().any(Operand::is_immediate) => None,
+            2 => bytes.get(1).map(|b| *b as u16),
+            3 => bytes.get(1).zip(bytes.get(2)).map(|(l, h)| u16::from_le_bytes([*l, *h])),
+            _ => None,
+        }
+    }
+
+    /// One of the opcodes, that lock up the CPU
+    pub fn is_illegal(&self) -> bool {
+        self.mnemonic == "ILLEGAL"
+    }
+}
+
+/// Look up the opcode at the start of `bytes`. Prefixed opcodes need the second byte, `None` if
+/// it is missing.
+pub fn decode(bytes: &[u8]) -> Option<&'static Opcode> {
+    match bytes {
+        [PREFIX, cb, ..] => Some(&CB_OPCODES[*cb as usize]),
+        [PREFIX] | [] => None,
+        [opcode, ..] => Some(&OPCODES[*opcode as usize]),
+    }
+}
+
+/// The unprefixed opcodes
+pub static OPCODES: [Opcode; 256] = [
+    /* 0x00 */ Opcode::new("NOP", &[], 1, 1, 1, b"----"),
+    /* 0x01 */ Opcode::new("LD", &[BC, Imm16], 3, 3, 3, b"----"),
+    /* 0x02 */ Opcode::new("LD", &[IndBC, A], 1, 2, 2, b"----"),
+    /* 0x03 */ Opcode::new("INC", &[BC], 1, 2, 2, b"----"),
+    /* 0x04 */ Opcode::new("INC", &[B], 1, 1, 1, b"Z0H-"),
+    /* 0x05 */ Opcode::new("DEC", &[B], 1, 1, 1, b"Z1H-"),
+    /* 0x06 */ Opcode::new("LD", &[B, Imm8], 2, 2, 2, b"----"),
+    /* 0x07 */ Opcode::new("RLCA", &[], 1, 1, 1, b"000C"),
+    /* 0x08 */ Opcode::new("LD", &[Addr16, SP], 3, 5, 5, b"----"),
+    /* 0x09 */ Opcode::new("ADD", &[HL, BC], 1, 2, 2, b"-0HC"),
+    /* 0x0A */ Opcode::new("LD", &[A, IndBC], 1, 2, 2, b"----"),
+    /* 0x0B */ Opcode::new("DEC", &[BC], 1, 2, 2, b"----"),
+    /* 0x0C */ Opcode::new("INC", &[C], 1, 1, 1, b"Z0H-"),
+    /* 0x0D */ Opcode::new("DEC", &[C], 1, 1, 1, b"Z1H-"),
+    /* 0x0E */ Opcode::new("LD", &[C, Imm8], 2, 2, 2, b"----"),
+    /* 0x0F */ Opcode::new("RRCA", &[], 1, 1, 1, b"000C"),
+    /* 0x10 */ Opcode::new("STOP", &[], 2, 1, 2050, b"----"),
+    /* 0x11 */ Opcode::new("LD", &[DE, Imm16], 3, 3, 3, b"----"),
+    /* 0x12 */ Opcode::new("LD", &[IndDE, A], 1, 2, 2, b"----"),
+    /* 0x13 */ Opcode::new("INC", &[DE], 1, 2, 2, b"----"),
+    /* 0x14 */ Opcode::new("INC", &[D], 1, 1, 1, b"Z0H-"),
+    /* 0x15 */ Opcode::new("DEC", &[D], 1, 1, 1, b"Z1H-"),
+    /* 0x16 */ Opcode::new("LD", &[D, Imm8], 2, 2, 2, b"----"),
+    /* 0x17 */ Opcode::new("RLA", &[], 1, 1, 1, b"000C"),
+    /* 0x18 */ Opcode::new("JR", &[Relative], 2, 3, 3, b"----"),
+    /* 0x19 */ Opcode::new("ADD", &[HL, DE], 1, 2, 2, b"-0HC"),
+    /* 0x1A */ Opcode::new("LD", &[A, IndDE], 1, 2, 2, b"----"),
+    /* 0x1B */ Opcode::new("DEC", &[DE], 1, 2, 2, b"----"),
+    /* 0x1C */ Opcode::new("INC", &[E], 1, 1, 1, b"Z0H-"),
+    /* 0x1D */ Opcode::new("DEC", &[E], 1, 1, 1, b"Z1H-"),
+    /* 0x1E */ Opcode::new("LD", &[E, Imm8], 2, 2, 2, b"----"),
+    /* 0x1F */ Opcode::new("RRA", &[], 1, 1, 1, b"000C"),
+    /* 0x20 */ Opcode::new("JR", &[CondNZ, Relative], 2, 2, 3, b"----"),
+    /* 0x21 */ Opcode::new("LD", &[HL, Imm16], 3, 3, 3, b"----"),
+    /* 0x22 */ Opcode::new("LD", &[IndHLInc, A], 1, 2, 2, b"----"),
+    /* 0x23 */ Opcode::new("INC", &[HL], 1, 2, 2, b"----"),
+    /* 0x24 */ Opcode::new("INC", &[H], 1, 1, 1, b"Z0H-"),
+    /* 0x25 */ Opcode::new("DEC", &[H], 1, 1, 1, b"Z1H-"),
+    /* 0x26 */ Opcode::new("LD", &[H, Imm8], 2, 2, 2, b"----"),
+    /* 0x27 */ Opcode::new("DAA", &[], 1, 1, 1, b"Z-0C"),
+    /* 0x28 */ Opcode::new("JR", &[CondZ, Relative], 2, 2, 3, b"----"),
+    /* 0x29 */ Opcode::new("ADD", &[HL, HL], 1, 2, 2, b"-0HC"),
+    /* 0x2A */ Opcode::new("LD", &[A, IndHLInc], 1, 2, 2, b"----"),
+    /* 0x2B */ Opcode::new("DEC", &[HL], 1, 2, 2, b"----"),
+    /* 0x2C */ Opcode::new("INC", &[L], 1, 1, 1, b"Z0H-"),
+    /* 0x2D */ Opcode::new("DEC", &[L], 1, 1, 1, b"Z1H-"),
+    /* 0x2E */ Opcode::new("LD", &[L, Imm8], 2, 2, 2, b"----"),
+    /* 0x2F */ Opcode::new("CPL", &[], 1, 1, 1, b"-11-"),
+    /* 0x30 */ Opcode::new("JR", &[CondNC, Relative], 2, 2, 3, b"----"),
+    /* 0x31 */ Opcode::new("LD", &[SP, Imm16], 3, 3, 3, b"----"),
+    /* 0x32 */ Opcode::new("LD", &[IndHLDec, A], 1, 2, 2, b"----"),
+    /* 0x33 */ Opcode::new("INC", &[SP], 1, 2, 2, b"----"),
+    /* 0x34 */ Opcode::new("INC", &[IndHL], 1, 3, 3, b"Z0H-"),
+    /* 0x35 */ Opcode::new("DEC", &[IndHL], 1, 3, 3, b"Z1H-"),
+    /* 0x36 */ Opcode::new("LD", &[IndHL, Imm8], 2, 3, 3, b"----"),
+    /* 0x37 */ Opcode::new("SCF", &[], 1, 1, 1, b"-001"),
+    /* 0x38 */ Opcode::new("JR", &[CondC, Relative], 2, 2, 3, b"----"),
+    /* 0x39 */ Opcode::new("ADD", &[HL, SP], 1, 2, 2, b"-0HC"),
+    /* 0x3A */ Opcode::new("LD", &[A, IndHLDec], 1, 2, 2, b"----"),
+    /* 0x3B */ Opcode::new("DEC", &[SP], 1, 2, 2, b"----"),
+    /* 0x3C */ Opcode::new("INC", &[A], 1, 1, 1, b"Z0H-"),
+    /* 0x3D */ Opcode::new("DEC", &[A], 1, 1, 1, b"Z1H-"),
+    /* 0x3E */ Opcode::new("LD", &[A, Imm8], 2, 2, 2, b"----"),
+    /* 0x3F */ Opcode::new("CCF", &[], 1, 1, 1, b"-00C"),
+    /* 0x40 */ Opcode::new("LD", &[B, B], 1, 1, 1, b"----"),
+    /* 0x41 */ Opcode::new("LD", &[B, C], 1, 1, 1, b"----"),
+    /* 0x42 */ Opcode::new("LD", &[B, D], 1, 1, 1, b"----"),
+    /* 0x43 */ Opcode::new("LD", &[B, E], 1, 1, 1, b"----"),
+    /* 0x44 */ Opcode::new("LD", &[B, H], 1, 1, 1, b"----"),
+    /* 0x45 */ Opcode::new("LD", &[B, L], 1, 1, 1, b"----"),
+    /* 0x46 */ Opcode::new("LD", &[B, IndHL], 1, 2, 2, b"----"),
+    /* 0x47 */ Opcode::new("LD", &[B, A], 1, 1, 1, b"----"),
+    /* 0x48 */ Opcode::new("LD", &[C, B], 1, 1, 1, b"----"),
+    /* 0x49 */ Opcode::new("LD", &[C, C], 1, 1, 1, b"----"),
+    /* 0x4A */ Opcode::new("LD", &[C, D], 1, 1, 1, b"----"),
+    /* 0x4B */ Opcode::new("LD", &[C, E], 1, 1, 1, b"----"),
+    /* 0x4C */ Opcode::new("LD", &[C, H], 1, 1, 1, b"----"),
+    /* 0x4D */ Opcode::new("LD", &[C, L], 1, 1, 1, b"----"),
+    /* 0x4E */ Opcode::new("LD", &[C, IndHL], 1, 2, 2, b"----"),
+    /* 0x4F */ Opcode::new("LD", &[C, A], 1, 1, 1, b"----"),
+    /* 0x50 */ Opcode::new("LD", &[D, B], 1, 1, 1, b"----"),
+    /* 0x51 */ Opcode::new("LD", &[D, C], 1, 1, 1, b"----"),
+    /* 0x52 */ Opcode::new("LD", &[D, D], 1, 1, 1, b"----"),
+    /* 0x53 */ Opcode::new("LD", &[D, E], 1, 1, 1, b"----"),
+    /* 0x54 */ Opcode::new("LD", &[D, H], 1, 1, 1, b"----"),
+    /* 0x55 */ Opcode::new("LD", &[D, L], 1, 1, 1, b"----"),
+    /* 0x56 */ Opcode::new("LD", &[D, IndHL], 1, 2, 2, b"----"),
+    /* 0x57 */ Opcode::new("LD", &[D, A], 1, 1, 1, b"----"),
+    /* 0x58 */ Opcode::new("LD", &[E, B], 1, 1, 1, b"----"),
+    /* 0x59 */ Opcode::new("LD", &[E, C], 1, 1, 1, b"----"),
+    /* 0x5A */ Opcode::new("LD", &[E, D], 1, 1, 1, b"----"),
+    /* 0x5B */ Opcode::new("LD", &[E, E], 1, 1, 1, b"----"),
+    /* 0x5C */ Opcode::new("LD", &[E, H], 1, 1, 1, b"----"),
+    /* 0x5D */ Opcode::new("LD", &[E, L], 1, 1, 1, b"----"),
+    /* 0x5E */ Opcode::new("LD", &[E, IndHL], 1, 2, 2, b"----"),
+    /* 0x5F */ Opcode::new("LD", &[E, A], 1, 1, 1, b"----"),
+    /* 0x60 */ Opcode::new("LD", &[H, B], 1, 1, 1, b"----"),
+    /* 0x61 */ Opcode::new("LD", &[H, C], 1, 1, 1, b"----"),
+    /* 0x62 */ Opcode::new("LD", &[H, D], 1, 1, 1, b"----"),
+    /* 0x63 */ Opcode::new("LD", &[H, E], 1, 1, 1, b"----"),
+    /* 0x64 */ Opcode::new("LD", &[H, H], 1, 1, 1, b"----"),
+    /* 0x65 */ Opcode::new("LD", &[H, L], 1, 1, 1, b"----"),
+    /* 0x66 */ Opcode::new("LD", &[H, IndHL], 1, 2, 2, b"----"),
+    /* 0x67 */ Opcode::new("LD", &[H, A], 1, 1, 1, b"----"),
+    /* 0x68 */ Opcode::new("LD", &[L, B], 1, 1, 1, b"----"),
+    /* 0x69 */ Opcode::new("LD", &[L, C], 1, 1, 1, b"----"),
+    /* 0x6A */ Opcode::new("LD", &[L, D], 1, 1, 1, b"----"),
+    /* 0x6B */ Opcode::new("LD", &[L, E], 1, 1, 1, b"----"),
+    /* 0x6C */ Opcode::new("LD", &[L, H], 1, 1, 1, b"----"),
+    /* 0x6D */ Opcode::new("LD", &[L, L], 1, 1, 1, b"----"),
+    /* 0x6E */ Opcode::new("LD", &[L, IndHL], 1, 2, 2, b"----"),
+    /* 0x6F */ Opcode::new("LD", &[L, A], 1, 1, 1, b"----"),
+    /* 0x70 */ Opcode::new("LD", &[IndHL, B], 1, 2, 2, b"----"),
+    /* 0x71 */ Opcode::new("LD", &[IndHL, C], 1, 2, 2, b"----"),
+    /* 0x72 */ Opcode::new("LD", &[IndHL, D], 1, 2, 2, b"----"),
+    /* 0x73 */ Opcode::new("LD", &[IndHL, E], 1, 2, 2, b"----"),
+    /* 0x74 */ Opcode::new("LD", &[IndHL, H], 1, 2, 2, b"----"),
+    /* 0x75 */ Opcode::new("LD", &[IndHL, L], 1, 2, 2, b"----"),
+    /* 0x76 */ Opcode::new("HALT", &[], 1, 1, 1, b"----"),
+    /* 0x77 */ Opcode::new("LD", &[IndHL, A], 1, 2, 2, b"----"),
+    /* 0x78 */ Opcode::new("LD", &[A, B], 1, 1, 1, b"----"),
+    /* 0x79 */ Opcode::new("LD", &[A, C], 1, 1, 1, b"----"),
+    /* 0x7A */ Opcode::new("LD", &[A, D], 1, 1, 1, b"----"),
+    /* 0x7B */ Opcode::new("LD", &[A, E], 1, 1, 1, b"----"),
+    /* 0x7C */ Opcode::new("LD", &[A, H], 1, 1, 1, b"----"),
+    /* 0x7D */ Opcode::new("LD", &[A, L], 1, 1, 1, b"----"),
+    /* 0x7E */ Opcode::new("LD", &[A, IndHL], 1, 2, 2, b"----"),
+    /* 0x7F */ Opcode::new("LD", &[A, A], 1, 1, 1, b"----"),
+    /* 0x80 */ Opcode::new("ADD", &[A, B], 1, 1, 1, b"Z0HC"),
+    /* 0x81 */ Opcode::new("ADD", &[A, C], 1, 1, 1, b"Z0HC"),
+    /* 0x82 */ Opcode::new("ADD", &[A, D], 1, 1, 1, b"Z0HC"),
+    /* 0x83 */ Opcode::new("ADD", &[A, E], 1, 1, 1, b"Z0HC"),
+    /* 0x84 */ Opcode::new("ADD", &[A, H], 1, 1, 1, b"Z0HC"),
+    /* 0x85 */ Opcode::new("ADD", &[A, L], 1, 1, 1, b"Z0HC"),
+    /* 0x86 */ Opcode::new("ADD", &[A, IndHL], 1, 2, 2, b"Z0HC"),
+    /* 0x87 */ Opcode::new("ADD", &[A, A], 1, 1, 1, b"Z0HC"),
+    /* 0x88 */ Opcode::new("ADC", &[A, B], 1, 1, 1, b"Z0HC"),
+    /* 0x89 */ Opcode::new("ADC", &[A, C], 1, 1, 1, b"Z0HC"),
+    /* 0x8A */ Opcode::new("ADC", &[A, D], 1, 1, 1, b"Z0HC"),
+    /* 0x8B */ Opcode::new("ADC", &[A, E], 1, 1, 1, b"Z0HC"),
+    /* 0x8C */ Opcode::new("ADC", &[A, H], 1, 1, 1, b"Z0HC"),
+    /* 0x8D */ Opcode::new("ADC", &[A, L], 1, 1, 1, b"Z0HC"),
+    /* 0x8E */ Opcode::new("ADC", &[A, IndHL], 1, 2, 2, b"Z0HC"),
+    /* 0x8F */ Opcode::new("ADC", &[A, A], 1, 1, 1, b"Z0HC"),
+    /* 0x90 */ Opcode::new("SUB", &[A, B], 1, 1, 1, b"Z1HC"),
+    /* 0x91 */ Opcode::new("SUB", &[A, C], 1, 1, 1, b"Z1HC"),
+    /* 0x92 */ Opcode::new("SUB", &[A, D], 1, 1, 1, b"Z1HC"),
+    /* 0x93 */ Opcode::new("SUB", &[A, E], 1, 1, 1, b"Z1HC"),
+    /* 0x94 */ Opcode::new("SUB", &[A, H], 1, 1, 1, b"Z1HC"),
+    /* 0x95 */ Opcode::new("SUB", &[A, L], 1, 1, 1, b"Z1HC"),
+    /* 0x96 */ Opcode::new("SUB", &[A, IndHL], 1, 2, 2, b"Z1HC"),
+    /* 0x97 */ Opcode::new("SUB", &[A, A], 1, 1, 1, b"Z1HC"),
+    /* 0x98 */ Opcode::new("SBC", &[A, B], 1, 1, 1, b"Z1HC"),
+    /* 0x99 */ Opcode::new("SBC", &[A, C], 1, 1, 1, b"Z1HC"),
+    /* 0x9A */ Opcode::new("SBC", &[A, D], 1, 1, 1, b"Z1HC"),
+    /* 0x9B */ Opcode::new("SBC", &[A, E], 1, 1, 1, b"Z1HC"),
+    /* 0x9C */ Opcode::new("SBC", &[A, H], 1, 1, 1, b"Z1HC"),
+    /* 0x9D */ Opcode::new("SBC", &[A, L], 1, 1, 1, b"Z1HC"),
+    /* 0x9E */ Opcode::new("SBC", &[A, IndHL], 1, 2, 2, b"Z1HC"),
+    /* 0x9F */ Opcode::new("SBC", &[A, A], 1, 1, 1, b"Z1HC"),
+    /* 0xA0 */ Opcode::new("AND", &[A, B], 1, 1, 1, b"Z010"),
+    /* 0xA1 */ Opcode::new("AND", &[A, C], 1, 1, 1, b"Z010"),
+    /* 0xA2 */ Opcode::new("AND", &[A, D], 1, 1, 1, b"Z010"),
+    /* 0xA3 */ Opcode::new("AND", &[A, E], 1, 1, 1, b"Z010"),
+    /* 0xA4 */ Opcode::new("AND", &[A, H], 1, 1, 1, b"Z010"),
+    /* 0xA5 */ Opcode::new("AND", &[A, L], 1, 1, 1, b"Z010"),
+    /* 0xA6 */ Opcode::new("AND", &[A, IndHL], 1, 2, 2, b"Z010"),
+    /* 0xA7 */ Opcode::new("AND", &[A, A], 1, 1, 1, b"Z010"),
+    /* 0xA8 */ Opcode::new("XOR", &[A, B], 1, 1, 1, b"Z000"),
+    /* 0xA9 */ Opcode::new("XOR", &[A, C], 1, 1, 1, b"Z000"),
+    /* 0xAA */ Opcode::new("XOR", &[A, D], 1, 1, 1, b"Z000"),
+    /* 0xAB */ Opcode::new("XOR", &[A, E], 1, 1, 1, b"Z000"),
+    /* 0xAC */ Opcode::new("XOR", &[A, H], 1, 1, 1, b"Z000"),
+    /* 0xAD */ Opcode::new("XOR", &[A, L], 1, 1, 1, b"Z000"),
+    /* 0xAE */ Opcode::new("XOR", &[A, IndHL], 1, 2, 2, b"Z000"),
+    /* 0xAF */ Opcode::new("XOR", &[A, A], 1, 1, 1, b"Z000"),
+    /* 0xB0 */ Opcode::new("OR", &[A, B], 1, 1, 1, b"Z000"),
+    /* 0xB1 */ Opcode::new("OR", &[A, C], 1, 1, 1, b"Z000"),
+    /* 0xB2 */ Opcode::new("OR", &[A, D], 1, 1, 1, b"Z000"),
+    /* 0xB3 */ Opcode::new("OR", &[A, E], 1, 1, 1, b"Z000"),
+    /* 0xB4 */ Opcode::new("OR", &[A, H], 1, 1, 1, b"Z000"),
+    /* 0xB5 */ Opcode::new("OR", &[A, L], 1, 1, 1, b"Z000"),
+    /* 0xB6 */ Opcode::new("OR", &[A, IndHL], 1, 2, 2, b"Z000"),
+    /* 0xB7 */ Opcode::new("OR", &[A, A], 1, 1, 1, b"Z000"),
+    /* 0xB8 */ Opcode::new("CP", &[A, B], 1, 1, 1, b"Z1HC"),
+    /* 0xB9 */ Opcode::new("CP", &[A, C], 1, 1, 1, b"Z1HC"),
+    /* 0xBA */ Opcode::new("CP", &[A, D], 1, 1, 1, b"Z1HC"),
+    /* 0xBB */ Opcode::new("CP", &[A, E], 1, 1, 1, b"Z1HC"),
+    /* 0xBC */ Opcode::new("CP", &[A, H], 1, 1, 1, b"Z1HC"),
+    /* 0xBD */ Opcode::new("CP", &[A, L], 1, 1, 1, b"Z1HC"),
+    /* 0xBE */ Opcode::new("CP", &[A, IndHL], 1, 2, 2, b"Z1HC"),
+    /* 0xBF */ Opcode::new("CP", &[A, A], 1, 1, 1, b"Z1HC"),
+    /* 0xC0 */ Opcode::new("RET", &[CondNZ], 1, 2, 5, b"----"),
+    /* 0xC1 */ Opcode::new("POP", &[BC], 1, 3, 3, b"----"),
+    /* 0xC2 */ Opcode::new("JP", &[CondNZ, Imm16], 3, 3, 4, b"----"),
+    /* 0xC3 */ Opcode::new("JP", &[Imm16], 3, 4, 4, b"----"),
+    /* 0xC4 */ Opcode::new("CALL", &[CondNZ, Imm16], 3, 3, 6, b"----"),
+    /* 0xC5 */ Opcode::new("PUSH", &[BC], 1, 4, 4, b"----"),
+    /* 0xC6 */ Opcode::new("ADD", &[A, Imm8], 2, 2, 2, b"Z0HC"),
+    /* 0xC7 */ Opcode::new("RST", &[RstVec(0x00)], 1, 4, 4, b"----"),
+    /* 0xC8 */ Opcode::new("RET", &[CondZ], 1, 2, 5, b"----"),
+    /* 0xC9 */ Opcode::new("RET", &[], 1, 4, 4, b"----"),
+    /* 0xCA */ Opcode::new("JP", &[CondZ, Imm16], 3, 3, 4, b"----"),
+    /* 0xCB */ Opcode::new("PREFIX", &[], 1, 1, 1, b"----"),
+    /* 0xCC */ Opcode::new("CALL", &[CondZ, Imm16], 3, 3, 6, b"----"),
+    /* 0xCD */ Opcode::new("CALL", &[Imm16], 3, 6, 6, b"----"),
+    /* 0xCE */ Opcode::new("ADC", &[A, Imm8], 2, 2, 2, b"Z0HC"),
+    /* 0xCF */ Opcode::new("RST", &[RstVec(0x08)], 1, 4, 4, b"----"),
+    /* 0xD0 */ Opcode::new("RET", &[CondNC], 1, 2, 5, b"----"),
+    /* 0xD1 */ Opcode::new("POP", &[DE], 1, 3, 3, b"----"),
+    /* 0xD2 */ Opcode::new("JP", &[CondNC, Imm16], 3, 3, 4, b"----"),
+    /* 0xD3 */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xD4 */ Opcode::new("CALL", &[CondNC, Imm16], 3, 3, 6, b"----"),
+    /* 0xD5 */ Opcode::new("PUSH", &[DE], 1, 4, 4, b"----"),
+    /* 0xD6 */ Opcode::new("SUB", &[A, Imm8], 2, 2, 2, b"Z1HC"),
+    /* 0xD7 */ Opcode::new("RST", &[RstVec(0x10)], 1, 4, 4, b"----"),
+    /* 0xD8 */ Opcode::new("RET", &[CondC], 1, 2, 5, b"----"),
+    /* 0xD9 */ Opcode::new("RETI", &[], 1, 4, 4, b"----"),
+    /* 0xDA */ Opcode::new("JP", &[CondC, Imm16], 3, 3, 4, b"----"),
+    /* 0xDB */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xDC */ Opcode::new("CALL", &[CondC, Imm16], 3, 3, 6, b"----"),
+    /* 0xDD */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xDE */ Opcode::new("SBC", &[A, Imm8], 2, 2, 2, b"Z1HC"),
+    /* 0xDF */ Opcode::new("RST", &[RstVec(0x18)], 1, 4, 4, b"----"),
+    /* 0xE0 */ Opcode::new("LDH", &[HighAddr8, A], 2, 3, 3, b"----"),
+    /* 0xE1 */ Opcode::new("POP", &[HL], 1, 3, 3, b"----"),
+    /* 0xE2 */ Opcode::new("LDH", &[HighC, A], 1, 2, 2, b"----"),
+    /* 0xE3 */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xE4 */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xE5 */ Opcode::new("PUSH", &[HL], 1, 4, 4, b"----"),
+    /* 0xE6 */ Opcode::new("AND", &[A, Imm8], 2, 2, 2, b"Z010"),
+    /* 0xE7 */ Opcode::new("RST", &[RstVec(0x20)], 1, 4, 4, b"----"),
+    /* 0xE8 */ Opcode::new("ADD", &[SP, Offset8], 2, 4, 4, b"00HC"),
+    /* 0xE9 */ Opcode::new("JP", &[HL], 1, 1, 1, b"----"),
+    /* 0xEA */ Opcode::new("LD", &[Addr16, A], 3, 4, 4, b"----"),
+    /* 0xEB */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xEC */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xED */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xEE */ Opcode::new("XOR", &[A, Imm8], 2, 2, 2, b"Z000"),
+    /* 0xEF */ Opcode::new("RST", &[RstVec(0x28)], 1, 4, 4, b"----"),
+    /* 0xF0 */ Opcode::new("LDH", &[A, HighAddr8], 2, 3, 3, b"----"),
+    /* 0xF1 */ Opcode::new("POP", &[AF], 1, 3, 3, b"ZNHC"),
+    /* 0xF2 */ Opcode::new("LDH", &[A, HighC], 1, 2, 2, b"----"),
+    /* 0xF3 */ Opcode::new("DI", &[], 1, 1, 1, b"----"),
+    /* 0xF4 */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xF5 */ Opcode::new("PUSH", &[AF], 1, 4, 4, b"----"),
+    /* 0xF6 */ Opcode::new("OR", &[A, Imm8], 2, 2, 2, b"Z000"),
+    /* 0xF7 */ Opcode::new("RST", &[RstVec(0x30)], 1, 4, 4, b"----"),
+    /* 0xF8 */ Opcode::new("LD", &[HL, SpOffset], 2, 3, 3, b"00HC"),
+    /* 0xF9 */ Opcode::new("LD", &[SP, HL], 1, 2, 2, b"----"),
+    /* 0xFA */ Opcode::new("LD", &[A, Addr16], 3, 4, 4, b"----"),
+    /* 0xFB */ Opcode::new("EI", &[], 1, 1, 1, b"----"),
+    /* 0xFC */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xFD */ Opcode::new("ILLEGAL", &[], 1, 1, 1, b"----"),
+    /* 0xFE */ Opcode::new("CP", &[A, Imm8], 2, 2, 2, b"Z1HC"),
+    /* 0xFF */ Opcode::new("RST", &[RstVec(0x38)], 1, 4, 4, b"----"),
+];
+
+/// The opcodes following the `$CB` prefix. Length and cycles include the prefix.
+pub static CB_OPCODES: [Opcode; 256] = [
+    /* 0x00 */ Opcode::new("RLC", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x01 */ Opcode::new("RLC", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x02 */ Opcode::new("RLC", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x03 */ Opcode::new("RLC", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x04 */ Opcode::new("RLC", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x05 */ Opcode::new("RLC", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x06 */ Opcode::new("RLC", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x07 */ Opcode::new("RLC", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x08 */ Opcode::new("RRC", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x09 */ Opcode::new("RRC", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x0A */ Opcode::new("RRC", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x0B */ Opcode::new("RRC", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x0C */ Opcode::new("RRC", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x0D */ Opcode::new("RRC", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x0E */ Opcode::new("RRC", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x0F */ Opcode::new("RRC", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x10 */ Opcode::new("RL", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x11 */ Opcode::new("RL", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x12 */ Opcode::new("RL", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x13 */ Opcode::new("RL", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x14 */ Opcode::new("RL", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x15 */ Opcode::new("RL", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x16 */ Opcode::new("RL", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x17 */ Opcode::new("RL", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x18 */ Opcode::new("RR", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x19 */ Opcode::new("RR", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x1A */ Opcode::new("RR", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x1B */ Opcode::new("RR", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x1C */ Opcode::new("RR", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x1D */ Opcode::new("RR", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x1E */ Opcode::new("RR", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x1F */ Opcode::new("RR", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x20 */ Opcode::new("SLA", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x21 */ Opcode::new("SLA", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x22 */ Opcode::new("SLA", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x23 */ Opcode::new("SLA", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x24 */ Opcode::new("SLA", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x25 */ Opcode::new("SLA", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x26 */ Opcode::new("SLA", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x27 */ Opcode::new("SLA", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x28 */ Opcode::new("SRA", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x29 */ Opcode::new("SRA", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x2A */ Opcode::new("SRA", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x2B */ Opcode::new("SRA", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x2C */ Opcode::new("SRA", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x2D */ Opcode::new("SRA", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x2E */ Opcode::new("SRA", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x2F */ Opcode::new("SRA", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x30 */ Opcode::new("SWAP", &[B], 2, 2, 2, b"Z000"),
+    /* 0x31 */ Opcode::new("SWAP", &[C], 2, 2, 2, b"Z000"),
+    /* 0x32 */ Opcode::new("SWAP", &[D], 2, 2, 2, b"Z000"),
+    /* 0x33 */ Opcode::new("SWAP", &[E], 2, 2, 2, b"Z000"),
+    /* 0x34 */ Opcode::new("SWAP", &[H], 2, 2, 2, b"Z000"),
+    /* 0x35 */ Opcode::new("SWAP", &[L], 2, 2, 2, b"Z000"),
+    /* 0x36 */ Opcode::new("SWAP", &[IndHL], 2, 4, 4, b"Z000"),
+    /* 0x37 */ Opcode::new("SWAP", &[A], 2, 2, 2, b"Z000"),
+    /* 0x38 */ Opcode::new("SRL", &[B], 2, 2, 2, b"Z00C"),
+    /* 0x39 */ Opcode::new("SRL", &[C], 2, 2, 2, b"Z00C"),
+    /* 0x3A */ Opcode::new("SRL", &[D], 2, 2, 2, b"Z00C"),
+    /* 0x3B */ Opcode::new("SRL", &[E], 2, 2, 2, b"Z00C"),
+    /* 0x3C */ Opcode::new("SRL", &[H], 2, 2, 2, b"Z00C"),
+    /* 0x3D */ Opcode::new("SRL", &[L], 2, 2, 2, b"Z00C"),
+    /* 0x3E */ Opcode::new("SRL", &[IndHL], 2, 4, 4, b"Z00C"),
+    /* 0x3F */ Opcode::new("SRL", &[A], 2, 2, 2, b"Z00C"),
+    /* 0x40 */ Opcode::new("BIT", &[Bit(0), B], 2, 2, 2, b"Z01-"),
+    /* 0x41 */ Opcode::new("BIT", &[Bit(0), C], 2, 2, 2, b"Z01-"),
+    /* 0x42 */ Opcode::new("BIT", &[Bit(0), D], 2, 2, 2, b"Z01-"),
+    /* 0x43 */ Opcode::new("BIT", &[Bit(0), E], 2, 2, 2, b"Z01-"),
+    /* 0x44 */ Opcode::new("BIT", &[Bit(0), H], 2, 2, 2, b"Z01-"),
+    /* 0x45 */ Opcode::new("BIT", &[Bit(0), L], 2, 2, 2, b"Z01-"),
+    /* 0x46 */ Opcode::new("BIT", &[Bit(0), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x47 */ Opcode::new("BIT", &[Bit(0), A], 2, 2, 2, b"Z01-"),
+    /* 0x48 */ Opcode::new("BIT", &[Bit(1), B], 2, 2, 2, b"Z01-"),
+    /* 0x49 */ Opcode::new("BIT", &[Bit(1), C], 2, 2, 2, b"Z01-"),
+    /* 0x4A */ Opcode::new("BIT", &[Bit(1), D], 2, 2, 2, b"Z01-"),
+    /* 0x4B */ Opcode::new("BIT", &[Bit(1), E], 2, 2, 2, b"Z01-"),
+    /* 0x4C */ Opcode::new("BIT", &[Bit(1), H], 2, 2, 2, b"Z01-"),
+    /* 0x4D */ Opcode::new("BIT", &[Bit(1), L], 2, 2, 2, b"Z01-"),
+    /* 0x4E */ Opcode::new("BIT", &[Bit(1), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x4F */ Opcode::new("BIT", &[Bit(1), A], 2, 2, 2, b"Z01-"),
+    /* 0x50 */ Opcode::new("BIT", &[Bit(2), B], 2, 2, 2, b"Z01-"),
+    /* 0x51 */ Opcode::new("BIT", &[Bit(2), C], 2, 2, 2, b"Z01-"),
+    /* 0x52 */ Opcode::new("BIT", &[Bit(2), D], 2, 2, 2, b"Z01-"),
+    /* 0x53 */ Opcode::new("BIT", &[Bit(2), E], 2, 2, 2, b"Z01-"),
+    /* 0x54 */ Opcode::new("BIT", &[Bit(2), H], 2, 2, 2, b"Z01-"),
+    /* 0x55 */ Opcode::new("BIT", &[Bit(2), L], 2, 2, 2, b"Z01-"),
+    /* 0x56 */ Opcode::new("BIT", &[Bit(2), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x57 */ Opcode::new("BIT", &[Bit(2), A], 2, 2, 2, b"Z01-"),
+    /* 0x58 */ Opcode::new("BIT", &[Bit(3), B], 2, 2, 2, b"Z01-"),
+    /* 0x59 */ Opcode::new("BIT", &[Bit(3), C], 2, 2, 2, b"Z01-"),
+    /* 0x5A */ Opcode::new("BIT", &[Bit(3), D], 2, 2, 2, b"Z01-"),
+    /* 0x5B */ Opcode::new("BIT", &[Bit(3), E], 2, 2, 2, b"Z01-"),
+    /* 0x5C */ Opcode::new("BIT", &[Bit(3), H], 2, 2, 2, b"Z01-"),
+    /* 0x5D */ Opcode::new("BIT", &[Bit(3), L], 2, 2, 2, b"Z01-"),
+    /* 0x5E */ Opcode::new("BIT", &[Bit(3), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x5F */ Opcode::new("BIT", &[Bit(3), A], 2, 2, 2, b"Z01-"),
+    /* 0x60 */ Opcode::new("BIT", &[Bit(4), B], 2, 2, 2, b"Z01-"),
+    /* 0x61 */ Opcode::new("BIT", &[Bit(4), C], 2, 2, 2, b"Z01-"),
+    /* 0x62 */ Opcode::new("BIT", &[Bit(4), D], 2, 2, 2, b"Z01-"),
+    /* 0x63 */ Opcode::new("BIT", &[Bit(4), E], 2, 2, 2, b"Z01-"),
+    /* 0x64 */ Opcode::new("BIT", &[Bit(4), H], 2, 2, 2, b"Z01-"),
+    /* 0x65 */ Opcode::new("BIT", &[Bit(4), L], 2, 2, 2, b"Z01-"),
+    /* 0x66 */ Opcode::new("BIT", &[Bit(4), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x67 */ Opcode::new("BIT", &[Bit(4), A], 2, 2, 2, b"Z01-"),
+    /* 0x68 */ Opcode::new("BIT", &[Bit(5), B], 2, 2, 2, b"Z01-"),
+    /* 0x69 */ Opcode::new("BIT", &[Bit(5), C], 2, 2, 2, b"Z01-"),
+    /* 0x6A */ Opcode::new("BIT", &[Bit(5), D], 2, 2, 2, b"Z01-"),
+    /* 0x6B */ Opcode::new("BIT", &[Bit(5), E], 2, 2, 2, b"Z01-"),
+    /* 0x6C */ Opcode::new("BIT", &[Bit(5), H], 2, 2, 2, b"Z01-"),
+    /* 0x6D */ Opcode::new("BIT", &[Bit(5), L], 2, 2, 2, b"Z01-"),
+    /* 0x6E */ Opcode::new("BIT", &[Bit(5), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x6F */ Opcode::new("BIT", &[Bit(5), A], 2, 2, 2, b"Z01-"),
+    /* 0x70 */ Opcode::new("BIT", &[Bit(6), B], 2, 2, 2, b"Z01-"),
+    /* 0x71 */ Opcode::new("BIT", &[Bit(6), C], 2, 2, 2, b"Z01-"),
+    /* 0x72 */ Opcode::new("BIT", &[Bit(6), D], 2, 2, 2, b"Z01-"),
+    /* 0x73 */ Opcode::new("BIT", &[Bit(6), E], 2, 2, 2, b"Z01-"),
+    /* 0x74 */ Opcode::new("BIT", &[Bit(6), H], 2, 2, 2, b"Z01-"),
+    /* 0x75 */ Opcode::new("BIT", &[Bit(6), L], 2, 2, 2, b"Z01-"),
+    /* 0x76 */ Opcode::new("BIT", &[Bit(6), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x77 */ Opcode::new("BIT", &[Bit(6), A], 2, 2, 2, b"Z01-"),
+    /* 0x78 */ Opcode::new("BIT", &[Bit(7), B], 2, 2, 2, b"Z01-"),
+    /* 0x79 */ Opcode::new("BIT", &[Bit(7), C], 2, 2, 2, b"Z01-"),
+    /* 0x7A */ Opcode::new("BIT", &[Bit(7), D], 2, 2, 2, b"Z01-"),
+    /* 0x7B */ Opcode::new("BIT", &[Bit(7), E], 2, 2, 2, b"Z01-"),
+    /* 0x7C */ Opcode::new("BIT", &[Bit(7), H], 2, 2, 2, b"Z01-"),
+    /* 0x7D */ Opcode::new("BIT", &[Bit(7), L], 2, 2, 2, b"Z01-"),
+    /* 0x7E */ Opcode::new("BIT", &[Bit(7), IndHL], 2, 3, 3, b"Z01-"),
+    /* 0x7F */ Opcode::new("BIT", &[Bit(7), A], 2, 2, 2, b"Z01-"),
+    /* 0x80 */ Opcode::new("RES", &[Bit(0), B], 2, 2, 2, b"----"),
+    /* 0x81 */ Opcode::new("RES", &[Bit(0), C], 2, 2, 2, b"----"),
+    /* 0x82 */ Opcode::new("RES", &[Bit(0), D], 2, 2, 2, b"----"),
+    /* 0x83 */ Opcode::new("RES", &[Bit(0), E], 2, 2, 2, b"----"),
+    /* 0x84 */ Opcode::new("RES", &[Bit(0), H], 2, 2, 2, b"----"),
+    /* 0x85 */ Opcode::new("RES", &[Bit(0), L], 2, 2, 2, b"----"),
+    /* 0x86 */ Opcode::new("RES", &[Bit(0), IndHL], 2, 4, 4, b"----"),
+    /* 0x87 */ Opcode::new("RES", &[Bit(0), A], 2, 2, 2, b"----"),
+    /* 0x88 */ Opcode::new("RES", &[Bit(1), B], 2, 2, 2, b"----"),
+    /* 0x89 */ Opcode::new("RES", &[Bit(1), C], 2, 2, 2, b"----"),
+    /* 0x8A */ Opcode::new("RES", &[Bit(1), D], 2, 2, 2, b"----"),
+    /* 0x8B */ Opcode::new("RES", &[Bit(1), E], 2, 2, 2, b"----"),
+    /* 0x8C */ Opcode::new("RES", &[Bit(1), H], 2, 2, 2, b"----"),
+    /* 0x8D */ Opcode::new("RES", &[Bit(1), L], 2, 2, 2, b"----"),
+    /* 0x8E */ Opcode::new("RES", &[Bit(1), IndHL], 2, 4, 4, b"----"),
+    /* 0x8F */ Opcode::new("RES", &[Bit(1), A], 2, 2, 2, b"----"),
+    /* 0x90 */ Opcode::new("RES", &[Bit(2), B], 2, 2, 2, b"----"),
+    /* 0x91 */ Opcode::new("RES", &[Bit(2), C], 2, 2, 2, b"----"),
+    /* 0x92 */ Opcode::new("RES", &[Bit(2), D], 2, 2, 2, b"----"),
+    /* 0x93 */ Opcode::new("RES", &[Bit(2), E], 2, 2, 2, b"----"),
+    /* 0x94 */ Opcode::new("RES", &[Bit(2), H], 2, 2, 2, b"----"),
+    /* 0x95 */ Opcode::new("RES", &[Bit(2), L], 2, 2, 2, b"----"),
+    /* 0x96 */ Opcode::new("RES", &[Bit(2), IndHL], 2, 4, 4, b"----"),
+    /* 0x97 */ Opcode::new("RES", &[Bit(2), A], 2, 2, 2, b"----"),
+    /* 0x98 */ Opcode::new("RES", &[Bit(3), B], 2, 2, 2, b"----"),
+    /* 0x99 */ Opcode::new("RES", &[Bit(3), C], 2, 2, 2, b"----"),
+    /* 0x9A */ Opcode::new("RES", &[Bit(3), D], 2, 2, 2, b"----"),
+    /* 0x9B */ Opcode::new("RES", &[Bit(3), E], 2, 2, 2, b"----"),
+    /* 0x9C */ Opcode::new("RES", &[Bit(3), H], 2, 2, 2, b"----"),
+    /* 0x9D */ Opcode::new("RES", &[Bit(3), L], 2, 2, 2, b"----"),
+    /* 0x9E */ Opcode::new("RES", &[Bit(3), IndHL], 2, 4, 4, b"----"),
+    /* 0x9F */ Opcode::new("RES", &[Bit(3), A], 2, 2, 2, b"----"),
+    /* 0xA0 */ Opcode::new("RES", &[Bit(4), B], 2, 2, 2, b"----"),
+    /* 0xA1 */ Opcode::new("RES", &[Bit(4), C], 2, 2, 2, b"----"),
+    /* 0xA2 */ Opcode::new("RES", &[Bit(4), D], 2, 2, 2, b"----"),
+    /* 0xA3 */ Opcode::new("RES", &[Bit(4), E], 2, 2, 2, b"----"),
+    /* 0xA4 */ Opcode::new("RES", &[Bit(4), H], 2, 2, 2, b"----"),
+    /* 0xA5 */ Opcode::new("RES", &[Bit(4), L], 2, 2, 2, b"----"),
+    /* 0xA6 */ Opcode::new("RES", &[Bit(4), IndHL], 2, 4, 4, b"----"),
+    /* 0xA7 */ Opcode::new("RES", &[Bit(4), A], 2, 2, 2, b"----"),
+    /* 0xA8 */ Opcode::new("RES", &[Bit(5), B], 2, 2, 2, b"----"),
+    /* 0xA9 */ Opcode::new("RES", &[Bit(5), C], 2, 2, 2, b"----"),
+    /* 0xAA */ Opcode::new("RES", &[Bit(5), D], 2, 2, 2, b"----"),
+    /* 0xAB */ Opcode::new("RES", &[Bit(5), E], 2, 2, 2, b"----"),
+    /* 0xAC */ Opcode::new("RES", &[Bit(5), H], 2, 2, 2, b"----"),
+    /* 0xAD */ Opcode::new("RES", &[Bit(5), L], 2, 2, 2, b"----"),
+    /* 0xAE */ Opcode::new("RES", &[Bit(5), IndHL], 2, 4, 4, b"----"),
+    /* 0xAF */ Opcode::new("RES", &[Bit(5), A], 2, 2, 2, b"----"),
+    /* 0xB0 */ Opcode::new("RES", &[Bit(6), B], 2, 2, 2, b"----"),
+    /* 0xB1 */ Opcode::new("RES", &[Bit(6), C], 2, 2, 2, b"----"),
+    /* 0xB2 */ Opcode::new("RES", &[Bit(6), D], 2, 2, 2, b"----"),
+    /* 0xB3 */ Opcode::new("RES", &[Bit(6), E], 2, 2, 2, b"----"),
+    /* 0xB4 */ Opcode::new("RES", &[Bit(6), H], 2, 2, 2, b"----"),
+    /* 0xB5 */ Opcode::new("RES", &[Bit(6), L], 2, 2, 2, b"----"),
+    /* 0xB6 */ Opcode::new("RES", &[Bit(6), IndHL], 2, 4, 4, b"----"),
+    /* 0xB7 */ Opcode::new("RES", &[Bit(6), A], 2, 2, 2, b"----"),
+    /* 0xB8 */ Opcode::new("RES", &[Bit(7), B], 2, 2, 2, b"----"),
+    /* 0xB9 */ Opcode::new("RES", &[Bit(7), C], 2, 2, 2, b"----"),
+    /* 0xBA */ Opcode::new("RES", &[Bit(7), D], 2, 2, 2, b"----"),
+    /* 0xBB */ Opcode::new("RES", &[Bit(7), E], 2, 2, 2, b"----"),
+    /* 0xBC */ Opcode::new("RES", &[Bit(7), H], 2, 2, 2, b"----"),
+    /* 0xBD */ Opcode::new("RES", &[Bit(7), L], 2, 2, 2, b"----"),
+    /* 0xBE */ Opcode::new("RES", &[Bit(7), IndHL], 2, 4, 4, b"----"),
+    /* 0xBF */ Opcode::new("RES", &[Bit(7), A], 2, 2, 2, b"----"),
+    /* 0xC0 */ Opcode::new("SET", &[Bit(0), B], 2, 2, 2, b"----"),
+    /* 0xC1 */ Opcode::new("SET", &[Bit(0), C], 2, 2, 2, b"----"),
+    /* 0xC2 */ Opcode::new("SET", &[Bit(0), D], 2, 2, 2, b"----"),
+    /* 0xC3 */ Opcode::new("SET", &[Bit(0), E], 2, 2, 2, b"----"),
+    /* 0xC4 */ Opcode::new("SET", &[Bit(0), H], 2, 2, 2, b"----"),
+    /* 0xC5 */ Opcode::new("SET", &[Bit(0), L], 2, 2, 2, b"----"),
+    /* 0xC6 */ Opcode::new("SET", &[Bit(0), IndHL], 2, 4, 4, b"----"),
+    /* 0xC7 */ Opcode::new("SET", &[Bit(0), A], 2, 2, 2, b"----"),
+    /* 0xC8 */ Opcode::new("SET", &[Bit(1), B], 2, 2, 2, b"----"),
+    /* 0xC9 */ Opcode::new("SET", &[Bit(1), C], 2, 2, 2, b"----"),
+    /* 0xCA */ Opcode::new("SET", &[Bit(1), D], 2, 2, 2, b"----"),
+    /* 0xCB */ Opcode::new("SET", &[Bit(1), E], 2, 2, 2, b"----"),
+    /* 0xCC */ Opcode::new("SET", &[Bit(1), H], 2, 2, 2, b"----"),
+    /* 0xCD */ Opcode::new("SET", &[Bit(1), L], 2, 2, 2, b"----"),
+    /* 0xCE */ Opcode::new("SET", &[Bit(1), IndHL], 2, 4, 4, b"----"),
+    /* 0xCF */ Opcode::new("SET", &[Bit(1), A], 2, 2, 2, b"----"),
+    /* 0xD0 */ Opcode::new("SET", &[Bit(2), B], 2, 2, 2, b"----"),
+    /* 0xD1 */ Opcode::new("SET", &[Bit(2), C], 2, 2, 2, b"----"),
+    /* 0xD2 */ Opcode::new("SET", &[Bit(2), D], 2, 2, 2, b"----"),
+    /* 0xD3 */ Opcode::new("SET", &[Bit(2), E], 2, 2, 2, b"----"),
+    /* 0xD4 */ Opcode::new("SET", &[Bit(2), H], 2, 2, 2, b"----"),
+    /* 0xD5 */ Opcode::new("SET", &[Bit(2), L], 2, 2, 2, b"----"),
+    /* 0xD6 */ Opcode::new("SET", &[Bit(2), IndHL], 2, 4, 4, b"----"),
+    /* 0xD7 */ Opcode::new("SET", &[Bit(2), A], 2, 2, 2, b"----"),
+    /* 0xD8 */ Opcode::new("SET", &[Bit(3), B], 2, 2, 2, b"----"),
+    /* 0xD9 */ Opcode::new("SET", &[Bit(3), C], 2, 2, 2, b"----"),
+    /* 0xDA */ Opcode::new("SET", &[Bit(3), D], 2, 2, 2, b"----"),
+    /* 0xDB */ Opcode::new("SET", &[Bit(3), E], 2, 2, 2, b"----"),
+    /* 0xDC */ Opcode::new("SET", &[Bit(3), H], 2, 2, 2, b"----"),
+    /* 0xDD */ Opcode::new("SET", &[Bit(3), L], 2, 2, 2, b"----"),
+    /* 0xDE */ Opcode::new("SET", &[Bit(3), IndHL], 2, 4, 4, b"----"),
+    /* 0xDF */ Opcode::new("SET", &[Bit(3), A], 2, 2, 2, b"----"),
+    /* 0xE0 */ Opcode::new("SET", &[Bit(4), B], 2, 2, 2, b"----"),
+    /* 0xE1 */ Opcode::new("SET", &[Bit(4), C], 2, 2, 2, b"----"),
+    /* 0xE2 */ Opcode::new("SET", &[Bit(4), D], 2, 2, 2, b"----"),
+    /* 0xE3 */ Opcode::new("SET", &[Bit(4), E], 2, 2, 2, b"----"),
+    /* 0xE4 */ Opcode::new("SET", &[Bit(4), H], 2, 2, 2, b"----"),
+    /* 0xE5 */ Opcode::new("SET", &[Bit(4), L], 2, 2, 2, b"----"),
+    /* 0xE6 */ Opcode::new("SET", &[Bit(4), IndHL], 2, 4, 4, b"----"),
+    /* 0xE7 */ Opcode::new("SET", &[Bit(4), A], 2, 2, 2, b"----"),
+    /* 0xE8 */ Opcode::new("SET", &[Bit(5), B], 2, 2, 2, b"----"),
+    /* 0xE9 */ Opcode::new("SET", &[Bit(5), C], 2, 2, 2, b"----"),
+    /* 0xEA */ Opcode::new("SET", &[Bit(5), D], 2, 2, 2, b"----"),
+    /* 0xEB */ Opcode::new("SET", &[Bit(5), E], 2, 2, 2, b"----"),
+    /* 0xEC */ Opcode::new("SET", &[Bit(5), H], 2, 2, 2, b"----"),
+    /* 0xED */ Opcode::new("SET", &[Bit(5), L], 2, 2, 2, b"----"),
+    /* 0xEE */ Opcode::new("SET", &[Bit(5), IndHL], 2, 4, 4, b"----"),
+    /* 0xEF */ Opcode::new("SET", &[Bit(5), A], 2, 2, 2, b"----"),
+    /* 0xF0 */ Opcode::new("SET", &[Bit(6), B], 2, 2, 2, b"----"),
+    /* 0xF1 */ Opcode::new("SET", &[Bit(6), C], 2, 2, 2, b"----"),
+    /* 0xF2 */ Opcode::new("SET", &[Bit(6), D], 2, 2, 2, b"----"),
+    /* 0xF3 */ Opcode::new("SET", &[Bit(6), E], 2, 2, 2, b"----"),
+    /* 0xF4 */ Opcode::new("SET", &[Bit(6), H], 2, 2, 2, b"----"),
+    /* 0xF5 */ Opcode::new("SET", &[Bit(6), L], 2, 2, 2, b"----"),
+    /* 0xF6 */ Opcode::new("SET", &[Bit(6), IndHL], 2, 4, 4, b"----"),
+    /* 0xF7 */ Opcode::new("SET", &[Bit(6), A], 2, 2, 2, b"----"),
+    /* 0xF8 */ Opcode::new("SET", &[Bit(7), B], 2, 2, 2, b"----"),
+    /* 0xF9 */ Opcode::new("SET", &[Bit(7), C], 2, 2, 2, b"----"),
+    /* 0xFA */ Opcode::new("SET", &[Bit(7), D], 2, 2, 2, b"----"),
+    /* 0xFB */ Opcode::new("SET", &[Bit(7), E], 2, 2, 2, b"----"),
+    /* 0xFC */ Opcode::new("SET", &[Bit(7), H], 2, 2, 2, b"----"),
+    /* 0xFD */ Opcode::new("SET", &[Bit(7), L], 2, 2, 2, b"----"),
+    /* 0xFE */ Opcode::new("SET", &[Bit(7), IndHL], 2, 4, 4, b"----"),
+    /* 0xFF */ Opcode::new("SET", &[Bit(7), A], 2, 2, 2, b"----"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The unprefixed opcodes of https://gbdev.io/gb-opcodes/optables/ as `length cycles flags`,
+    /// conditional instructions as `length cycles/taken flags`
+    #[rustfmt::skip]
+    const PUBLISHED: [[&str; 16]; 16] = [
+        ["1 1 ----", "3 3 ----", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 000C", "3 5 ----", "1 2 -0HC", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 000C"],
+        ["2 1 ----", "3 3 ----", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 000C", "2 3 ----", "1 2 -0HC", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 000C"],
+        ["2 2/3 ----", "3 3 ----", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 Z-0C", "2 2/3 ----", "1 2 -0HC", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 -11-"],
+        ["2 2/3 ----", "3 3 ----", "1 2 ----", "1 2 ----", "1 3 Z0H-", "1 3 Z1H-", "2 3 ----", "1 1 -001", "2 2/3 ----", "1 2 -0HC", "1 2 ----", "1 2 ----", "1 1 Z0H-", "1 1 Z1H-", "2 2 ----", "1 1 -00C"],
+        ["1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----"],
+        ["1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----"],
+        ["1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----"],
+        ["1 2 ----", "1 2 ----", "1 2 ----", "1 2 ----", "1 2 ----", "1 2 ----", "1 1 ----", "1 2 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 1 ----", "1 2 ----", "1 1 ----"],
+        ["1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 2 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 1 Z0HC", "1 2 Z0HC", "1 1 Z0HC"],
+        ["1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 2 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 2 Z1HC", "1 1 Z1HC"],
+        ["1 1 Z010", "1 1 Z010", "1 1 Z010", "1 1 Z010", "1 1 Z010", "1 1 Z010", "1 2 Z010", "1 1 Z010", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 2 Z000", "1 1 Z000"],
+        ["1 1 Z000", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 1 Z000", "1 2 Z000", "1 1 Z000", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 1 Z1HC", "1 2 Z1HC", "1 1 Z1HC"],
+        ["1 2/5 ----", "1 3 ----", "3 3/4 ----", "3 4 ----", "3 3/6 ----", "1 4 ----", "2 2 Z0HC", "1 4 ----", "1 2/5 ----", "1 4 ----", "3 3/4 ----", "1 1 ----", "3 3/6 ----", "3 6 ----", "2 2 Z0HC", "1 4 ----"],
+        ["1 2/5 ----", "1 3 ----", "3 3/4 ----", "1 1 ----", "3 3/6 ----", "1 4 ----", "2 2 Z1HC", "1 4 ----", "1 2/5 ----", "1 4 ----", "3 3/4 ----", "1 1 ----", "3 3/6 ----", "1 1 ----", "2 2 Z1HC", "1 4 ----"],
+        ["2 3 ----", "1 3 ----", "1 2 ----", "1 1 ----", "1 1 ----", "1 4 ----", "2 2 Z010", "1 4 ----", "2 4 00HC", "1 1 ----", "3 4 ----", "1 1 ----", "1 1 ----", "1 1 ----", "2 2 Z000", "1 4 ----"],
+        ["2 3 ----", "1 3 ZNHC", "1 2 ----", "1 1 ----", "1 1 ----", "1 4 ----", "2 2 Z000", "1 4 ----", "2 3 00HC", "1 2 ----", "3 4 ----", "1 1 ----", "1 1 ----", "1 1 ----", "2 2 Z1HC", "1 4 ----"],
+    ];
+
+    /// The flags of the CB-prefixed opcodes of the same table, by the top 5 bits. All are 2 bytes
+    /// long and take 2 cycles, the [HL] variants 4 and BIT [HL] 3.
+    const PUBLISHED_CB: [&str; 32] = [
+        "Z00C", "Z00C", "Z00C", "Z00C", "Z00C", "Z00C", "Z000", "Z00C",
+        "Z01-", "Z01-", "Z01-", "Z01-", "Z01-", "Z01-", "Z01-", "Z01-",
+        "----", "----", "----", "----", "----", "----", "----", "----",
+        "----", "----", "----", "----", "----", "----", "----", "----",
+    ];
+
+    /// The flags like in the opcode tables
+    fn flags(opcode: &Opcode) -> String {
+        opcode
+            .flags()
+            .iter()
+            .zip("ZNHC".chars())
+            .map(|(effect, name)| match effect {
+                FlagEffect::Unaffected => '-',
+                FlagEffect::Reset => '0',
+                FlagEffect::Set => '1',
+                FlagEffect::Affected => name,
+            })
+            .collect()
+    }
+
+    fn describe(opcode: &Opcode, taken_differs: bool) -> String {
+        let cycles = match taken_differs {
+            true => format!("{}/{}", opcode.cycles(false), opcode.cycles(true)),
+            false => opcode.cycles(false).to_string(),
+        };
+        format!("{} {} {}", opcode.length(), cycles, flags(opcode))
+    }
+
+    #[test]
+    fn opcodes_match_the_published_table() {
+        for (code, opcode) in OPCODES.iter().enumerate() {
+            // STOP takes longer, when it switches the speed, which the table doesn't list
+            let taken_differs = opcode.is_conditional() && opcode.mnemonic() != "STOP";
+            assert_eq!(describe(opcode, taken_differs), PUBLISHED[code >> 4][code & 0xF], "opcode ${:02X} ({})", code, opcode.mnemonic());
+        }
+    }
+
+    #[test]
+    fn cb_opcodes_match_the_published_table() {
+        for (code, opcode) in CB_OPCODES.iter().enumerate() {
+            let cycles = match code {
+                _ if code & 7 != 6 => 2,
+                0x40..=0x7F => 3,
+                _ => 4,
+            };
+            let expected = format!("2 {} {}", cycles, PUBLISHED_CB[code >> 3]);
+            assert!(!opcode.is_conditional(), "CB opcode ${:02X}", code);
+            assert_eq!(describe(opcode, false), expected, "CB opcode ${:02X} ({})", code, opcode.mnemonic());
+        }
+    }
+}
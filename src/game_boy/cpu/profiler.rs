@@ -0,0 +1,185 @@
+//! Where the emulated program spends its time. Every M-cycle is counted for the instruction it
+//! belongs to, together with the call stack (see [`super::callstack`]) at the start of the
+//! instruction. Interrupt dispatches count for the interrupted instruction, HALT and STOP for
+//! the instruction after them.
+
+use std::collections::HashMap;
+use super::callstack::{CallStack, Entry};
+use super::disassembler::Location;
+use super::Cpu;
+
+/// A call-stack path: where the outermost frame was entered from (without an entry), then the
+/// function and entry of each frame, outermost first. Empty outside of any frame.
+pub type Path = Vec<(Location, Option<Entry>)>;
+
+#[derive(Default)]
+pub struct Profile {
+    paths: Vec<Path>,
+    path_ids: HashMap<Path, usize>,
+    /// M-cycles by path and PC
+    cycles: HashMap<(usize, Location), u64>,
+    /// The path of the call stack, as of its `changes`
+    current_path: Option<(u64, usize)>,
+    /// The path and PC of the current instruction
+    current: Option<(usize, Location)>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// `(path, PC, M-cycles)` of everything that was executed
+    pub fn samples(&self) -> impl Iterator<Item = (&Path, Location, u64)> {
+        self.cycles.iter().map(|((path, pc), cycles)| (&self.paths[*path], *pc, *cycles))
+    }
+
+    /// M-cycles by PC
+    pub fn by_pc(&self) -> HashMap<Location, u64> {
+        let mut by_pc = HashMap::new();
+        for ((_, pc), cycles) in &self.cycles {
+            *by_pc.entry(*pc).or_default() += cycles;
+        }
+        by_pc
+    }
+
+    pub fn total(&self) -> u64 {
+        self.cycles.values().sum()
+    }
+
+    fn start(&mut self, call_stack: &CallStack, pc: Location) {
+        let path = match self.current_path {
+            Some((changes, path)) if changes == call_stack.changes() => path,
+            _ => {
+                let path = self.path_id(call_stack);
+                self.current_path = Some((call_stack.changes(), path));
+                path
+            }
+        };
+        self.current = Some((path, pc));
+    }
+
+    fn count(&mut self, cycles: u64) {
+        if let Some(current) = self.current {
+            *self.cycles.entry(current).or_default() += cycles;
+        }
+    }
+
+    fn path_id(&mut self, call_stack: &CallStack) -> usize {
+        let frames = call_stack.frames();
+        let mut path: Path = Vec::with_capacity(frames.len() + 1);
+        if let Some(outermost) = frames.first() {
+            path.push((outermost.caller(), None));
+        }
+        path.extend(frames.iter().map(|frame| (frame.function(), Some(frame.entry()))));
+        if let Some(id) = self.path_ids.get(&path) {
+            return *id;
+        }
+        let id = self.paths.len();
+        self.paths.push(path.clone());
+        self.path_ids.insert(path, id);
+        id
+    }
+}
+
+impl Cpu {
+    /// Start profiling, a running profile is started over
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Box::default());
+    }
+
+    /// Stop profiling and return the profile, if one was running
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take().map(|profile| *profile)
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    /// Before an instruction (or interrupt dispatch, or idle cycle) starts
+    pub(super) fn profile_start(&mut self) {
+        if self.profile.is_none() {
+            return;
+        }
+        let pc = self.location_of(self.pc);
+        if let Some(profile) = &mut self.profile {
+            profile.start(&self.call_stack, pc);
+        }
+    }
+
+    /// M-cycles of the current instruction
+    pub(super) fn profile_cycles(&mut self, cycles: u32) {
+        if let Some(profile) = &mut self.profile {
+            profile.count(cycles as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::write_folded;
+    use crate::symbols::Symbols;
+
+    /// Calls Outer twice, which calls Inner
+    fn profiled() -> Profile {
+        let mut cpu = Cpu::with_program(&[
+            0x31, 0x00, 0xD0, // $0100: LD SP,$D000
+            0xCD, 0x10, 0x01, // CALL Outer
+            0xCD, 0x10, 0x01, // CALL Outer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xCD, 0x20, 0x01, // $0110 Outer: CALL Inner
+            0xC9,             // RET
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00,             // $0120 Inner: NOP
+            0xC9,             // RET
+        ]);
+        cpu.start_profile();
+        while cpu.pc != 0x0109 {
+            cpu.step();
+        }
+        cpu.take_profile().unwrap()
+    }
+
+    fn folded(profile: &Profile, symbols: &Symbols) -> String {
+        let mut out = Vec::new();
+        write_folded(profile, symbols, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn cycles_are_counted_by_path_and_pc() {
+        let profile = profiled();
+        assert_eq!(profile.total(), 3 + 2 * 6 + 2 * (6 + 4) + 2 * (1 + 4));
+        let by_pc = profile.by_pc();
+        assert_eq!(by_pc[&Location::new(Some(0), 0x0103)], 6);
+        assert_eq!(by_pc[&Location::new(Some(0), 0x0110)], 12);
+        assert_eq!(by_pc[&Location::new(Some(0), 0x0121)], 8);
+        // Outer is entered from two places, the paths differ in where
+        let inner = profile
+            .samples()
+            .filter(|(_, pc, _)| pc.address() == 0x0120)
+            .map(|(path, _, cycles)| (path.clone(), cycles))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(inner.len(), 2);
+        for path in inner.keys() {
+            assert_eq!(path.len(), 3);
+            assert_eq!(path[1], (Location::new(Some(0), 0x0110), Some(Entry::Call)));
+            assert_eq!(path[2], (Location::new(Some(0), 0x0120), Some(Entry::Call)));
+        }
+    }
+
+    #[test]
+    fn folded_stacks_name_frames_by_location() {
+        let folded = folded(&profiled(), &Symbols::default());
+        assert_eq!(folded, "(root) 15\n(root);00:0110 20\n(root);00:0110;00:0120 10\n");
+    }
+
+    #[test]
+    fn folded_stacks_name_frames_by_label() {
+        let symbols = Symbols::parse("00:0100 Main\n00:0110 Outer\n00:0120 Inner\n00:0121 Inner.end\n");
+        let folded = folded(&profiled(), &symbols);
+        assert_eq!(folded, "Main 15\nMain;Outer 20\nMain;Outer;Inner 10\n");
+    }
+}
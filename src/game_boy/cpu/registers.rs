@@ -0,0 +1,179 @@
+//! A public view of the CPU state, to set up states and read them back (tools, tests, debuggers)
+
+use std::fmt::{Display, Formatter};
+use super::{Cpu, Register8};
+
+/// The registers of the CPU, together with IME and the HALT/STOP state
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    a: u8,
+    f: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    h: u8,
+    l: u8,
+    sp: u16,
+    pc: u16,
+    ime: bool,
+    /// EI was executed, IME is set after the next instruction
+    ime_scheduled: bool,
+    halted: bool,
+    stopped: bool,
+}
+
+impl Registers {
+    pub fn a(&self) -> u8 { self.a }
+    pub fn f(&self) -> u8 { self.f }
+    pub fn b(&self) -> u8 { self.b }
+    pub fn c(&self) -> u8 { self.c }
+    pub fn d(&self) -> u8 { self.d }
+    pub fn e(&self) -> u8 { self.e }
+    pub fn h(&self) -> u8 { self.h }
+    pub fn l(&self) -> u8 { self.l }
+    pub fn sp(&self) -> u16 { self.sp }
+    pub fn pc(&self) -> u16 { self.pc }
+    /// Interrupt Master Enable
+    pub fn ime(&self) -> bool { self.ime }
+    /// Is IME set after the next instruction (by EI)?
+    pub fn ime_scheduled(&self) -> bool { self.ime_scheduled }
+    pub fn halted(&self) -> bool { self.halted }
+    pub fn stopped(&self) -> bool { self.stopped }
+
+    pub fn af(&self) -> u16 { u16::from_le_bytes([self.f, self.a]) }
+    pub fn bc(&self) -> u16 { u16::from_le_bytes([self.c, self.b]) }
+    pub fn de(&self) -> u16 { u16::from_le_bytes([self.e, self.d]) }
+    pub fn hl(&self) -> u16 { u16::from_le_bytes([self.l, self.h]) }
+
+    pub fn zero_flag(&self) -> bool { self.f & 0x80 != 0 }
+    pub fn negative_flag(&self) -> bool { self.f & 0x40 != 0 }
+    pub fn half_carry_flag(&self) -> bool { self.f & 0x20 != 0 }
+    pub fn carry_flag(&self) -> bool { self.f & 0x10 != 0 }
+
+    pub fn set_a(&mut self, val: u8) { self.a = val; }
+    /// The lower nibble is stored as is, like POP AF does
+    pub fn set_f(&mut self, val: u8) { self.f = val; }
+    pub fn set_b(&mut self, val: u8) { self.b = val; }
+    pub fn set_c(&mut self, val: u8) { self.c = val; }
+    pub fn set_d(&mut self, val: u8) { self.d = val; }
+    pub fn set_e(&mut self, val: u8) { self.e = val; }
+    pub fn set_h(&mut self, val: u8) { self.h = val; }
+    pub fn set_l(&mut self, val: u8) { self.l = val; }
+    pub fn set_sp(&mut self, val: u16) { self.sp = val; }
+    pub fn set_pc(&mut self, val: u16) { self.pc = val; }
+    /// Also cancels a pending EI
+    pub fn set_ime(&mut self, val: bool) {
+        self.ime = val;
+        self.ime_scheduled = false;
+    }
+    pub fn set_halted(&mut self, val: bool) { self.halted = val; }
+    pub fn set_stopped(&mut self, val: bool) { self.stopped = val; }
+
+    pub fn set_af(&mut self, val: u16) { [self.f, self.a] = val.to_le_bytes(); }
+    pub fn set_bc(&mut self, val: u16) { [self.c, self.b] = val.to_le_bytes(); }
+    pub fn set_de(&mut self, val: u16) { [self.e, self.d] = val.to_le_bytes(); }
+    pub fn set_hl(&mut self, val: u16) { [self.l, self.h] = val.to_le_bytes(); }
+}
+
+impl Display for Registers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} IME={}",
+            self.af(),
+            self.bc(),
+            self.de(),
+            self.hl(),
+            self.sp,
+            self.pc,
+            self.ime as u8
+        )?;
+        if self.ime_scheduled {
+            write!(f, " (EI)")?;
+        }
+        if self.halted {
+            write!(f, " HALT")?;
+        }
+        if self.stopped {
+            write!(f, " STOP")?;
+        }
+        Ok(())
+    }
+}
+
+impl Cpu {
+    /// The current registers, with the flags evaluated
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.reg(Register8::A),
+            f: self.reg(Register8::F),
+            b: self.reg(Register8::B),
+            c: self.reg(Register8::C),
+            d: self.reg(Register8::D),
+            e: self.reg(Register8::E),
+            h: self.reg(Register8::H),
+            l: self.reg(Register8::L),
+            sp: self.sp,
+            pc: self.pc,
+            ime: self.interrupts_enabled,
+            ime_scheduled: self.ime_scheduled,
+            halted: self.halted,
+            stopped: self.stopped,
+        }
+    }
+
+    /// Overwrite the registers. Meant to be used between instructions, an unfinished
+    /// instruction continues with the new registers.
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.lazy_flags = None;
+        self.registers = [
+            registers.a,
+            registers.f,
+            registers.b,
+            registers.c,
+            registers.d,
+            registers.e,
+            registers.h,
+            registers.l,
+        ];
+        self.sp = registers.sp;
+        self.pc = registers.pc;
+        self.interrupts_enabled = registers.ime;
+        self.ime_scheduled = registers.ime_scheduled;
+        self.halted = registers.halted;
+        self.stopped = registers.stopped;
+        self.unwind_frames();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// EI, NOP, NOP
+    const PROGRAM: [u8; 3] = [0xFB, 0x00, 0x00];
+
+    #[test]
+    fn clearing_ime_cancels_a_pending_ei() {
+        let mut cpu = Cpu::with_program(&PROGRAM);
+        cpu.step();
+        let mut registers = cpu.registers();
+        assert!(registers.ime_scheduled());
+        registers.set_ime(false);
+        cpu.set_registers(registers);
+        cpu.step();
+        assert!(!cpu.registers().ime());
+    }
+
+    #[test]
+    fn other_registers_keep_a_pending_ei() {
+        let mut cpu = Cpu::with_program(&PROGRAM);
+        cpu.step();
+        let mut registers = cpu.registers();
+        registers.set_a(0x42);
+        cpu.set_registers(registers);
+        cpu.step();
+        assert!(cpu.registers().ime());
+    }
+}
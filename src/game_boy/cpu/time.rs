@@ -7,7 +7,8 @@ const MODE_01_TICKS: u32 = CPU_CLOCK_SPEED / 262144;
 const MODE_10_TICKS: u32 = CPU_CLOCK_SPEED / 65536;
 const MODE_11_TICKS: u32 = CPU_CLOCK_SPEED / 16384;
 
-const DIVIDER_CLOCKS: u32 = CPU_CLOCK_SPEED / 16384;
+/// M-cycles between two increments of DIV
+pub const DIVIDER_PERIOD: u32 = CPU_CLOCK_SPEED / 16384;
 
 impl Cpu {
     fn timer_tick_count(&self) -> u32 {
@@ -33,43 +34,36 @@ impl Cpu {
         if overflow {
             result = self.mmu.read_8(adr::timer::MODULO);
         }
-        self.mmu.write_8(adr::timer::COUNTER, result);
+        // Not a write of the program, that would reschedule the timer
+        self.mmu.raw_write_8(adr::timer::COUNTER, result).expect("TIMA is writable");
         overflow
     }
 
-    fn divider_increase(&mut self) -> bool {
-        // Read divider counter, increment it and reset it to timer modulo on overflow
-        let tima = self.mmu.read_8(adr::timer::DIVIDER_REGISTER);
-        let (result, overflow) = tima.overflowing_add(1);
-        self.mmu.raw_write_8(adr::timer::DIVIDER_REGISTER, result);
-        overflow
+    fn divider_increase(&mut self) {
+        // Read divider counter and increment it, writing it normally would reset it
+        let div = self.mmu.read_8(adr::timer::DIVIDER_REGISTER);
+        self.mmu
+            .raw_write_8(adr::timer::DIVIDER_REGISTER, div.wrapping_add(1))
+            .expect("DIV is writable");
     }
 
-    pub fn timer_clock_cycle(&mut self) {
-        // TODO timer interrupts
-        let mut reset_divider = false;
-        let mut reset_clock = false;
-        // handle divider clock
-        if self.clock_counter_divider % DIVIDER_CLOCKS == 0 {
-            // Increase divider clock
-            reset_divider = self.divider_increase();
-            self.request_interrupt(Interrupt::TimerOverflow);
-        }
-        // handle time clock
-        if self.clock_counter % self.timer_tick_count() == 0 && self.timer_enabled() {
-            reset_clock = self.timer_increase();
-            self.request_interrupt(Interrupt::TimerOverflow);
-        }
-
-        if reset_clock {
-            self.clock_counter = 0;
+    /// M-cycles between two increments of TIMA, `None` if the timer is disabled
+    pub fn timer_period(&self) -> Option<u32> {
+        if self.timer_enabled() {
+            Some(self.timer_tick_count())
         } else {
-            self.clock_counter += 1;
+            None
         }
-        if reset_divider {
-            self.clock_counter_divider = 0;
-        } else {
-            self.clock_counter_divider += 1;
+    }
+
+    pub fn divider_tick(&mut self) {
+        self.divider_increase();
+    }
+
+    pub fn timer_tick(&mut self) {
+        // Only an overflow of TIMA requests the interrupt
+        if self.timer_increase() {
+            self.request_interrupt(Interrupt::TimerOverflow);
         }
     }
 }
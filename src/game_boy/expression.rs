@@ -0,0 +1,465 @@
+//! A small expression language over the machine state, for breakpoint conditions, watch
+//! expressions and trace filters, e.g. `a == $3F && [$FF44] > 100`.
+//!
+//! - Numbers: decimal, `$` or `0x` hex and `%` binary
+//! - Registers: `a` ... `l`, `af` ... `hl`, `sp`, `pc`, the flags `zf`, `nf`, `hf`, `cf`, `ime`
+//! - `ly`, `bank` (the ROM bank at $4000), `hits` (how often the breakpoint was reached,
+//!   including this time) and `value` (the value a watchpoint saw)
+//! - `[address]` reads a byte, without side effects
+//! - The operators of C, with C's precedence: `|| && == != < <= > >= | ^ & << >> + - * / %`
+//!   and the unary `- ~ !`. Comparisons are 1 or 0, dividing by 0 is 0.
+//!
+//! Names are case insensitive. Labels can be resolved while parsing, see
+//! [`Expression::parse_with`].
+
+use std::fmt::{Display, Formatter};
+use crate::game_boy::cpu::registers::Registers;
+use crate::game_boy::memory::MMU;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpressionError {
+    message: String,
+}
+
+impl ExpressionError {
+    fn new(message: impl Into<String>) -> ExpressionError {
+        ExpressionError { message: message.into() }
+    }
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, ExpressionError>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Variable {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+    ZeroFlag,
+    NegativeFlag,
+    HalfCarryFlag,
+    CarryFlag,
+    Ime,
+    Ly,
+    Bank,
+    Hits,
+    Value,
+}
+
+const VARIABLES: [(&str, Variable); 23] = [
+    ("a", Variable::A),
+    ("f", Variable::F),
+    ("b", Variable::B),
+    ("c", Variable::C),
+    ("d", Variable::D),
+    ("e", Variable::E),
+    ("h", Variable::H),
+    ("l", Variable::L),
+    ("af", Variable::AF),
+    ("bc", Variable::BC),
+    ("de", Variable::DE),
+    ("hl", Variable::HL),
+    ("sp", Variable::SP),
+    ("pc", Variable::PC),
+    ("zf", Variable::ZeroFlag),
+    ("nf", Variable::NegativeFlag),
+    ("hf", Variable::HalfCarryFlag),
+    ("cf", Variable::CarryFlag),
+    ("ime", Variable::Ime),
+    ("ly", Variable::Ly),
+    ("bank", Variable::Bank),
+    ("hits", Variable::Hits),
+    ("value", Variable::Value),
+];
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(i64),
+    Variable(Variable),
+    /// `[address]`
+    Memory(Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+/// Binary operators by precedence, lowest first
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// Longest first, so `<<` isn't read as two `<`
+const OPERATORS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "/", "%", "~", "!", "(", ")", "[", "]",
+];
+
+/// The state an expression is evaluated in
+#[derive(Copy, Clone)]
+pub struct Environment<'a> {
+    registers: Registers,
+    memory: &'a MMU,
+    hits: u32,
+    value: u8,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new(registers: Registers, memory: &'a MMU) -> Environment<'a> {
+        Environment { registers, memory, hits: 0, value: 0 }
+    }
+
+    /// The same state, as a breakpoint sees it
+    pub(crate) fn for_hit(&self, hits: u32, value: u8) -> Environment<'a> {
+        Environment { hits, value, ..*self }
+    }
+
+    fn variable(&self, variable: Variable) -> i64 {
+        let r = &self.registers;
+        (match variable {
+            Variable::A => r.a() as u16,
+            Variable::F => r.f() as u16,
+            Variable::B => r.b() as u16,
+            Variable::C => r.c() as u16,
+            Variable::D => r.d() as u16,
+            Variable::E => r.e() as u16,
+            Variable::H => r.h() as u16,
+            Variable::L => r.l() as u16,
+            Variable::AF => r.af(),
+            Variable::BC => r.bc(),
+            Variable::DE => r.de(),
+            Variable::HL => r.hl(),
+            Variable::SP => r.sp(),
+            Variable::PC => r.pc(),
+            Variable::ZeroFlag => r.zero_flag() as u16,
+            Variable::NegativeFlag => r.negative_flag() as u16,
+            Variable::HalfCarryFlag => r.half_carry_flag() as u16,
+            Variable::CarryFlag => r.carry_flag() as u16,
+            Variable::Ime => r.ime() as u16,
+            Variable::Ly => self.memory.read_ly() as u16,
+            Variable::Bank => self.memory.rom().bank_of(0x4000),
+            Variable::Hits => return self.hits as i64,
+            Variable::Value => self.value as u16,
+        }) as i64
+    }
+}
+
+/// A parsed expression
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression> {
+        Expression::parse_with(text, &|_| None)
+    }
+
+    /// Names, that aren't variables, are looked up with `labels` (e.g. in a sym file)
+    pub fn parse_with(text: &str, labels: &dyn Fn(&str) -> Option<i64>) -> Result<Expression> {
+        let tokens = tokenize(text, labels)?;
+        let mut position = 0;
+        let root = parse_binary(&tokens, &mut position, 0)?;
+        match tokens.get(position) {
+            None => Ok(Expression { source: text.trim().to_owned(), root }),
+            Some(token) => Err(ExpressionError::new(format!("unexpected {}", token))),
+        }
+    }
+
+    pub fn evaluate(&self, environment: &Environment) -> i64 {
+        evaluate(&self.root, environment)
+    }
+
+    /// Is it not 0?
+    pub fn holds(&self, environment: &Environment) -> bool {
+        self.evaluate(environment) != 0
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn evaluate(node: &Node, environment: &Environment) -> i64 {
+    match node {
+        Node::Number(n) => *n,
+        Node::Variable(variable) => environment.variable(*variable),
+        Node::Memory(address) => environment.memory.read_8(evaluate(address, environment) as u16) as i64,
+        Node::Unary(op, value) => {
+            let value = evaluate(value, environment);
+            match *op {
+                "-" => value.wrapping_neg(),
+                "~" => !value,
+                _ => (value == 0) as i64,
+            }
+        }
+        Node::Binary(op, left, right) => {
+            let left = evaluate(left, environment);
+            // Short circuit, so `hits > 10 && [hl] == 0` doesn't read when it doesn't have to
+            match *op {
+                "||" if left != 0 => return 1,
+                "&&" if left == 0 => return 0,
+                _ => {}
+            }
+            let right = evaluate(right, environment);
+            match *op {
+                "||" | "&&" => (right != 0) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" => left.checked_div(right).unwrap_or(0),
+                _ => left.checked_rem(right).unwrap_or(0),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Variable(Variable),
+    Operator(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Variable(v) => write!(f, "{:?}", v),
+            Token::Operator(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+fn tokenize(text: &str, labels: &dyn Fn(&str) -> Option<i64>) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied().unwrap_or(' ');
+        let (radix, skip) = match c {
+            '$' => (16, 1),
+            '0' if next == 'x' || next == 'X' => (16, 2),
+            // `%` is the remainder after a value
+            '%' if (next == '0' || next == '1') && tokens_end_with_operator(&tokens) => (2, 1),
+            '0'..='9' => (10, 0),
+            _ => (0, 0),
+        };
+        if radix != 0 {
+            let start = i + skip;
+            let mut end = start;
+            while end < chars.len() && (chars[end].is_digit(radix) || chars[end] == '_') {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().filter(|c| **c != '_').collect();
+            let value = i64::from_str_radix(&digits, radix)
+                .map_err(|_| ExpressionError::new(format!("invalid number {}", chars[i..end].iter().collect::<String>())))?;
+            tokens.push(Token::Number(value));
+            i = end;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            let variable = VARIABLES.iter().find(|(n, _)| n.eq_ignore_ascii_case(&name)).map(|(_, v)| *v);
+            match variable {
+                Some(variable) => tokens.push(Token::Variable(variable)),
+                None => match labels(&name) {
+                    Some(value) => tokens.push(Token::Number(value)),
+                    None => return Err(ExpressionError::new(format!("unknown name {}", name))),
+                },
+            }
+        } else {
+            let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let operator = OPERATORS
+                .iter()
+                .find(|o| rest.starts_with(**o))
+                .ok_or_else(|| ExpressionError::new(format!("unexpected {}", c)))?;
+            i += operator.len();
+            tokens.push(Token::Operator(operator));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Can a number start here? (`%` is an operator after a value)
+fn tokens_end_with_operator(tokens: &[Token]) -> bool {
+    !matches!(tokens.last(), Some(Token::Number(_)) | Some(Token::Variable(_)) | Some(Token::Operator(")" | "]")))
+}
+
+fn parse_binary(tokens: &[Token], position: &mut usize, level: usize) -> Result<Node> {
+    if level == BINARY_OPERATORS.len() {
+        return parse_unary(tokens, position);
+    }
+    let mut left = parse_binary(tokens, position, level + 1)?;
+    while let Some(Token::Operator(op)) = tokens.get(*position) {
+        if !BINARY_OPERATORS[level].contains(op) {
+            break;
+        }
+        *position += 1;
+        let right = parse_binary(tokens, position, level + 1)?;
+        left = Node::Binary(op, Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[Token], position: &mut usize) -> Result<Node> {
+    let token = tokens.get(*position).ok_or_else(|| ExpressionError::new("missing value"))?;
+    *position += 1;
+    Ok(match token {
+        Token::Number(n) => Node::Number(*n),
+        Token::Variable(v) => Node::Variable(*v),
+        Token::Operator(op @ ("-" | "~" | "!")) => Node::Unary(op, Box::new(parse_unary(tokens, position)?)),
+        Token::Operator("+") => parse_unary(tokens, position)?,
+        Token::Operator(open @ ("(" | "[")) => {
+            let inner = parse_binary(tokens, position, 0)?;
+            let close = if *open == "(" { ")" } else { "]" };
+            if tokens.get(*position) != Some(&Token::Operator(close)) {
+                return Err(ExpressionError::new(format!("missing {}", close)));
+            }
+            *position += 1;
+            if *open == "(" { inner } else { Node::Memory(Box::new(inner)) }
+        }
+        Token::Operator(op) => return Err(ExpressionError::new(format!("unexpected {}", op))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::cpu::Cpu;
+
+    /// A blank cartridge, with $C000 = $42 and LY = 100
+    fn memory() -> MMU {
+        let mut mmu = MMU::load_from_bytes(vec![0u8; 0x8000].into_boxed_slice()).unwrap();
+        mmu.write_8(0xC000, 0x42);
+        mmu.raw_write_8(0xFF44, 100).unwrap();
+        mmu
+    }
+
+    /// A = $3F, HL = $C000
+    fn registers() -> Registers {
+        let mut registers = Cpu::with_program(&[]).registers();
+        registers.set_a(0x3F);
+        registers.set_hl(0xC000);
+        registers
+    }
+
+    fn evaluate(text: &str) -> i64 {
+        let mmu = memory();
+        Expression::parse(text).unwrap().evaluate(&Environment::new(registers(), &mmu))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), 7);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9);
+        assert_eq!(evaluate("1 << 2 + 1"), 8);
+        assert_eq!(evaluate("6 & 3 == 3"), 0);
+        assert_eq!(evaluate("1 | 2 ^ 3 & 4"), 3);
+        assert_eq!(evaluate("0 || 1 && 0"), 0);
+        assert_eq!(evaluate("-2 * -3 - ~0"), 7);
+        assert_eq!(evaluate("!0 + !5"), 1);
+        assert_eq!(evaluate("a == $3F && hl == 0xC000"), 1);
+    }
+
+    #[test]
+    fn percent_is_binary_before_a_value_and_the_remainder_after_one() {
+        assert_eq!(evaluate("%1010"), 10);
+        assert_eq!(evaluate("%10_10 + 1"), 11);
+        assert_eq!(evaluate("a % 2"), 1);
+        assert_eq!(evaluate("a %10"), 3);
+        assert_eq!(evaluate("(a) % %100"), 3);
+        assert_eq!(evaluate("[hl] % 16"), 2);
+    }
+
+    #[test]
+    fn memory_reads() {
+        assert_eq!(evaluate("[hl]"), 0x42);
+        assert_eq!(evaluate("[hl + 1]"), 0);
+        assert_eq!(evaluate("[$FF44]"), 100);
+        assert_eq!(evaluate("[$FF44] == ly"), 1);
+        assert_eq!(evaluate("[[$FF44] + $BF9C]"), 0x42);
+    }
+
+    #[test]
+    fn hits_and_value_come_from_the_breakpoint() {
+        let mmu = memory();
+        let environment = Environment::new(registers(), &mmu);
+        let expression = Expression::parse("hits % 10 == 0 && value == $42").unwrap();
+        assert_eq!(Expression::parse("hits").unwrap().evaluate(&environment), 0);
+        assert!(!expression.holds(&environment.for_hit(9, 0x42)));
+        assert!(expression.holds(&environment.for_hit(10, 0x42)));
+        assert!(!expression.holds(&environment.for_hit(10, 0x41)));
+    }
+
+    #[test]
+    fn dividing_by_zero_is_zero() {
+        assert_eq!(evaluate("5 / 0"), 0);
+        assert_eq!(evaluate("5 % 0"), 0);
+        assert_eq!(evaluate("a / (hl - $C000)"), 0);
+        assert_eq!(evaluate("(-9223372036854775807 - 1) / -1"), 0);
+    }
+
+    #[test]
+    fn errors() {
+        let message = |text: &str| Expression::parse(text).unwrap_err().to_string();
+        assert_eq!(message("a == foo"), "unknown name foo");
+        assert_eq!(message("(1 + 2"), "missing )");
+        assert_eq!(message("[hl"), "missing ]");
+        assert_eq!(message("1 +"), "missing value");
+        assert_eq!(message("1 2"), "unexpected 2");
+        assert_eq!(message("a @ 1"), "unexpected @");
+        assert_eq!(message("$"), "invalid number $");
+    }
+
+    #[test]
+    fn names_are_case_insensitive_and_labels_are_resolved() {
+        let labels = |name: &str| (name == "Main.loop").then_some(0x150);
+        let mmu = memory();
+        let environment = Environment::new(registers(), &mmu);
+        let expression = Expression::parse_with("PC != Main.loop && A == $3f", &labels).unwrap();
+        assert!(expression.holds(&environment));
+        assert_eq!(expression.to_string(), "PC != Main.loop && A == $3f");
+    }
+}
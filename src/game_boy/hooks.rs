@@ -0,0 +1,194 @@
+//! Callbacks to observe the emulation, without patching the source. Hooks are installed with
+//! [`GameBoy::hooks`](crate::game_boy::GameBoy::hooks). Until then, the CPU only checks for
+//! `None`, so emulation without hooks isn't slowed down.
+//!
+//! Memory hooks see the data accesses of instructions and interrupt dispatches, not the opcode
+//! and operand fetches (see the execute hooks for those).
+
+use crate::game_boy::interrupt::Interrupt;
+use crate::game_boy::memory::MemRegion;
+
+/// An instruction, right before it is executed
+#[derive(Copy, Clone, Debug)]
+pub struct ExecuteEvent {
+    pc: u16,
+    bank: u16,
+    opcode: u8,
+    prefixed: bool,
+    operand: u16,
+}
+
+impl ExecuteEvent {
+    pub(crate) fn new(pc: u16, bank: u16, opcode: u8, prefixed: bool, operand: u16) -> ExecuteEvent {
+        ExecuteEvent { pc, bank, opcode, prefixed, operand }
+    }
+
+    pub fn pc(&self) -> u16 { self.pc }
+    /// The ROM bank of PC, 0 outside of ROM
+    pub fn bank(&self) -> u16 { self.bank }
+    /// The opcode, after the CB prefix for prefixed instructions
+    pub fn opcode(&self) -> u8 { self.opcode }
+    pub fn prefixed(&self) -> bool { self.prefixed }
+    /// The immediate operand, 0 if the instruction has none
+    pub fn operand(&self) -> u16 { self.operand }
+}
+
+/// A read or write of memory
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryEvent {
+    address: u16,
+    value: u8,
+    region: MemRegion,
+}
+
+impl MemoryEvent {
+    pub(crate) fn new(address: u16, value: u8) -> MemoryEvent {
+        MemoryEvent { address, value, region: MemRegion::get_region(address) }
+    }
+
+    pub fn address(&self) -> u16 { self.address }
+    /// The value, that was read or written
+    pub fn value(&self) -> u8 { self.value }
+    pub fn region(&self) -> MemRegion { self.region }
+}
+
+/// An interrupt is dispatched, its flag in IF was just reset
+#[derive(Copy, Clone, Debug)]
+pub struct InterruptEvent {
+    interrupt: Interrupt,
+    return_address: u16,
+}
+
+impl InterruptEvent {
+    pub(crate) fn new(interrupt: Interrupt, return_address: u16) -> InterruptEvent {
+        InterruptEvent { interrupt, return_address }
+    }
+
+    pub fn interrupt(&self) -> Interrupt { self.interrupt }
+    /// The PC, that was pushed
+    pub fn return_address(&self) -> u16 { self.return_address }
+}
+
+/// Identifies an installed hook, to remove it again
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(u32);
+
+type HookList<T> = Vec<(HookId, Box<dyn FnMut(&T)>)>;
+
+/// The installed hooks
+#[derive(Default)]
+pub struct Hooks {
+    next_id: u32,
+    execute: HookList<ExecuteEvent>,
+    read: HookList<MemoryEvent>,
+    write: HookList<MemoryEvent>,
+    interrupt: HookList<InterruptEvent>,
+    /// Gets LY
+    scanline: HookList<u8>,
+    /// Gets the number of frames since power on
+    frame: HookList<u64>,
+}
+
+impl Hooks {
+    pub fn new() -> Hooks {
+        Hooks::default()
+    }
+
+    fn next_id(&mut self) -> HookId {
+        self.next_id += 1;
+        HookId(self.next_id)
+    }
+
+    /// Called before every instruction
+    pub fn on_execute(&mut self, hook: impl FnMut(&ExecuteEvent) + 'static) -> HookId {
+        let id = self.next_id();
+        self.execute.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Called after every memory read of the CPU
+    pub fn on_read(&mut self, hook: impl FnMut(&MemoryEvent) + 'static) -> HookId {
+        let id = self.next_id();
+        self.read.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Called after every memory write of the CPU
+    pub fn on_write(&mut self, hook: impl FnMut(&MemoryEvent) + 'static) -> HookId {
+        let id = self.next_id();
+        self.write.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Called, when the CPU jumps to an interrupt handler
+    pub fn on_interrupt(&mut self, hook: impl FnMut(&InterruptEvent) + 'static) -> HookId {
+        let id = self.next_id();
+        self.interrupt.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Called at the start of every line (including the V-Blank lines) with the new LY
+    pub fn on_scanline(&mut self, hook: impl FnMut(&u8) + 'static) -> HookId {
+        let id = self.next_id();
+        self.scanline.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Called, when a frame is done, with the number of frames done since power on
+    pub fn on_frame(&mut self, hook: impl FnMut(&u64) + 'static) -> HookId {
+        let id = self.next_id();
+        self.frame.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Remove a hook. Returns `false`, if it wasn't installed (anymore).
+    pub fn remove(&mut self, id: HookId) -> bool {
+        fn remove_from<T>(list: &mut HookList<T>, id: HookId) -> bool {
+            let len = list.len();
+            list.retain(|(hook_id, _)| *hook_id != id);
+            list.len() != len
+        }
+        remove_from(&mut self.execute, id)
+            || remove_from(&mut self.read, id)
+            || remove_from(&mut self.write, id)
+            || remove_from(&mut self.interrupt, id)
+            || remove_from(&mut self.scanline, id)
+            || remove_from(&mut self.frame, id)
+    }
+
+    /// Are any instruction hooks installed? Decoding the instruction for them can be skipped
+    /// otherwise.
+    pub(crate) fn wants_execute(&self) -> bool {
+        !self.execute.is_empty()
+    }
+
+    pub(crate) fn execute(&mut self, event: &ExecuteEvent) {
+        call(&mut self.execute, event);
+    }
+
+    pub(crate) fn read(&mut self, event: &MemoryEvent) {
+        call(&mut self.read, event);
+    }
+
+    pub(crate) fn write(&mut self, event: &MemoryEvent) {
+        call(&mut self.write, event);
+    }
+
+    pub(crate) fn interrupt(&mut self, event: &InterruptEvent) {
+        call(&mut self.interrupt, event);
+    }
+
+    pub(crate) fn scanline(&mut self, ly: u8) {
+        call(&mut self.scanline, &ly);
+    }
+
+    pub(crate) fn frame(&mut self, frames: u64) {
+        call(&mut self.frame, &frames);
+    }
+}
+
+fn call<T>(hooks: &mut HookList<T>, event: &T) {
+    for (_, hook) in hooks.iter_mut() {
+        hook(event);
+    }
+}
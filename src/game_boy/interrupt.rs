@@ -1,5 +1,5 @@
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Interrupt {
     /// Gameboy enters VBLANK
     VBlank,
@@ -14,6 +14,29 @@ pub enum Interrupt {
 }
 
 impl Interrupt {
+    /// All interrupts, ordered by priority (highest first)
+    pub const PRIORITY: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdcStatus,
+        Interrupt::TimerOverflow,
+        Interrupt::SerialTransferCompletion,
+        Interrupt::Input,
+    ];
+
+    /// The short names of the debugger, by priority
+    pub const NAMES: [(&'static str, Interrupt); 5] = [
+        ("vblank", Interrupt::VBlank),
+        ("stat", Interrupt::LcdcStatus),
+        ("timer", Interrupt::TimerOverflow),
+        ("serial", Interrupt::SerialTransferCompletion),
+        ("joypad", Interrupt::Input),
+    ];
+
+    /// The interrupt with the short name, case insensitive
+    pub fn from_name(name: &str) -> Option<Interrupt> {
+        Interrupt::NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, interrupt)| *interrupt)
+    }
+
     /// The address to jump to when the interrupt occurs
     pub fn jump_address(&self) -> u16 {
         // If an interrupt occurs, push the PC to the stack and call the specified address
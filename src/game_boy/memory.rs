@@ -7,10 +7,14 @@ pub mod addresses;
 pub mod misc;
 pub mod rom;
 pub mod video;
+pub mod joypad;
+mod pages;
 mod flags;
 mod debug;
 
 use addresses as adr;
+use pages::{PageTable, ReadPage, WritePage, PAGE_SIZE};
+use crate::game_boy::helpers::check_bit;
 
 const BOOT_ROM: &[u8] = include_bytes!("../../res/boot/dmg_boot.bin");
 
@@ -33,9 +37,11 @@ impl From<rom::RomError> for MemError {
 
 const MEM_SIZE: usize = 0x10000;
 const NON_ROM_SIZE: usize = 0x10000 - 0x8000;
+/// The boot ROM is kept behind the rest of the memory, so it can be mapped like RAM
+const BOOT_ROM_OFFSET: usize = NON_ROM_SIZE;
 
 /// The memory region
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MemRegion {
     /// External Bus ROM Region
     Rom,
@@ -117,8 +123,22 @@ impl MemRegion {
 #[derive(Debug)]
 pub struct MMU {
     // For now put it on the stack :^) -> it SHOULD be able to handle 64kiB
-    mem: [u8; NON_ROM_SIZE],
+    mem: [u8; NON_ROM_SIZE + 0x100],
     rom: rom::Rom,
+    /// Where every page of the address space is mapped to
+    pages: PageTable,
+    /// Pressed buttons, directions in the lower and action buttons in the upper nibble
+    buttons: u8,
+    /// CGB double speed mode (KEY1 bit 7)
+    double_speed: bool,
+    /// STAT or LYC was written, the LCD status has to be updated
+    lcd_status_written: bool,
+    /// DIV was written (or reset by STOP), both divider and timer ticks have to be rescheduled
+    divider_written: bool,
+    /// TIMA or TAC was written, the next timer tick has to be rescheduled
+    timer_written: bool,
+    /// The watched code pages, that were written (see [`MMU::watch_code`])
+    written_code: u128,
 }
 
 // const DBG_ADDRESS: &[u16] = &[0xFEu16, adr::video::LCD_CONTROL, adr::memory::BOOT_ROM_ENABLED];
@@ -130,11 +150,30 @@ impl MMU {
 
     // TODO handle boot ROM
     pub fn load_from_path(path: &PathBuf) -> MemResult<MMU> {
-        let rom = rom::Rom::load_from_path(path)?;
-        Ok(MMU {
-            mem: [0; NON_ROM_SIZE],
+        Ok(MMU::with_rom(rom::Rom::load_from_path(path)?))
+    }
+
+    /// Like [`MMU::load_from_path`], with a ROM image, that is already in memory
+    pub fn load_from_bytes(bytes: Box<[u8]>) -> MemResult<MMU> {
+        Ok(MMU::with_rom(rom::Rom::load_from_bytes(bytes)?))
+    }
+
+    fn with_rom(rom: rom::Rom) -> MMU {
+        // The boot ROM is enabled, as long as 0xFF50 is 0
+        let pages = PageTable::new(&rom, Some(BOOT_ROM_OFFSET as u32));
+        let mut mem = [0; NON_ROM_SIZE + 0x100];
+        mem[BOOT_ROM_OFFSET..].copy_from_slice(BOOT_ROM);
+        MMU {
+            mem,
             rom,
-        })
+            pages,
+            buttons: 0,
+            double_speed: false,
+            lcd_status_written: false,
+            divider_written: false,
+            timer_written: false,
+            written_code: 0,
+        }
     }
 
     pub fn rom(&self) -> &rom::Rom {
@@ -143,56 +182,74 @@ impl MMU {
 
     // TODO let read_8 return MemResult
 
+    #[inline]
     pub fn read_8(&self, address: u16) -> u8 {
-        if self.boot_rom_enabled() && MemRegion::is_in_boot_rom(address) {
-            self.read_8_boot(address)
-        } else if MMU::ROM_REGION.contains(&address) {
-            self.rom.read_8(address)
-        } else {
-            self.mem[address as usize - 0x8000]
+        let offset = address as usize % PAGE_SIZE;
+        match self.pages.read(address) {
+            ReadPage::Ram(page) => self.mem[page as usize + offset],
+            ReadPage::Rom(page) => self.rom.data()[page as usize + offset],
+            ReadPage::Io => self.read_io(address),
         }
     }
 
-    fn read_8_boot(&self, address: u16) -> u8 {
-        if MemRegion::is_in_boot_rom(address) {
-            BOOT_ROM[address as usize]
-        } else {
-            0xFF
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            adr::input::P1 => self.read_p1(),
+            // The upper 3 bits of IF are unused and always read as 1
+            adr::interrupts::FLAGS => self.mem[address as usize - 0x8000] | 0xE0,
+            adr::memory::SPEED_SWITCH => self.read_key1(),
+            _ => self.mem[address as usize - 0x8000],
         }
     }
 
-    // TODO maybe just return bool?
-
-
-    pub fn write_8(&mut self, address: u16, val: u8) -> MemResult<()> {
-        // TODO there are some special addresses with specific behavior
-        // if address == adr::memory::DMA_TRANSFER_SOURCE_ADDRESS {
-        //     self.dma_transfer(val);
-        //     Ok(())
-        // } else
-        if MemRegion::is_writable(address) {
-            match address {
-                // region Debug cases
-                // adr::interrupts::FLAGS => println!("Writing to interrupt flags {:b}", val),
-                // adr::interrupts::ENABLE => println!("Writing to interrupt enable {:b}", val),
-                // endregion Debug cases end
-                // TODO: check, if we need to write the value to RAM as well
-                adr::memory::DMA_TRANSFER_SOURCE_ADDRESS => self.dma_transfer(val),
-                _ => {}
+    #[inline]
+    pub fn write_8(&mut self, address: u16, val: u8) {
+        match self.pages.write(address) {
+            WritePage::Ram(page) => self.mem[page as usize + address as usize % PAGE_SIZE] = val,
+            WritePage::Code(page) => {
+                self.mem[page as usize + address as usize % PAGE_SIZE] = val;
+                self.written_code |= PageTable::code_bit(address);
+            }
+            WritePage::Io => self.write_io(address, val),
+            WritePage::Cartridge => {
+                if self.rom.write_8(address, val) {
+                    self.map_pages();
+                }
             }
-            self.mem[address as usize - 0x8000] = match address {
-                adr::timer::DIVIDER_REGISTER => 0,
-                adr::video::LCD_STATUS => val & 0xFC, // bit 0 and 1 can't be written
-                _ => val
-            };
-            Ok(())
-        } else {
-            Err(MemError::InvalidAddressRegion(MemRegion::get_region(
-                address,
-            )))
         }
     }
 
+    fn write_io(&mut self, address: u16, val: u8) {
+        match address {
+            // region Debug cases
+            // adr::interrupts::FLAGS => println!("Writing to interrupt flags {:b}", val),
+            // adr::interrupts::ENABLE => println!("Writing to interrupt enable {:b}", val),
+            // endregion Debug cases end
+            // TODO: check, if we need to write the value to RAM as well
+            adr::memory::DMA_TRANSFER_SOURCE_ADDRESS => self.dma_transfer(val),
+            adr::video::LCD_STATUS | adr::video::LINE_COMPARE => self.lcd_status_written = true,
+            adr::timer::DIVIDER_REGISTER => self.divider_written = true,
+            adr::timer::COUNTER | adr::timer::CONTROL => self.timer_written = true,
+            _ => {}
+        }
+        self.mem[address as usize - 0x8000] = match address {
+            adr::input::P1 => val & 0x30, // only the selection bits can be written
+            adr::timer::DIVIDER_REGISTER => 0,
+            adr::memory::SPEED_SWITCH => val & 0x01, // only "prepare speed switch" can be written
+            adr::video::LCD_STATUS => val & 0xFC, // bit 0 and 1 can't be written
+            _ => val
+        };
+        if address == adr::memory::BOOT_ROM_ENABLED {
+            self.map_pages();
+        }
+    }
+
+    /// Update the page table, after the boot ROM was disabled or the cartridge switched banks
+    fn map_pages(&mut self) {
+        let boot_rom = if self.boot_rom_enabled() { Some(BOOT_ROM_OFFSET as u32) } else { None };
+        self.pages.map(&self.rom, boot_rom);
+    }
+
     /// Will try to write value to address in ram without any additional checks or handling
     /// e.g. zeroing the divider register or the DMA transfer won't be done
     ///
@@ -202,6 +259,9 @@ impl MMU {
     pub fn raw_write_8(&mut self, address: u16, val: u8) -> MemResult<()> {
         if MemRegion::is_writable(address) {
             self.mem[address as usize - 0x8000] = val;
+            if address == adr::memory::BOOT_ROM_ENABLED {
+                self.map_pages();
+            }
             Ok(())
         } else {
             Err(MemError::InvalidAddressRegion(MemRegion::get_region(
@@ -210,6 +270,28 @@ impl MMU {
         }
     }
 
+    /// Change memory without any side effects, for debuggers. Unlike [`MMU::raw_write_8`] this
+    /// also patches the ROM image in the bank, that is mapped right now. The boot ROM can't be
+    /// patched, writes below 0x100 go to the cartridge ROM under it.
+    pub fn poke_8(&mut self, address: u16, val: u8) {
+        if MMU::ROM_REGION.contains(&address) {
+            let bank = self.rom.bank_of(address);
+            self.rom.poke_banked(bank, address, val);
+        } else {
+            self.mem[address as usize - 0x8000] = val;
+            if address == adr::memory::BOOT_ROM_ENABLED {
+                self.map_pages();
+            }
+        }
+    }
+
+    /// [`MMU::poke_8`] for consecutive bytes, wrapping around at the end of the address space
+    pub fn poke(&mut self, address: u16, bytes: &[u8]) {
+        for (i, b) in bytes.iter().enumerate() {
+            self.poke_8(address.wrapping_add(i as u16), *b);
+        }
+    }
+
     pub fn read_16(&self, address: u16) -> MemResult<u16> {
         // Do 2-byte reads have to be aligned to a 2-byte grid?
         // If yes a simple modulo is enough
@@ -271,7 +353,115 @@ impl MMU {
         // TODO allow Boot ROM Reads
     }
 
+    /// KEY1: bit 7 is the current speed, bit 0 arms the speed switch on the next STOP.
+    /// Only exists in CGB mode, a DMG reads 0xFF.
+    fn read_key1(&self) -> u8 {
+        if self.rom.is_color() {
+            0x7E | ((self.double_speed as u8) << 7) | (self.mem[adr::memory::SPEED_SWITCH as usize - 0x8000] & 0x01)
+        } else {
+            0xFF
+        }
+    }
+
+    /// Is a speed switch prepared (KEY1 bit 0), that will happen on the next STOP?
+    pub fn speed_switch_armed(&self) -> bool {
+        self.rom.is_color() && check_bit(self.mem[adr::memory::SPEED_SWITCH as usize - 0x8000], 0)
+    }
+
+    /// Toggle between normal and double speed and disarm the switch
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.mem[adr::memory::SPEED_SWITCH as usize - 0x8000] = 0;
+    }
+
+    /// Is the CGB running in double speed mode?
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Was STAT or LYC written since the last call?
+    pub fn take_lcd_status_written(&mut self) -> bool {
+        std::mem::take(&mut self.lcd_status_written)
+    }
+
+    /// Was DIV written since the last call?
+    pub fn take_divider_written(&mut self) -> bool {
+        std::mem::take(&mut self.divider_written)
+    }
+
+    /// Was TIMA or TAC written since the last call?
+    pub fn take_timer_written(&mut self) -> bool {
+        std::mem::take(&mut self.timer_written)
+    }
+
+    /// Remember writes to the RAM page of the address, because code was decoded from it. The
+    /// IO page (and HRAM) can't be watched.
+    pub fn watch_code(&mut self, address: u16) {
+        self.pages.watch(address);
+    }
+
+    /// The watched code pages, that were written since the last call. Bit 0 is page 0x80.
+    pub fn take_written_code(&mut self) -> u128 {
+        std::mem::take(&mut self.written_code)
+    }
+
+    /// The bit of the page of a RAM address in [`MMU::take_written_code`]
+    pub fn code_page_bit(address: u16) -> u128 {
+        PageTable::code_bit(address)
+    }
+
+    /// A byte of code, with the boot ROM and the ROM `bank` at $4000 mapped like given. For
+    /// disassembling instructions, that ran earlier, RAM is read like it is now.
+    pub fn read_code(&self, address: u16, boot_rom: bool, bank: u16) -> u8 {
+        match address {
+            0x0000..=0x00FF if boot_rom => BOOT_ROM[address as usize],
+            0x0000..=0x3FFF => self.rom.read_banked(0, address).unwrap_or(0xFF),
+            0x4000..=0x7FFF => self.rom.read_banked(bank, address).unwrap_or(0xFF),
+            _ => self.read_8(address),
+        }
+    }
+
     pub fn boot_rom_enabled(&self) -> bool {
         self.mem[adr::memory::BOOT_ROM_ENABLED as usize - 0x8000] == 0x00
     }
 }
+
+/// Everything but the ROM, see [`MMU::snapshot`]
+#[derive(Clone)]
+pub struct MemorySnapshot {
+    mem: Box<[u8; NON_ROM_SIZE + 0x100]>,
+    pages: PageTable,
+    buttons: u8,
+    double_speed: bool,
+    lcd_status_written: bool,
+    divider_written: bool,
+    timer_written: bool,
+    written_code: u128,
+}
+
+impl MMU {
+    /// The state of the memory. The ROM isn't part of it, patches to it stay.
+    pub fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            mem: Box::new(self.mem),
+            pages: self.pages.clone(),
+            buttons: self.buttons,
+            double_speed: self.double_speed,
+            lcd_status_written: self.lcd_status_written,
+            divider_written: self.divider_written,
+            timer_written: self.timer_written,
+            written_code: self.written_code,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &MemorySnapshot) {
+        self.mem = *snapshot.mem;
+        self.pages = snapshot.pages.clone();
+        self.buttons = snapshot.buttons;
+        self.double_speed = snapshot.double_speed;
+        self.lcd_status_written = snapshot.lcd_status_written;
+        self.divider_written = snapshot.divider_written;
+        self.timer_written = snapshot.timer_written;
+        self.written_code = snapshot.written_code;
+    }
+}
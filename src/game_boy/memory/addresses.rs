@@ -54,4 +54,75 @@ pub mod video {
 pub mod memory {
     pub const BOOT_ROM_ENABLED: u16 = 0xFF50;
     pub const DMA_TRANSFER_SOURCE_ADDRESS: u16 = 0xFF46;
+    /// Prepare speed switch (KEY1), CGB only
+    pub const SPEED_SWITCH: u16 = 0xFF4D;
+}
+
+/// The names of the I/O registers, like in hardware.inc (https://github.com/gbdev/hardware.inc)
+pub const IO_REGISTER_NAMES: &[(u16, &str)] = &[
+    (input::P1, "rP1"),
+    (serial::SB, "rSB"),
+    (serial::SC, "rSC"),
+    (timer::DIVIDER_REGISTER, "rDIV"),
+    (timer::COUNTER, "rTIMA"),
+    (timer::MODULO, "rTMA"),
+    (timer::CONTROL, "rTAC"),
+    (interrupts::FLAGS, "rIF"),
+    (0xFF10, "rNR10"),
+    (0xFF11, "rNR11"),
+    (0xFF12, "rNR12"),
+    (0xFF13, "rNR13"),
+    (0xFF14, "rNR14"),
+    (0xFF16, "rNR21"),
+    (0xFF17, "rNR22"),
+    (0xFF18, "rNR23"),
+    (0xFF19, "rNR24"),
+    (0xFF1A, "rNR30"),
+    (0xFF1B, "rNR31"),
+    (0xFF1C, "rNR32"),
+    (0xFF1D, "rNR33"),
+    (0xFF1E, "rNR34"),
+    (0xFF20, "rNR41"),
+    (0xFF21, "rNR42"),
+    (0xFF22, "rNR43"),
+    (0xFF23, "rNR44"),
+    (0xFF24, "rNR50"),
+    (0xFF25, "rNR51"),
+    (0xFF26, "rNR52"),
+    (video::LCD_CONTROL, "rLCDC"),
+    (video::LCD_STATUS, "rSTAT"),
+    (video::SCREEN_Y, "rSCY"),
+    (video::SCREEN_X, "rSCX"),
+    (video::CURRENT_LINE, "rLY"),
+    (video::LINE_COMPARE, "rLYC"),
+    (memory::DMA_TRANSFER_SOURCE_ADDRESS, "rDMA"),
+    (0xFF47, "rBGP"),
+    (0xFF48, "rOBP0"),
+    (0xFF49, "rOBP1"),
+    (video::WINDOW_Y, "rWY"),
+    (video::WINDOW_X, "rWX"),
+    (memory::SPEED_SWITCH, "rKEY1"),
+    (0xFF4F, "rVBK"),
+    (0xFF51, "rHDMA1"),
+    (0xFF52, "rHDMA2"),
+    (0xFF53, "rHDMA3"),
+    (0xFF54, "rHDMA4"),
+    (0xFF55, "rHDMA5"),
+    (0xFF56, "rRP"),
+    (0xFF68, "rBCPS"),
+    (0xFF69, "rBCPD"),
+    (0xFF6A, "rOCPS"),
+    (0xFF6B, "rOCPD"),
+    (0xFF70, "rSVBK"),
+    (0xFF76, "rPCM12"),
+    (0xFF77, "rPCM34"),
+    (interrupts::ENABLE, "rIE"),
+];
+
+/// The hardware.inc name of the I/O register at the address
+pub fn io_register_name(address: u16) -> Option<&'static str> {
+    IO_REGISTER_NAMES
+        .iter()
+        .find(|(a, _)| *a == address)
+        .map(|(_, name)| *name)
 }
@@ -0,0 +1,76 @@
+use super::*;
+use crate::game_boy::helpers::check_bit;
+
+/// A button of the Game Boy
+#[derive(Copy, Clone, Debug)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// The bit in the lower nibble of P1 this button pulls low
+    fn line(&self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0,
+            Button::Left | Button::B => 1,
+            Button::Up | Button::Select => 2,
+            Button::Down | Button::Start => 3,
+        }
+    }
+
+    /// The bit of P1 that has to be low for this button to be visible
+    /// (4 -> direction keys, 5 -> action buttons)
+    fn select_bit(&self) -> u8 {
+        match self {
+            Button::Right | Button::Left | Button::Up | Button::Down => 4,
+            Button::A | Button::B | Button::Select | Button::Start => 5,
+        }
+    }
+
+    /// The bit in the internal button state
+    fn state_bit(&self) -> u8 {
+        self.line() + if self.select_bit() == 4 { 0 } else { 4 }
+    }
+}
+
+impl MMU {
+    /// Compose P1 from the selection bits written by the game and the pressed buttons.
+    /// Bits 6 and 7 are unused and always read as 1, a pressed button reads as 0.
+    pub(super) fn read_p1(&self) -> u8 {
+        let select = self.mem[adr::input::P1 as usize - 0x8000] & 0x30;
+        let mut lines = 0x0F;
+        if !check_bit(select, 4) {
+            lines &= !(self.buttons & 0x0F);
+        }
+        if !check_bit(select, 5) {
+            lines &= !(self.buttons >> 4);
+        }
+        0xC0 | select | lines
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        check_bit(self.buttons, button.state_bit())
+    }
+
+    /// Set the state of a button.
+    ///
+    /// Returns true, if this pulled one of the selected P1 lines from high to low. This is what
+    /// requests the joypad interrupt and wakes the CPU from STOP.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.read_p1();
+        if pressed {
+            self.buttons |= 1 << button.state_bit();
+        } else {
+            self.buttons &= !(1 << button.state_bit());
+        }
+        let falling = before & !self.read_p1() & 0x0F;
+        falling != 0
+    }
+}
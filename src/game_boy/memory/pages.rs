@@ -0,0 +1,193 @@
+//! The address space in pages of 256 bytes. Every page is mapped straight to the memory behind
+//! it, or to a handler for the pages, where an access has side effects. Reads and writes only
+//! look up the page, instead of checking the boot ROM, the region and the special addresses.
+//!
+//! The table has to be mapped again, whenever the mapping changes (boot ROM disabled, bank
+//! switched).
+
+use super::rom::Rom;
+
+pub const PAGE_SIZE: usize = 0x100;
+const PAGE_COUNT: usize = 0x10000 / PAGE_SIZE;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadPage {
+    /// The offset of the page in the ROM image
+    Rom(u32),
+    /// The offset of the page in the internal memory (VRAM, RAM, WRAM, OAM and the boot ROM)
+    Ram(u32),
+    /// IO registers, HRAM and IE
+    Io,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WritePage {
+    /// Writes to the ROM region go to the cartridge (e.g. MBC registers)
+    Cartridge,
+    /// The offset of the page in the internal memory (VRAM, RAM, WRAM, OAM)
+    Ram(u32),
+    /// Like `Ram`, but cached code was decoded from the page, see [`PageTable::watch`]
+    Code(u32),
+    /// IO registers, HRAM and IE
+    Io,
+}
+
+#[derive(Clone, Debug)]
+pub struct PageTable {
+    read: [ReadPage; PAGE_COUNT],
+    write: [WritePage; PAGE_COUNT],
+    /// The pages from 0x80 on, that are watched for writes to code
+    code: u128,
+}
+
+impl PageTable {
+    /// `boot_rom` is the offset of the boot ROM in the internal memory
+    pub fn new(rom: &Rom, boot_rom: Option<u32>) -> PageTable {
+        let mut table = PageTable {
+            read: [ReadPage::Io; PAGE_COUNT],
+            write: [WritePage::Io; PAGE_COUNT],
+            code: 0,
+        };
+        table.map(rom, boot_rom);
+        table
+    }
+
+    /// Map all pages for the current state of the boot ROM and the banks of the cartridge.
+    /// `boot_rom` is the offset of the boot ROM in the internal memory, while it's enabled.
+    pub fn map(&mut self, rom: &Rom, boot_rom: Option<u32>) {
+        for page in 0..PAGE_COUNT {
+            let address = (page * PAGE_SIZE) as u16;
+            self.read[page] = match address {
+                0x0000..=0x00FF if boot_rom.is_some() => ReadPage::Ram(boot_rom.unwrap()),
+                0x0000..=0x7FFF => ReadPage::Rom(rom.offset_of(address) as u32),
+                0xFF00..=0xFFFF => ReadPage::Io,
+                _ => ReadPage::Ram(address as u32 - 0x8000),
+            };
+            self.write[page] = match address {
+                0x0000..=0x7FFF => WritePage::Cartridge,
+                0xFF00..=0xFFFF => WritePage::Io,
+                _ if self.is_watched(address) => WritePage::Code(address as u32 - 0x8000),
+                _ => WritePage::Ram(address as u32 - 0x8000),
+            };
+        }
+    }
+
+    /// Watch the RAM page of the address for writes, because code was decoded from it. The IO
+    /// page can't be watched.
+    pub fn watch(&mut self, address: u16) {
+        if let WritePage::Ram(offset) = self.write(address) {
+            self.code |= Self::code_bit(address);
+            self.write[address as usize / PAGE_SIZE] = WritePage::Code(offset);
+        }
+    }
+
+    pub fn is_watched(&self, address: u16) -> bool {
+        self.code & Self::code_bit(address) != 0
+    }
+
+    /// The bit of the page of a RAM address (0x8000 and up) in a set of pages
+    pub fn code_bit(address: u16) -> u128 {
+        1 << (address as usize / PAGE_SIZE - 0x80)
+    }
+
+    #[inline]
+    pub fn read(&self, address: u16) -> ReadPage {
+        self.read[address as usize / PAGE_SIZE]
+    }
+
+    #[inline]
+    pub fn write(&self, address: u16) -> WritePage {
+        self.write[address as usize / PAGE_SIZE]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::memory::{addresses as adr, MMU};
+
+    const BOOT_ROM: u32 = 0x8000;
+
+    fn rom() -> Rom {
+        Rom::load_from_bytes(vec![0u8; 2 * Rom::BANK_SIZE].into_boxed_slice()).unwrap()
+    }
+
+    #[test]
+    fn rom_pages_follow_the_mapped_banks() {
+        let rom = rom();
+        let table = PageTable::new(&rom, None);
+        for address in (0x0000..0x8000).step_by(PAGE_SIZE) {
+            assert_eq!(table.read(address), ReadPage::Rom(rom.offset_of(address) as u32), "${:04X}", address);
+            assert_eq!(table.write(address), WritePage::Cartridge, "${:04X}", address);
+        }
+        assert_eq!(table.read(0x3FFF), ReadPage::Rom(0x3F00));
+        assert_eq!(table.read(0x4000), ReadPage::Rom(0x4000));
+    }
+
+    #[test]
+    fn the_boot_rom_is_mapped_over_the_first_page_only() {
+        let rom = rom();
+        let mut table = PageTable::new(&rom, Some(BOOT_ROM));
+        assert_eq!(table.read(0x0000), ReadPage::Ram(BOOT_ROM));
+        assert_eq!(table.read(0x00FF), ReadPage::Ram(BOOT_ROM));
+        assert_eq!(table.read(0x0100), ReadPage::Rom(0x0100));
+        assert_eq!(table.write(0x0000), WritePage::Cartridge);
+
+        table.map(&rom, None);
+        assert_eq!(table.read(0x0000), ReadPage::Rom(0x0000));
+    }
+
+    #[test]
+    fn writes_go_to_ram_or_the_io_handler() {
+        let table = PageTable::new(&rom(), None);
+        let ram = [
+            (0x8000, 0x0000), // VRAM
+            (0xA000, 0x2000), // cartridge RAM
+            (0xC000, 0x4000), // WRAM
+            (0xE000, 0x6000), // echo RAM
+            (0xFDFF, 0x7D00),
+            (0xFE00, 0x7E00), // OAM
+            (0xFEA0, 0x7E00), // unusable
+        ];
+        for (address, offset) in ram {
+            assert_eq!(table.read(address), ReadPage::Ram(offset), "${:04X}", address);
+            assert_eq!(table.write(address), WritePage::Ram(offset), "${:04X}", address);
+        }
+        for address in [0xFF00, 0xFF50, 0xFF80, 0xFFFF] {
+            assert_eq!(table.read(address), ReadPage::Io, "${:04X}", address);
+            assert_eq!(table.write(address), WritePage::Io, "${:04X}", address);
+        }
+    }
+
+    #[test]
+    fn watched_pages_stay_watched_after_mapping() {
+        let rom = rom();
+        let mut table = PageTable::new(&rom, Some(BOOT_ROM));
+        table.watch(0xC123);
+        table.watch(0xFF80);
+        table.watch(0x0150);
+        assert_eq!(table.write(0xC0FF), WritePage::Ram(0x4000));
+        assert_eq!(table.write(0xC100), WritePage::Code(0x4100));
+        assert_eq!(table.write(0xC200), WritePage::Ram(0x4200));
+        assert_eq!(table.write(0xFF80), WritePage::Io);
+        assert_eq!(table.write(0x0100), WritePage::Cartridge);
+
+        table.map(&rom, None);
+        assert_eq!(table.write(0xC1FF), WritePage::Code(0x4100));
+        assert!(table.is_watched(0xC100));
+        assert!(!table.is_watched(0xC200));
+    }
+
+    #[test]
+    fn disabling_the_boot_rom_remaps_the_first_page() {
+        let mut mmu = MMU::load_from_bytes(vec![0u8; 2 * Rom::BANK_SIZE].into_boxed_slice()).unwrap();
+        assert!(matches!(mmu.pages.read(0x0000), ReadPage::Ram(_)));
+        // Ignored by a cartridge without MBC
+        mmu.write_8(0x2000, 0x01);
+        assert!(matches!(mmu.pages.read(0x0000), ReadPage::Ram(_)));
+        assert_eq!(mmu.pages.read(0x4000), ReadPage::Rom(0x4000));
+
+        mmu.write_8(adr::memory::BOOT_ROM_ENABLED, 0x01);
+        assert_eq!(mmu.pages.read(0x0000), ReadPage::Rom(0x0000));
+    }
+}
@@ -70,6 +70,8 @@ impl Rom {
     /// This is basically the size of the smallest ROM size the GB had.
     /// Anything below that is invalid.
     const MIN_SUPPLIED_BYTE_ARRAY_LEN: usize = 32_768;
+    /// The size of a ROM bank. Bank 0 is always at 0x0000, the others are switched into 0x4000
+    pub const BANK_SIZE: usize = 0x4000;
     /// The title of the ROM
     pub fn title(&self) -> &str {
         self.title.as_str()
@@ -85,6 +87,12 @@ impl Rom {
         self.super_game_boy
     }
 
+    /// The whole ROM image
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
     /// Is the game meant for a japanese market?
     pub fn is_japanese(&self) -> bool {
             self.japanese
@@ -179,7 +187,56 @@ impl Rom {
         let a = address as usize;
         u16::from_le_bytes(self.data[a..a+2].try_into().unwrap())
     }
-    // TODO write methods??? Don't make sense with ROM
+
+    /// The number of 16 KiB banks in the ROM
+    pub fn bank_count(&self) -> u16 {
+        (self.data.len() / Rom::BANK_SIZE) as u16
+    }
+
+    /// The bank, that is mapped to the address right now
+    pub fn bank_of(&self, address: u16) -> u16 {
+        if (address as usize) < Rom::BANK_SIZE {
+            0
+        } else {
+            // TODO switchable banks, once MBCs are supported
+            1
+        }
+    }
+
+    /// The offset in the ROM image, that is mapped to the address right now
+    pub fn offset_of(&self, address: u16) -> usize {
+        self.bank_of(address) as usize * Rom::BANK_SIZE + address as usize % Rom::BANK_SIZE
+    }
+
+    /// Read from a specific bank, no matter what is mapped right now. `None`, if the bank
+    /// doesn't exist.
+    pub fn read_banked(&self, bank: u16, address: u16) -> Option<u8> {
+        let offset = bank as usize * Rom::BANK_SIZE + address as usize % Rom::BANK_SIZE;
+        self.data.get(offset).copied()
+    }
+
+    /// Change the ROM image in a specific bank. Only for debugging (patching the ROM), the
+    /// cartridge never sees it. Returns `false`, if the bank doesn't exist.
+    pub fn poke_banked(&mut self, bank: u16, address: u16, val: u8) -> bool {
+        let offset = bank as usize * Rom::BANK_SIZE + address as usize % Rom::BANK_SIZE;
+        match self.data.get_mut(offset) {
+            Some(byte) => {
+                *byte = val;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A write to the ROM region. It doesn't change the ROM, but would select banks, enable RAM,
+    /// etc. on cartridges with an MBC. A ROM only cartridge ignores it.
+    ///
+    /// Returns, whether other banks are mapped now.
+    pub fn write_8(&mut self, _address: u16, _val: u8) -> bool {
+        match self.cartridge_type {
+            CartridgeType::RomOnly => false,
+        }
+    }
 }
 
 // Debug functions
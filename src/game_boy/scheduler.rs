@@ -0,0 +1,129 @@
+//! Things, that happen at a known time. Instead of checking every M-cycle, whether the PPU
+//! changes its mode or a timer ticks, `GameBoy::clock` only does work when an event is due. While
+//! the CPU only waits (HALT, STOP), it skips right to the next event.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// LY changes, OAM search (or V-Blank) starts
+    LineStart,
+    PixelTransfer,
+    HBlank,
+    /// DIV is incremented
+    DividerTick,
+    /// TIMA is incremented, if the timer is enabled
+    TimerTick,
+}
+
+#[derive(Clone)]
+pub struct Scheduler {
+    /// M-cycles since power on (PPU cycles, the CPU runs two per cycle in double speed mode)
+    now: u64,
+    /// Sorted by time, the next event is last
+    events: Vec<(u64, Event)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            now: 0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Schedule the event `cycles` M-cycles from now. Events at the same time happen in the order
+    /// they were scheduled.
+    pub fn schedule(&mut self, cycles: u64, event: Event) {
+        self.schedule_at(self.now + cycles, event);
+    }
+
+    /// Schedule the event at a point in time. For periodic events relative to when they were due,
+    /// so they don't drift, if they are handled late.
+    pub fn schedule_at(&mut self, at: u64, event: Event) {
+        // Behind (popped after) all events up to that time
+        let index = self.events.iter().position(|(time, _)| *time <= at).unwrap_or(self.events.len());
+        self.events.insert(index, (at, event));
+    }
+
+    /// The M-cycles until the next event, 0 if one is due
+    pub fn cycles_until_next(&self) -> u64 {
+        self.events.last().map_or(u64::MAX, |(time, _)| time.saturating_sub(self.now))
+    }
+
+    /// Take the next event and the time it was due at, if it is due
+    pub fn pop_due(&mut self) -> Option<(u64, Event)> {
+        match self.events.last() {
+            Some((time, _)) if *time <= self.now => self.events.pop(),
+            _ => None,
+        }
+    }
+
+    /// Remove all pending events of this kind
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|(_, pending)| *pending != event);
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Scheduler};
+
+    fn drain(scheduler: &mut Scheduler) -> Vec<(u64, Event)> {
+        std::iter::from_fn(|| scheduler.pop_due()).collect()
+    }
+
+    #[test]
+    fn events_are_popped_in_time_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_at(30, Event::HBlank);
+        scheduler.schedule_at(10, Event::LineStart);
+        scheduler.schedule_at(20, Event::PixelTransfer);
+        assert_eq!(scheduler.cycles_until_next(), 10);
+        assert_eq!(scheduler.pop_due(), None);
+
+        scheduler.advance(20);
+        assert_eq!(scheduler.cycles_until_next(), 0);
+        assert_eq!(drain(&mut scheduler), vec![(10, Event::LineStart), (20, Event::PixelTransfer)]);
+        assert_eq!(scheduler.cycles_until_next(), 10);
+
+        scheduler.advance(15);
+        assert_eq!(drain(&mut scheduler), vec![(30, Event::HBlank)]);
+        assert_eq!(scheduler.cycles_until_next(), u64::MAX);
+    }
+
+    #[test]
+    fn events_at_the_same_time_keep_their_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_at(5, Event::DividerTick);
+        scheduler.schedule_at(5, Event::LineStart);
+        scheduler.schedule(5, Event::TimerTick);
+        scheduler.schedule_at(1, Event::HBlank);
+        scheduler.advance(5);
+        assert_eq!(drain(&mut scheduler), vec![
+            (1, Event::HBlank),
+            (5, Event::DividerTick),
+            (5, Event::LineStart),
+            (5, Event::TimerTick),
+        ]);
+    }
+
+    #[test]
+    fn cancel_removes_only_that_event() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_at(4, Event::TimerTick);
+        scheduler.schedule_at(8, Event::DividerTick);
+        scheduler.schedule_at(12, Event::TimerTick);
+        scheduler.cancel(Event::TimerTick);
+        assert_eq!(scheduler.cycles_until_next(), 8);
+        scheduler.cancel(Event::HBlank);
+        scheduler.advance(20);
+        assert_eq!(drain(&mut scheduler), vec![(8, Event::DividerTick)]);
+    }
+}
@@ -0,0 +1,337 @@
+//! Reverse debugging. While recording, a snapshot of the machine is taken every frame and the
+//! button changes are logged. Going back restores the last snapshot before the target and runs
+//! forward again, which ends in the same state, as the emulation is deterministic.
+//!
+//! The hooks, the profile and the code/data log don't see the re-execution. Breakpoints only
+//! see it, when searching for the last hit, and their hit counts aren't rewound. Going back
+//! drops the recorded future, running forward again records a new one.
+
+use std::collections::VecDeque;
+use super::breakpoints::{BreakpointHit, Hit};
+use super::cpu::{CpuSnapshot, Tools};
+use super::scheduler::Scheduler;
+use super::video::VideoMode;
+use super::{Button, ClockInformation, GBRSError, GameBoy};
+
+/// Snapshots are kept for this many seconds (frames)
+const SNAPSHOT_COUNT: usize = 600;
+
+/// The machine, without the ROM and the debugging tools
+struct Snapshot {
+    /// M-cycles since power on, see [`GameBoy::cycles`]
+    cycle: u64,
+    /// The recorded button changes, that happened before
+    inputs: usize,
+    cpu: CpuSnapshot,
+    clock_number_in_current_frame: u32,
+    old_stat_interrupt_state: bool,
+    video_mode: VideoMode,
+    scheduler: Scheduler,
+    spare_cpu_cycle: bool,
+    frames: u64,
+}
+
+#[derive(Default)]
+pub struct Timeline {
+    /// Oldest first
+    snapshots: VecDeque<Snapshot>,
+    /// Button changes and the M-cycle they happened before, oldest first
+    inputs: Vec<(u64, Button, bool)>,
+}
+
+impl GameBoy {
+    /// Record snapshots and inputs, so the emulation can go back, see [`GameBoy::step_back`]
+    pub fn start_recording(&mut self) {
+        let mut timeline = Box::<Timeline>::default();
+        timeline.snapshots.push_back(self.snapshot(0));
+        self.timeline = Some(timeline);
+    }
+
+    /// M-cycles since power on (PPU cycles in double speed mode). The position in the recording.
+    pub fn cycles(&self) -> u64 {
+        self.scheduler.now()
+    }
+
+    /// Go back to the start of the last instruction, that started before now (or of the
+    /// current one, after a watchpoint). Returns `false`, if that isn't recorded (anymore).
+    pub fn step_back(&mut self, buffer: &mut Box<[u8]>) -> Result<bool, GBRSError> {
+        let Some(mut timeline) = self.timeline.take() else { return Ok(false) };
+        let now = self.cycles();
+        let mut result = Ok(false);
+        for index in (0..timeline.snapshots.len()).rev().filter(|i| timeline.snapshots[*i].cycle < now) {
+            let mut start = None;
+            let replayed = self.replay(&timeline, index, now, &mut Tools::default(), buffer, |cycle, info| {
+                if info.instruction().is_new() {
+                    start = Some(cycle);
+                }
+            });
+            if let Err(e) = replayed {
+                result = Err(e);
+                break;
+            }
+            if let Some(start) = start {
+                result = self.go_back(&mut timeline, index, start, buffer).map(|_| {
+                    // Like after an execute breakpoint, it doesn't pause here again
+                    if self.cpu.installed_breakpoints().is_some() {
+                        let pc = self.cpu.get_pc();
+                        self.cpu.breakpoints().resume_at(pc);
+                    }
+                    true
+                });
+                break;
+            }
+        }
+        self.timeline = Some(timeline);
+        result
+    }
+
+    /// Go back to the last time a breakpoint was hit, with the state as if the emulation had
+    /// paused there. Goes back to the start of the recording and returns `None`, if there is no
+    /// such hit.
+    pub fn reverse_continue(&mut self, buffer: &mut Box<[u8]>) -> Result<Option<BreakpointHit>, GBRSError> {
+        let Some(mut timeline) = self.timeline.take() else { return Ok(None) };
+        let now = self.cycles();
+        let result = self.last_hit(&timeline, now, buffer).and_then(|found| match found {
+            Some((index, cycle, hit)) => {
+                self.go_back(&mut timeline, index, cycle, buffer)?;
+                if hit.hit() == Hit::Execute {
+                    self.cpu.breakpoints().resume_at(hit.pc());
+                }
+                Ok(Some(hit))
+            }
+            None => {
+                let start = timeline.snapshots[0].cycle;
+                self.go_back(&mut timeline, 0, start, buffer)?;
+                Ok(None)
+            }
+        });
+        self.timeline = Some(timeline);
+        result
+    }
+
+    /// Take a snapshot, if the last one is a frame ago. Before the clock does anything.
+    pub(super) fn record(&mut self) {
+        let Some(timeline) = &self.timeline else { return };
+        let cycle = self.cycles();
+        if timeline.snapshots.back().is_some_and(|last| cycle < last.cycle + Self::CLOCKS as u64) {
+            return;
+        }
+        let snapshot = self.snapshot(timeline.inputs.len());
+        let timeline = self.timeline.as_mut().unwrap();
+        timeline.snapshots.push_back(snapshot);
+        if timeline.snapshots.len() > SNAPSHOT_COUNT {
+            timeline.snapshots.pop_front();
+            // Nothing replays the inputs before the oldest snapshot anymore
+            let dropped = timeline.snapshots[0].inputs;
+            timeline.inputs.drain(..dropped);
+            for snapshot in &mut timeline.snapshots {
+                snapshot.inputs -= dropped;
+            }
+        }
+    }
+
+    /// A button changed
+    pub(super) fn record_input(&mut self, button: Button, pressed: bool) {
+        let cycle = self.cycles();
+        if let Some(timeline) = &mut self.timeline {
+            timeline.inputs.push((cycle, button, pressed));
+        }
+    }
+
+    fn snapshot(&self, inputs: usize) -> Snapshot {
+        Snapshot {
+            cycle: self.cycles(),
+            inputs,
+            cpu: self.cpu.snapshot(),
+            clock_number_in_current_frame: self.clock_number_in_current_frame,
+            old_stat_interrupt_state: self.old_stat_interrupt_state,
+            video_mode: self.video_mode,
+            scheduler: self.scheduler.clone(),
+            spare_cpu_cycle: self.spare_cpu_cycle,
+            frames: self.frames,
+        }
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu.restore(&snapshot.cpu);
+        self.clock_number_in_current_frame = snapshot.clock_number_in_current_frame;
+        self.old_stat_interrupt_state = snapshot.old_stat_interrupt_state;
+        self.video_mode = snapshot.video_mode;
+        self.scheduler = snapshot.scheduler.clone();
+        self.spare_cpu_cycle = snapshot.spare_cpu_cycle;
+        self.frames = snapshot.frames;
+        // The decoded code may be different now
+        if let Some(blocks) = &mut self.blocks {
+            blocks.clear();
+        }
+    }
+
+    /// Restore snapshot `index` and clock until `until`, with the given tools instead of the
+    /// installed ones. `clocked` gets the cycle before each clock. Returns the recorded inputs,
+    /// that were replayed up to then.
+    fn replay(
+        &mut self,
+        timeline: &Timeline,
+        index: usize,
+        until: u64,
+        tools: &mut Tools,
+        buffer: &mut Box<[u8]>,
+        mut clocked: impl FnMut(u64, &ClockInformation),
+    ) -> Result<usize, GBRSError> {
+        let snapshot = &timeline.snapshots[index];
+        self.restore(snapshot);
+        self.cpu.swap_tools(tools);
+        let mut inputs = snapshot.inputs;
+        let mut result = Ok(());
+        while self.cycles() < until {
+            while let Some((_, button, pressed)) = timeline.inputs.get(inputs).filter(|(cycle, _, _)| *cycle <= self.cycles()) {
+                self.cpu.set_button(*button, *pressed);
+                inputs += 1;
+            }
+            let cycle = self.cycles();
+            match self.clock(buffer) {
+                Ok(info) => clocked(cycle, &info),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.cpu.swap_tools(tools);
+        result.map(|_| inputs)
+    }
+
+    /// The snapshot, cycle and hit of the last breakpoint hit before `now`. Execute hits are at
+    /// the cycle before the instruction, the others at the cycle after the access.
+    fn last_hit(&mut self, timeline: &Timeline, now: u64, buffer: &mut Box<[u8]>) -> Result<Option<(usize, u64, BreakpointHit)>, GBRSError> {
+        let Some(breakpoints) = self.cpu.installed_breakpoints().map(|breakpoints| breakpoints.copy_for_replay()) else {
+            return Ok(None);
+        };
+        let mut tools = Tools::default();
+        tools.breakpoints = Some(Box::new(breakpoints));
+        let mut end = now;
+        for index in (0..timeline.snapshots.len()).rev().filter(|i| timeline.snapshots[*i].cycle < now) {
+            let mut last = None;
+            self.replay(timeline, index, end, &mut tools, buffer, |cycle, info| {
+                if let Some(hit) = info.breakpoint() {
+                    let at = if hit.hit() == Hit::Execute { cycle } else { cycle + info.cycles() as u64 };
+                    if at < now {
+                        last = Some((at, hit));
+                    }
+                }
+            })?;
+            if let Some((cycle, hit)) = last {
+                return Ok(Some((index, cycle, hit)));
+            }
+            end = timeline.snapshots[index].cycle;
+        }
+        Ok(None)
+    }
+
+    /// Replay to `cycle` and forget what was recorded after it
+    fn go_back(&mut self, timeline: &mut Timeline, index: usize, cycle: u64, buffer: &mut Box<[u8]>) -> Result<(), GBRSError> {
+        let inputs = self.replay(timeline, index, cycle, &mut Tools::default(), buffer, |_, _| {})?;
+        let keep = (index + 1..timeline.snapshots.len())
+            .find(|i| timeline.snapshots[*i].cycle >= cycle)
+            .unwrap_or(timeline.snapshots.len());
+        timeline.snapshots.truncate(keep);
+        timeline.inputs.truncate(inputs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_boy::breakpoints::{Access, Breakpoint};
+    use crate::game_boy::cpu::registers::Registers;
+    use crate::window::GbWindow;
+
+    /// Counts at $C000, and at $C001 each time $C000 wraps around
+    const COUNTER: [u8; 11] = [
+        0x21, 0x00, 0xC0, // LD HL,$C000
+        0x34,             // loop: INC [HL]
+        0x20, 0xFD,       // JR NZ,loop
+        0x2C,             // INC L
+        0x34,             // INC [HL]
+        0x2D,             // DEC L
+        0x18, 0xF8,       // JR loop
+    ];
+
+    /// What step_back and reverse_continue have to restore
+    #[derive(Debug, PartialEq)]
+    struct State {
+        cycle: u64,
+        registers: Registers,
+        counters: [u8; 2],
+    }
+
+    fn state(gb: &GameBoy) -> State {
+        State {
+            cycle: gb.cycles(),
+            registers: gb.registers(),
+            counters: [gb.memory().read_8(0xC000), gb.memory().read_8(0xC001)],
+        }
+    }
+
+    fn buffer() -> Box<[u8]> {
+        vec![0; GbWindow::buffer_size()].into_boxed_slice()
+    }
+
+    #[test]
+    fn step_back_restores_the_start_of_the_previous_instructions() {
+        let mut gb = GameBoy::with_program(&COUNTER);
+        let mut buffer = buffer();
+        gb.start_recording();
+        let mut starts = Vec::new();
+        let mut clock = |gb: &mut GameBoy| {
+            let before = state(gb);
+            if gb.clock(&mut buffer).unwrap().instruction().is_new() {
+                starts.push(before);
+            }
+        };
+        while gb.frames < 2 {
+            clock(&mut gb);
+        }
+        for _ in 0..200 {
+            clock(&mut gb);
+        }
+
+        // Going back crosses the last snapshot
+        let snapshot = gb.timeline.as_ref().unwrap().snapshots.back().unwrap().cycle;
+        assert!(snapshot > 0);
+
+        let first = starts.iter().rposition(|start| start.cycle < snapshot).unwrap() - 1;
+        for expected in starts[first..].iter().rev() {
+            assert!(gb.step_back(&mut buffer).unwrap());
+            assert_eq!(state(&gb), *expected);
+        }
+    }
+
+    #[test]
+    fn reverse_continue_goes_back_to_earlier_hits() {
+        let mut gb = GameBoy::with_program(&COUNTER);
+        let mut buffer = buffer();
+        gb.start_recording();
+        let id = gb.breakpoints().add(Breakpoint::watch(0xC001..=0xC001, Access::Write));
+        let mut hits = Vec::new();
+        while hits.len() < 3 {
+            if let Some(hit) = gb.frame(&mut buffer).unwrap() {
+                assert_eq!(hit.id(), id);
+                hits.push(state(&gb));
+            }
+        }
+        // Somewhere between two hits
+        for _ in 0..100 {
+            assert!(gb.clock(&mut buffer).unwrap().breakpoint().is_none());
+        }
+
+        for expected in hits.iter().rev() {
+            let hit = gb.reverse_continue(&mut buffer).unwrap().unwrap();
+            assert_eq!(hit.id(), id);
+            assert_eq!(state(&gb), *expected);
+        }
+        assert!(gb.reverse_continue(&mut buffer).unwrap().is_none());
+        assert_eq!(gb.cycles(), 0);
+    }
+}
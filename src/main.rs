@@ -2,15 +2,26 @@
 
 mod game_boy;
 mod window;
+mod disasm;
+mod bench;
+mod debugger;
+mod dap;
+mod symbols;
+mod profile;
 
 use minifb::{Key, KeyRepeat};
+use std::path::Path;
 use std::str::FromStr;
 use std::time::Instant;
 use chrono::{SecondsFormat, Utc};
 use crate::window::GbWindow;
-use clap::{crate_version, App, Arg};
+use clap::{crate_version, App, AppSettings, Arg, SubCommand};
 use rand::Rng;
-use crate::game_boy::cpu::debug::{ins_name, pretty_instruction};
+use crate::game_boy::cpu::debug::pretty_instruction;
+use crate::game_boy::cpu::history::HISTORY_LENGTH;
+use crate::game_boy::Button;
+use crate::game_boy::expression::Expression;
+use crate::symbols::Symbols;
 
 // Links:
 // Endianness Guide:
@@ -39,15 +50,54 @@ use crate::game_boy::cpu::debug::{ins_name, pretty_instruction};
 // Der Conditional Jump aus Schritt 15988 sollte eigentlicht NICHT springen
 // -> Eine Flag ist falsch
 
+const KEY_MAP: [(Key, Button); 8] = [
+    (Key::Right, Button::Right),
+    (Key::Left, Button::Left),
+    (Key::Up, Button::Up),
+    (Key::Down, Button::Down),
+    (Key::X, Button::A),
+    (Key::Z, Button::B),
+    (Key::Backspace, Button::Select),
+    (Key::Enter, Button::Start),
+];
+
+/// Dumps the last instructions to stderr
+const HISTORY_KEY: Key = Key::F9;
+
+fn update_buttons(gb: &mut game_boy::GameBoy, window: &GbWindow) {
+    for (key, button) in KEY_MAP {
+        gb.set_button(button, window.win().is_key_down(key));
+    }
+}
+
 struct CliOpts {
     rom_path: String,
     magnification: usize,
+    headless: bool,
+    frames: Option<u64>,
+    /// Run from the block cache instead of M-cycle by M-cycle
+    blocks: bool,
+    /// Read debugger commands from stdin
+    debug: bool,
+    /// Only trace the instructions, for which this expression holds
+    trace_filter: Option<String>,
+    /// Write the cycles of the game as folded stacks to this file
+    profile: Option<String>,
+    /// Merge the code/data log of this session into this file
+    cdl: Option<String>,
+    /// `gbrs disasm`: Write the disassembly to this file (or stdout)
+    disasm: Option<Option<String>>,
+    /// `gbrs bench`: Time memory accesses instead of running the ROM
+    bench: bool,
+    /// `gbrs dap`: Serve the Debug Adapter Protocol on this port
+    dap: Option<u16>,
 }
 
 impl CliOpts {
     fn load() -> CliOpts {
         let matches = App::new("GB-rs")
             .version(crate_version!())
+            .setting(AppSettings::SubcommandsNegateReqs)
             .arg(Arg::with_name("rom-path").required(true).index(1))
             .arg(
                 Arg::with_name("magnification")
@@ -55,15 +105,154 @@ impl CliOpts {
                     .long("magnification")
                     .value_name("VAL"),
             )
+            .arg(
+                Arg::with_name("headless")
+                    .long("headless")
+                    .help("Run without a window"),
+            )
+            .arg(
+                Arg::with_name("frames")
+                    .short("f")
+                    .long("frames")
+                    .value_name("COUNT")
+                    .help("Stop after this many frames (headless only)"),
+            )
+            .arg(
+                Arg::with_name("blocks")
+                    .long("blocks")
+                    .help("Run cached, pre-decoded blocks of instructions (faster, less exact timing)"),
+            )
+            .arg(
+                Arg::with_name("debug")
+                    .short("d")
+                    .long("debug")
+                    .help("Start paused and read debugger commands from stdin (ignores --blocks)"),
+            )
+            .arg(
+                Arg::with_name("trace-filter")
+                    .long("trace-filter")
+                    .value_name("EXPR")
+                    .help("Only trace instructions, for which the expression holds (e.g. \"pc >= $4000 && bank == 2\")"),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .value_name("FILE")
+                    .help("Profile the game, write folded stacks (for flamegraphs) to FILE and report the hottest functions"),
+            )
+            .arg(
+                Arg::with_name("cdl")
+                    .long("cdl")
+                    .value_name("FILE")
+                    .help("Log which ROM bytes are code, data or tiles, merged into FILE (ROM.cdl is used by disasm)"),
+            )
+            .subcommand(
+                SubCommand::with_name("disasm")
+                    .about("Disassemble the ROM into RGBDS source")
+                    .arg(Arg::with_name("rom-path").required(true).index(1))
+                    .arg(
+                        Arg::with_name("output")
+                            .short("o")
+                            .long("output")
+                            .value_name("FILE")
+                            .help("Write to this file instead of stdout"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("bench")
+                    .about("Measure how long memory accesses take")
+                    .arg(Arg::with_name("rom-path").required(true).index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("dap")
+                    .about("Wait for an editor to connect and debug over the Debug Adapter Protocol")
+                    .arg(
+                        Arg::with_name("port")
+                            .short("p")
+                            .long("port")
+                            .value_name("PORT")
+                            .help("The port on 127.0.0.1 to listen on (4711)"),
+                    )
+                    .arg(
+                        Arg::with_name("magnification")
+                            .short("m")
+                            .long("magnification")
+                            .value_name("VAL"),
+                    ),
+            )
             .get_matches();
+        if let Some(disasm) = matches.subcommand_matches("disasm") {
+            return CliOpts {
+                rom_path: disasm.value_of("rom-path").unwrap().to_owned(),
+                magnification: 1,
+                headless: true,
+                frames: None,
+                blocks: false,
+                debug: false,
+                trace_filter: None,
+                profile: None,
+                cdl: None,
+                disasm: Some(disasm.value_of("output").map(str::to_owned)),
+                bench: false,
+                dap: None,
+            };
+        }
+        if let Some(bench) = matches.subcommand_matches("bench") {
+            return CliOpts {
+                rom_path: bench.value_of("rom-path").unwrap().to_owned(),
+                magnification: 1,
+                headless: true,
+                frames: None,
+                blocks: false,
+                debug: false,
+                trace_filter: None,
+                profile: None,
+                cdl: None,
+                disasm: None,
+                bench: true,
+                dap: None,
+            };
+        }
+        if let Some(dap) = matches.subcommand_matches("dap") {
+            return CliOpts {
+                rom_path: String::new(),
+                magnification: dap
+                    .value_of("magnification")
+                    .map(|o| usize::from_str(o).expect("Could not parse number"))
+                    .unwrap_or(2),
+                headless: false,
+                frames: None,
+                blocks: false,
+                debug: false,
+                trace_filter: None,
+                profile: None,
+                cdl: None,
+                disasm: None,
+                bench: false,
+                dap: Some(dap.value_of("port").map(|o| u16::from_str(o).expect("Could not parse port")).unwrap_or(4711)),
+            };
+        }
         let rom_path = matches.value_of("rom-path").unwrap().to_owned();
         let magnification = matches
             .value_of("magnification")
             .map(|o| usize::from_str(o).expect("Could not parse number"))
             .unwrap_or(2);
+        let frames = matches
+            .value_of("frames")
+            .map(|o| u64::from_str(o).expect("Could not parse number"));
         CliOpts {
             rom_path,
             magnification,
+            headless: matches.is_present("headless"),
+            frames,
+            blocks: matches.is_present("blocks"),
+            debug: matches.is_present("debug"),
+            trace_filter: matches.value_of("trace-filter").map(str::to_owned),
+            profile: matches.value_of("profile").map(str::to_owned),
+            cdl: matches.value_of("cdl").map(str::to_owned),
+            disasm: None,
+            bench: false,
+            dap: None,
         }
     }
 }
@@ -74,10 +263,68 @@ fn main() {
     //     game_boy::memory::MemRegion::get_region(0xC3C8)
     // );
     let opts = CliOpts::load();
+    if let Some(output) = &opts.disasm {
+        if let Err(e) = disasm::run(&opts.rom_path, output.as_deref()) {
+            eprintln!("Could not disassemble {}: {}", opts.rom_path, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if opts.bench {
+        if let Err(e) = bench::run(&opts.rom_path) {
+            eprintln!("Could not load {}: {:?}", opts.rom_path, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(port) = opts.dap {
+        if let Err(e) = dap::run(port, opts.magnification) {
+            eprintln!("Debug adapter failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let symbols = Symbols::for_rom(Path::new(&opts.rom_path)).unwrap_or_else(|e| {
+        eprintln!("Could not load the symbols of {}: {}", opts.rom_path, e);
+        Symbols::default()
+    });
     let mut gb = game_boy::GameBoy::load(&opts.rom_path.into()).unwrap();
+    gb.use_block_cache(opts.blocks);
+    if opts.profile.is_some() {
+        gb.start_profile();
+    }
+    if let Some(path) = &opts.cdl {
+        start_code_data_log(&mut gb, path);
+    }
     //
     // gb.memory().rom().print_meta();
 
+    if opts.debug {
+        let window = if opts.headless { None } else { Some(GbWindow::new(opts.magnification)) };
+        let (mut gb, symbols) = debugger::run(gb, window, symbols);
+        save_profile(&mut gb, opts.profile.as_deref(), &symbols);
+        save_code_data_log(&mut gb, opts.cdl.as_deref());
+        return;
+    }
+
+    if opts.headless {
+        let crashed = !run_headless(&mut gb, opts.frames, &symbols);
+        save_profile(&mut gb, opts.profile.as_deref(), &symbols);
+        save_code_data_log(&mut gb, opts.cdl.as_deref());
+        if crashed {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let trace_filter = opts.trace_filter.as_deref().map(|filter| {
+        let labels = |name: &str| symbols.address_of(name).map(|(_, address)| address as i64);
+        Expression::parse_with(filter, &labels).unwrap_or_else(|e| {
+            eprintln!("Invalid trace filter: {}", e);
+            std::process::exit(1);
+        })
+    });
+
     let mut window = GbWindow::new(opts.magnification);
 
     // for i in window.buffer_mut().iter_mut() {
@@ -95,9 +342,27 @@ fn main() {
 
         let mut has_vblanked = false;
 
+        update_buttons(&mut gb, &window);
+        if window.win().is_key_pressed(HISTORY_KEY, KeyRepeat::No) {
+            report_history(&gb, &symbols);
+        }
+
         while has_clocks_left_in_frame {
-            let info = gb.clock(window.buffer_mut());
+            let info = match gb.clock(window.buffer_mut()) {
+                Ok(info) => info,
+                Err(e) => {
+                    eprintln!("Emulation fault: {}", e);
+                    report_crash(&gb, &symbols);
+                    save_profile(&mut gb, opts.profile.as_deref(), &symbols);
+                    save_code_data_log(&mut gb, opts.cdl.as_deref());
+                    std::process::exit(1);
+                }
+            };
             has_clocks_left_in_frame = !info.frame_done();
+            if let Some(lock_up) = info.lock_up() {
+                eprintln!("CPU locked up: {} ({})", lock_up, gb.cpu().debug_stack_info());
+                report_crash(&gb, &symbols);
+            }
             if info.instruction().is_new() {
                 // println!(
                 //     "[{:#06X}] [{:02X}] {{{}}} -> {}",
@@ -131,15 +396,19 @@ fn main() {
                     //     ),
                     //     ins_name(info.instruction().instruction(), info.instruction().data()[0])
                     // );
-                    println!(
-                        "{:>10} {:20} {:04X} {:02X} {} LY{:02x}",
-                        counter,
-                        pretty_instruction(&info.instruction(), gb.cpu()),
-                        info.instruction().stack_info().pc(),
-                        info.instruction().instruction(),
-                        info.instruction().stack_info(),
-                        gb.cpu().memory().read_ly()
-                    );
+                    if trace_filter.as_ref().is_none_or(|filter| gb.evaluate(filter) != 0) {
+                        let pc = info.instruction().stack_info().pc();
+                        println!(
+                            "{:>10} {:20} {:04X} {:02X} {} LY{:02x}{}",
+                            counter,
+                            pretty_instruction(&info.instruction(), &debugger::names(&gb, &symbols)),
+                            pc,
+                            info.instruction().instruction(),
+                            info.instruction().stack_info(),
+                            gb.cpu().memory().read_ly(),
+                            symbols.label_for(gb.cpu().location_of(pc)).map(|label| format!(" {}", label)).unwrap_or_default()
+                        );
+                    }
                 }
                 // if !gb.memory().boot_rom_enabled() && info.instruction().stack_info().pc() == 0x40 {
                 //     has_vblanked = true;
@@ -188,6 +457,89 @@ fn main() {
         //     eprintln!("Frame {}", cou)
         // }
     }
+    save_profile(&mut gb, opts.profile.as_deref(), &symbols);
+    save_code_data_log(&mut gb, opts.cdl.as_deref());
+}
+
+/// Run without a window, until the given number of frames is done.
+/// Stops early with `false`, if the CPU locks up or faults.
+fn run_headless(gb: &mut game_boy::GameBoy, frames: Option<u64>, symbols: &Symbols) -> bool {
+    let mut buffer = vec![0; GbWindow::buffer_size()].into_boxed_slice();
+    let mut frame = 0;
+    while frames.is_none_or(|frames| frame < frames) {
+        let info = match gb.clock(&mut buffer) {
+            Ok(info) => info,
+            Err(e) => {
+                eprintln!("Emulation fault in frame {}: {}", frame, e);
+                report_crash(gb, symbols);
+                return false;
+            }
+        };
+        if let Some(lock_up) = info.lock_up() {
+            eprintln!(
+                "CPU locked up in frame {}: {} ({})",
+                frame,
+                lock_up,
+                gb.cpu().debug_stack_info()
+            );
+            report_crash(gb, symbols);
+            return false;
+        }
+        if info.frame_done() {
+            frame += 1;
+        }
+    }
+    true
+}
+
+/// Write the profile, if the game was profiled
+fn save_profile(gb: &mut game_boy::GameBoy, path: Option<&str>, symbols: &Symbols) {
+    if let (Some(path), Some(profile)) = (path, gb.take_profile()) {
+        if let Err(e) = profile::save(&profile, symbols, path) {
+            eprintln!("Could not save the profile: {}", e);
+        }
+    }
+}
+
+/// Log code and data, on top of the log in the file, if there is one
+fn start_code_data_log(gb: &mut game_boy::GameBoy, path: &str) {
+    let flags = match std::fs::read(path) {
+        Ok(flags) => Some(flags),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Could not read the code/data log {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    if !gb.start_code_data_log(flags.as_deref()) {
+        eprintln!("The code/data log {} is of another ROM, starting over", path);
+    }
+}
+
+/// Write the code/data log, if there is one
+fn save_code_data_log(gb: &mut game_boy::GameBoy, path: Option<&str>) {
+    if let (Some(path), Some(log)) = (path, gb.take_code_data_log()) {
+        if let Err(e) = std::fs::write(path, log.flags()) {
+            eprintln!("Could not save the code/data log {}: {}", path, e);
+        }
+    }
+}
+
+/// The last instructions the CPU executed
+fn report_history(gb: &game_boy::GameBoy, symbols: &Symbols) {
+    eprintln!("Last instructions:");
+    for line in debugger::history(gb, symbols, HISTORY_LENGTH) {
+        eprintln!("  {}", line);
+    }
+}
+
+/// The instructions and calls, that led to a crash
+fn report_crash(gb: &game_boy::GameBoy, symbols: &Symbols) {
+    report_history(gb, symbols);
+    eprintln!("Backtrace:");
+    for line in debugger::backtrace(gb, symbols) {
+        eprintln!("  {}", line);
+    }
 }
 
 // GENERAL TODO
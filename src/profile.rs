@@ -0,0 +1,125 @@
+//! `--profile FILE`: where the game (not the emulator) spends its time. The cycles are written
+//! as folded stacks (`Main;UpdateActors;MoveActor 1234` per line), that flamegraph tools like
+//! `flamegraph.pl` or `inferno-flamegraph` turn into a graph, and the hottest functions and
+//! instructions are reported on stdout.
+//!
+//! Frames are named by the ROM's sym file, frames without a label by their location. The
+//! outermost frame is the function of the code, that isn't inside any tracked call.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::game_boy::cpu::callstack::Entry;
+use crate::game_boy::cpu::disassembler::Location;
+use crate::game_boy::cpu::profiler::{Path, Profile};
+use crate::symbols::Symbols;
+
+/// Lines per table of the report
+const REPORT_LENGTH: usize = 20;
+
+/// Code outside of any call, that has no label
+const ROOT: &str = "(root)";
+
+/// The name of a frame of a path, see [`Path`]
+fn frame_name(symbols: &Symbols, location: Location, entry: Option<Entry>) -> String {
+    let Some(entry) = entry else {
+        // Where the outermost frame was entered from
+        return symbols.function_of(location).unwrap_or(ROOT).to_owned();
+    };
+    match (symbols.name_of(location), entry) {
+        (Some(name), _) => name.to_owned(),
+        (None, Entry::Interrupt(interrupt)) => format!("{:?}Interrupt", interrupt),
+        (None, _) => location.to_string(),
+    }
+}
+
+/// The frames of a sample, outermost first
+fn stack(symbols: &Symbols, path: &Path, pc: Location) -> Vec<String> {
+    if path.is_empty() {
+        return vec![symbols.function_of(pc).unwrap_or(ROOT).to_owned()];
+    }
+    path.iter().map(|(location, entry)| frame_name(symbols, *location, *entry)).collect()
+}
+
+/// The function the PC is in: its label or the innermost frame
+fn function(symbols: &Symbols, path: &Path, pc: Location) -> String {
+    match symbols.function_of(pc) {
+        Some(name) => name.to_owned(),
+        None => stack(symbols, path, pc).pop().unwrap_or_else(|| ROOT.to_owned()),
+    }
+}
+
+pub fn write_folded(profile: &Profile, symbols: &Symbols, out: &mut dyn Write) -> io::Result<()> {
+    let mut folded: BTreeMap<String, u64> = BTreeMap::new();
+    for (path, pc, cycles) in profile.samples() {
+        *folded.entry(stack(symbols, path, pc).join(";")).or_default() += cycles;
+    }
+    for (stack, cycles) in folded {
+        writeln!(out, "{} {}", stack, cycles)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct FunctionCycles {
+    bank: Option<u16>,
+    own: u64,
+    /// Including the functions it called
+    total: u64,
+}
+
+/// The hottest functions (by their own cycles) and instructions
+pub fn write_report(profile: &Profile, symbols: &Symbols, out: &mut dyn Write) -> io::Result<()> {
+    let total = profile.total().max(1);
+    let percent = |cycles: u64| 100.0 * cycles as f64 / total as f64;
+    writeln!(out, "{} M-cycles profiled", profile.total())?;
+
+    let mut functions: HashMap<String, FunctionCycles> = HashMap::new();
+    for (path, pc, cycles) in profile.samples() {
+        let own = function(symbols, path, pc);
+        let entry = functions.entry(own.clone()).or_default();
+        entry.own += cycles;
+        entry.bank = entry.bank.or(pc.bank());
+        // Recursion only counts once
+        let mut callers: HashSet<String> = stack(symbols, path, pc).into_iter().collect();
+        callers.insert(own);
+        for name in callers {
+            functions.entry(name).or_default().total += cycles;
+        }
+    }
+    let mut functions: Vec<(String, FunctionCycles)> = functions.into_iter().collect();
+    functions.sort_by(|(a_name, a), (b_name, b)| b.own.cmp(&a.own).then_with(|| a_name.cmp(b_name)));
+    writeln!(out)?;
+    writeln!(out, "{:>7} {:>12} {:>7} {:>12}  bank  function", "own", "", "total", "")?;
+    for (name, cycles) in functions.iter().take(REPORT_LENGTH).filter(|(_, cycles)| cycles.own > 0) {
+        let bank = cycles.bank.map(|bank| format!("{:02X}", bank)).unwrap_or_else(|| "--".to_owned());
+        writeln!(
+            out,
+            "{:>6.2}% {:>12} {:>6.2}% {:>12}  {:>4}  {}",
+            percent(cycles.own),
+            cycles.own,
+            percent(cycles.total),
+            cycles.total,
+            bank,
+            name
+        )?;
+    }
+
+    let mut instructions: Vec<(Location, u64)> = profile.by_pc().into_iter().collect();
+    instructions.sort_by(|(a_pc, a), (b_pc, b)| b.cmp(a).then_with(|| a_pc.cmp(b_pc)));
+    writeln!(out)?;
+    writeln!(out, "{:>7} {:>12}  instruction", "own", "")?;
+    for (pc, cycles) in instructions.iter().take(REPORT_LENGTH) {
+        let label = symbols.label_for(*pc).map(|label| format!("  {}", label)).unwrap_or_default();
+        writeln!(out, "{:>6.2}% {:>12}  {}{}", percent(*cycles), cycles, pc, label)?;
+    }
+    Ok(())
+}
+
+/// Write the folded stacks to `path` and the report to stdout
+pub fn save(profile: &Profile, symbols: &Symbols, path: &str) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_folded(profile, symbols, &mut out)?;
+    out.flush()?;
+    write_report(profile, symbols, &mut io::stdout().lock())
+}
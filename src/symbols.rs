@@ -0,0 +1,297 @@
+//! Symbol files, to show names instead of addresses and to map source lines to addresses.
+//!
+//! A `.sym` file, as written by `rgblink -n` (and no$gmb), has a `BANK:ADDR Label` pair per
+//! line, `;` starts a comment. RGBDS has no line information of its own, a line map uses the
+//! same format with `path:line` instead of the label.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::game_boy::cpu::disassembler::Location;
+
+/// `BANK:ADDR`, both hex
+fn parse_location(text: &str) -> Option<(u16, u16)> {
+    let (bank, address) = text.split_once(':')?;
+    Some((u16::from_str_radix(bank, 16).ok()?, u16::from_str_radix(address, 16).ok()?))
+}
+
+/// The `(location, rest)` pairs of a sym file, comments and broken lines are skipped
+fn entries(text: &str) -> impl Iterator<Item = ((u16, u16), &str)> {
+    text.lines().filter_map(|line| {
+        let line = line.split(';').next()?.trim();
+        let (location, rest) = line.split_once(char::is_whitespace)?;
+        Some((parse_location(location)?, rest.trim()))
+    })
+}
+
+/// Labels by `(bank, address)`. Outside of ROM the bank is ignored, see [`Symbols::name_at`].
+#[derive(Default)]
+pub struct Symbols {
+    by_location: BTreeMap<(u16, u16), String>,
+    by_name: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    pub fn load(path: &Path) -> io::Result<Symbols> {
+        Ok(Symbols::parse(&fs::read_to_string(path)?))
+    }
+
+    /// The `.sym` file next to the ROM, no symbols if there is none
+    pub fn for_rom(rom: &Path) -> io::Result<Symbols> {
+        let path = rom.with_extension("sym");
+        if path.exists() { Symbols::load(&path) } else { Ok(Symbols::default()) }
+    }
+
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        for (location, name) in entries(text) {
+            symbols.by_name.insert(name.to_owned(), location);
+            // Several labels at one address: prefer the first global one
+            let replace = match symbols.by_location.get(&location) {
+                Some(existing) => existing.contains('.') && !name.contains('.'),
+                None => true,
+            };
+            if replace {
+                symbols.by_location.insert(location, name.to_owned());
+            }
+        }
+        symbols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// All labels with their `(bank, address)`, one per address
+    pub fn labels(&self) -> impl Iterator<Item = ((u16, u16), &str)> {
+        self.by_location.iter().map(|(location, name)| (*location, name.as_str()))
+    }
+
+    /// `(bank, address)` of the label
+    pub fn address_of(&self, name: &str) -> Option<(u16, u16)> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The label at exactly this address
+    pub fn name_at(&self, bank: u16, address: u16) -> Option<&str> {
+        self.nearest(bank, address).filter(|(_, offset)| *offset == 0).map(|(name, _)| name)
+    }
+
+    /// The last label at or before the address, and how far the address is past it. Labels
+    /// don't reach across the end of the memory region (ROM0, ROMX, VRAM, ...).
+    pub fn nearest(&self, bank: u16, address: u16) -> Option<(&str, u16)> {
+        self.nearest_matching(bank, address, |_| true)
+    }
+
+    /// The last global label at or before the address, the function it is in
+    pub fn function_at(&self, bank: u16, address: u16) -> Option<&str> {
+        self.nearest_matching(bank, address, |name| !name.contains('.')).map(|(name, _)| name)
+    }
+
+    fn nearest_matching(&self, bank: u16, address: u16, matches: impl Fn(&str) -> bool) -> Option<(&str, u16)> {
+        let label = if address < 0x8000 {
+            let region_start = address & 0xC000;
+            self.by_location
+                .range((bank, region_start)..=(bank, address))
+                .rev()
+                .find(|(_, name)| matches(name))
+        } else {
+            // RAM banks aren't tracked, any bank will do
+            let region_start = address & 0xE000;
+            self.by_location
+                .iter()
+                .filter(|((_, a), name)| (region_start..=address).contains(a) && matches(name))
+                .max_by_key(|((_, a), _)| *a)
+        };
+        label.map(|((_, label_address), name)| (name.as_str(), address - label_address))
+    }
+
+    /// `Label+$12`, or just `$ADDR` without a label in front of it
+    pub fn describe(&self, bank: u16, address: u16) -> String {
+        self.label_near(bank, address).unwrap_or_else(|| format!("${:04X}", address))
+    }
+
+    /// `Label+$12`, if there is a label in front of the address
+    pub fn label_near(&self, bank: u16, address: u16) -> Option<String> {
+        self.nearest(bank, address).map(|(name, offset)| match offset {
+            0 => name.to_owned(),
+            offset => format!("{}+${:X}", name, offset),
+        })
+    }
+
+    /// The label exactly at a location of the disassembler or the CPU, see [`banked`]
+    pub fn name_of(&self, location: Location) -> Option<&str> {
+        banked(location).and_then(|(bank, address)| self.name_at(bank, address))
+    }
+
+    /// The function a location of the disassembler or the CPU is in, see [`banked`] and
+    /// [`Symbols::function_at`]
+    pub fn function_of(&self, location: Location) -> Option<&str> {
+        banked(location).and_then(|(bank, address)| self.function_at(bank, address))
+    }
+
+    /// `Label+$12` for a location of the disassembler or the CPU, see [`banked`]
+    pub fn label_for(&self, location: Location) -> Option<String> {
+        banked(location).and_then(|(bank, address)| self.label_near(bank, address))
+    }
+}
+
+/// The `(bank, address)` of a location. ROM addresses without a bank (the boot ROM, or an
+/// unknown switchable bank) have no labels.
+fn banked(location: Location) -> Option<(u16, u16)> {
+    match location.bank() {
+        Some(bank) => Some((bank, location.address())),
+        None if location.address() >= 0x8000 => Some((0, location.address())),
+        None => None,
+    }
+}
+
+/// Source lines by `(bank, address)` and the other way around
+#[derive(Default)]
+pub struct LineMap {
+    by_location: BTreeMap<(u16, u16), (PathBuf, usize)>,
+}
+
+impl LineMap {
+    /// Relative paths are relative to the line map
+    pub fn load(path: &Path) -> io::Result<LineMap> {
+        let text = fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut lines = LineMap::default();
+        for (location, source) in entries(&text) {
+            let Some((file, line)) = source.rsplit_once(':') else { continue };
+            let Ok(line) = line.parse() else { continue };
+            lines.by_location.insert(location, (base.join(file), line));
+        }
+        Ok(lines)
+    }
+
+    /// The source line of the instruction at the address
+    pub fn line_of(&self, bank: u16, address: u16) -> Option<(&Path, usize)> {
+        let bank = if address < 0x8000 { bank } else { 0 };
+        self.by_location.get(&(bank, address)).map(|(file, line)| (file.as_path(), *line))
+    }
+
+    /// The `(bank, address)` of the code of the line, a macro can have several
+    pub fn addresses_of(&self, file: &Path, line: usize) -> Vec<(u16, u16)> {
+        self.by_location
+            .iter()
+            .filter(|(_, (f, l))| *l == line && same_file(f, file))
+            .map(|(location, _)| *location)
+            .collect()
+    }
+}
+
+/// Paths from different tools differ in how much of the path they have
+fn same_file(a: &Path, b: &Path) -> bool {
+    a.ends_with(b) || b.ends_with(a)
+}
+
+/// The full name of the label defined on the (1-based) line of RGBDS source. A local label
+/// (`.loop`) gets the name of the global label before it (`Main.loop`), like in the sym file.
+pub fn label_on_line(source: &str, line: usize) -> Option<String> {
+    let mut global = None;
+    for (number, text) in source.lines().enumerate() {
+        let label = label_of(text);
+        match label {
+            Some(label) if !label.starts_with('.') => global = Some(label),
+            _ => {}
+        }
+        if number + 1 == line {
+            let label = label?;
+            return match label.strip_prefix('.') {
+                Some(_) => Some(format!("{}{}", global?.split('.').next()?, label)),
+                None => Some(label.to_owned()),
+            };
+        }
+    }
+    None
+}
+
+/// The label a line of source starts with (`Main:`, `Main::`, `.loop:`, `.loop`)
+fn label_of(line: &str) -> Option<&str> {
+    let end = line.find(|c: char| !(c.is_ascii_alphanumeric() || "_.@#$".contains(c))).unwrap_or(line.len());
+    let label = &line[..end];
+    let rest = &line[end..];
+    let is_label = !label.is_empty()
+        && !label.starts_with(|c: char| c.is_ascii_digit())
+        && (rest.starts_with(':') || (label.starts_with('.') && label.len() > 1));
+    if is_label { Some(label) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGBDS: &str = "\
+; File generated by rgblink
+00:0000 RST_00
+00:0150 Main
+00:0150 Main.start
+00:0158 Main.loop
+01:4000 LoadTiles
+01:4010 LoadTiles.copy
+02:4000 PlaySound
+00:c000 wBuffer
+00:ff80 hDMA
+";
+
+    const NO_GMB: &str = "\
+;no$gmb symbolic information file
+;generated by rgblink
+
+[labels]
+0000:0150\tMain ; the entry
+0001:4000\tLoadTiles
+00:C000 wBuffer
+broken line
+";
+
+    #[test]
+    fn rgbds_sym_files_are_parsed() {
+        let symbols = Symbols::parse(RGBDS);
+        assert_eq!(symbols.address_of("Main"), Some((0, 0x0150)));
+        assert_eq!(symbols.address_of("Main.loop"), Some((0, 0x0158)));
+        assert_eq!(symbols.address_of("PlaySound"), Some((2, 0x4000)));
+        assert_eq!(symbols.address_of("wBuffer"), Some((0, 0xC000)));
+        // The global label wins over the local one at the same address
+        assert_eq!(symbols.name_at(0, 0x0150), Some("Main"));
+        assert_eq!(symbols.labels().count(), 8);
+    }
+
+    #[test]
+    fn no_gmb_sym_files_are_parsed() {
+        let symbols = Symbols::parse(NO_GMB);
+        let labels: Vec<_> = symbols.labels().collect();
+        assert_eq!(labels, vec![((0, 0x0150), "Main"), ((0, 0xC000), "wBuffer"), ((1, 0x4000), "LoadTiles")]);
+        assert!(Symbols::parse("[labels]\n; nothing\n").is_empty());
+    }
+
+    #[test]
+    fn addresses_are_described_by_the_label_before_them() {
+        let symbols = Symbols::parse(RGBDS);
+        assert_eq!(symbols.describe(0, 0x0150), "Main");
+        assert_eq!(symbols.describe(0, 0x015A), "Main.loop+$2");
+        assert_eq!(symbols.function_at(0, 0x015A), Some("Main"));
+        assert_eq!(symbols.describe(1, 0x4012), "LoadTiles.copy+$2");
+        assert_eq!(symbols.describe(2, 0x4012), "PlaySound+$12");
+        // ROMX labels don't reach back into ROM0, and not into another bank
+        assert_eq!(symbols.describe(3, 0x4000), "$4000");
+        assert_eq!(symbols.describe(0, 0x3FFF), "Main.loop+$3EA7");
+        // The bank of RAM doesn't matter
+        assert_eq!(symbols.describe(1, 0xC004), "wBuffer+$4");
+        assert_eq!(symbols.describe(0, 0xFF81), "hDMA+$1");
+        assert_eq!(symbols.describe(0, 0xE000), "$E000");
+    }
+
+    #[test]
+    fn locations_without_a_known_bank_have_no_labels() {
+        let symbols = Symbols::parse(RGBDS);
+        assert_eq!(symbols.name_of(Location::new(Some(1), 0x4000)), Some("LoadTiles"));
+        assert_eq!(symbols.name_of(Location::new(None, 0x4000)), None);
+        assert_eq!(symbols.name_of(Location::new(None, 0xC000)), Some("wBuffer"));
+        assert_eq!(symbols.function_of(Location::new(Some(1), 0x4011)), Some("LoadTiles"));
+        assert_eq!(symbols.label_for(Location::new(Some(0), 0x0151)), Some("Main+$1".to_owned()));
+    }
+}